@@ -61,6 +61,7 @@ export interface EscrowAccount {
   bump: number;
   qualityScore: number | null;
   refundPercentage: number | null;
+  agentIdentity: PublicKey | null; // Agent PDA the escrow is bound to
 }
 
 export interface EntityReputationAccount {
@@ -131,7 +132,7 @@ export class X402Program {
     timeLock: number; // Time lock in seconds
     transactionId: string;
   }): Promise<{ signature: string; escrowPDA: PublicKey }> {
-    const [escrowPDA] = this.pda.deriveEscrowPDA(params.transactionId);
+    const [escrowPDA] = this.pda.deriveEscrowPDA(this.wallet.publicKey, params.transactionId);
 
     const tx = await this.program.methods
       .initializeEscrow(BigInt(params.amount), BigInt(params.timeLock), params.transactionId)
@@ -156,8 +157,13 @@ export class X402Program {
    * @returns Transaction signature
    */
   async releaseFunds(transactionId: string): Promise<string> {
-    const [escrowPDA] = this.pda.deriveEscrowPDA(transactionId);
+    const [escrowPDA] = this.pda.deriveEscrowPDA(this.wallet.publicKey, transactionId);
     const escrow = await this.getEscrowAccount(escrowPDA);
+    // Reputations are updated only for parties that have opened one
+    const [agentReputationPDA] = this.pda.deriveReputationPDA(this.wallet.publicKey);
+    const [apiReputationPDA] = this.pda.deriveReputationPDA(escrow.api);
+    const agentHasReputation = await this.reputationExists(this.wallet.publicKey);
+    const apiHasReputation = await this.reputationExists(escrow.api);
 
     const tx = await this.program.methods
       .releaseFunds()
@@ -166,6 +172,9 @@ export class X402Program {
         agent: this.wallet.publicKey,
         api: escrow.api,
         systemProgram: SystemProgram.programId,
+        agentReputation: agentHasReputation ? agentReputationPDA : null,
+        apiReputation: apiHasReputation ? apiReputationPDA : null,
+        agentIdentity: escrow.agentIdentity,
       })
       .rpc();
 
@@ -179,7 +188,7 @@ export class X402Program {
    * @returns Transaction signature
    */
   async markDisputed(transactionId: string): Promise<string> {
-    const [escrowPDA] = this.pda.deriveEscrowPDA(transactionId);
+    const [escrowPDA] = this.pda.deriveEscrowPDA(this.wallet.publicKey, transactionId);
     const [reputationPDA] = this.pda.deriveReputationPDA(this.wallet.publicKey);
 
     const tx = await this.program.methods
@@ -204,11 +213,18 @@ export class X402Program {
     transactionId: string;
     qualityScore: number;
     refundPercentage: number;
-    signature: Buffer; // Ed25519 signature (64 bytes)
+    expiresAt: number; // Unix timestamp the signed attestation expires at
+    signature: Buffer; // Ed25519 signature over the oracle attestation (64 bytes)
     verifier: PublicKey;
+    agent?: PublicKey; // Escrow creator, defaults to wallet
   }): Promise<string> {
-    const [escrowPDA] = this.pda.deriveEscrowPDA(params.transactionId);
+    const [escrowPDA] = this.pda.deriveEscrowPDA(
+      params.agent ?? this.wallet.publicKey,
+      params.transactionId
+    );
     const escrow = await this.getEscrowAccount(escrowPDA);
+    const [disputeRecordPDA] = this.pda.deriveDisputeRecordPDA(escrowPDA);
+    const [verifierStakePDA] = this.pda.deriveOracleStakePDA(params.verifier);
 
     const [agentReputationPDA] = this.pda.deriveReputationPDA(escrow.agent);
     const [apiReputationPDA] = this.pda.deriveReputationPDA(escrow.api);
@@ -217,16 +233,24 @@ export class X402Program {
     const signatureArray = Array.from(params.signature);
 
     const tx = await this.program.methods
-      .resolveDispute(params.qualityScore, params.refundPercentage, signatureArray as any)
+      .resolveDispute(
+        params.qualityScore,
+        params.refundPercentage,
+        new BN(params.expiresAt),
+        signatureArray as any
+      )
       .accounts({
         escrow: escrowPDA,
+        disputeRecord: disputeRecordPDA,
         agent: escrow.agent,
         api: escrow.api,
         verifier: params.verifier,
+        verifierStake: verifierStakePDA,
         instructionsSysvar: INSTRUCTIONS_SYSVAR,
         agentReputation: agentReputationPDA,
         apiReputation: apiReputationPDA,
         systemProgram: SystemProgram.programId,
+        agentIdentity: escrow.agentIdentity,
       })
       .rpc();
 
@@ -281,15 +305,27 @@ export class X402Program {
     return accountData as any;
   }
 
+  /**
+   * Derive an escrow PDA, defaulting the agent to this wallet
+   *
+   * @param transactionId - Transaction ID
+   * @param agent - Escrow creator (defaults to wallet)
+   * @returns [PDA PublicKey, bump seed]
+   */
+  deriveEscrowPDA(transactionId: string, agent?: PublicKey): [PublicKey, number] {
+    return this.pda.deriveEscrowPDA(agent ?? this.wallet.publicKey, transactionId);
+  }
+
   /**
    * Check if escrow account exists
    *
    * @param transactionId - Transaction ID
+   * @param agent - Escrow creator (defaults to wallet)
    * @returns True if escrow exists
    */
-  async escrowExists(transactionId: string): Promise<boolean> {
+  async escrowExists(transactionId: string, agent?: PublicKey): Promise<boolean> {
     try {
-      const [escrowPDA] = this.pda.deriveEscrowPDA(transactionId);
+      const [escrowPDA] = this.deriveEscrowPDA(transactionId, agent);
       await this.getEscrowAccount(escrowPDA);
       return true;
     } catch {
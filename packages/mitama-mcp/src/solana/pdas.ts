@@ -8,19 +8,65 @@ export class PDADeriver {
   constructor(private programId: PublicKey) {}
 
   /**
-   * Derive escrow PDA from transaction ID
+   * Derive escrow PDA from the creating agent and transaction ID
+   * Seeds: ['escrow', agent_pubkey, transaction_id]
+   *
+   * @param agent - Agent that created the escrow
+   * @param transactionId - Transaction identifier, unique per agent
+   * @returns [PDA PublicKey, bump seed]
+   */
+  deriveEscrowPDA(agent: PublicKey, transactionId: string): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('escrow'), agent.toBuffer(), Buffer.from(transactionId)],
+      this.programId
+    );
+  }
+
+  /**
+   * Derive legacy escrow PDA from transaction ID alone
    * Seeds: ['escrow', transaction_id]
    *
-   * @param transactionId - Unique transaction identifier
+   * Only used to locate escrows created before agent namespacing,
+   * so they can be moved with `migrate_legacy_escrow`.
+   *
+   * @param transactionId - Transaction identifier
    * @returns [PDA PublicKey, bump seed]
    */
-  deriveEscrowPDA(transactionId: string): [PublicKey, number] {
+  deriveLegacyEscrowPDA(transactionId: string): [PublicKey, number] {
     return PublicKey.findProgramAddressSync(
       [Buffer.from('escrow'), Buffer.from(transactionId)],
       this.programId
     );
   }
 
+  /**
+   * Derive dispute record PDA for a disputed escrow
+   * Seeds: ['dispute', escrow_pubkey]
+   *
+   * @param escrow - Escrow PDA
+   * @returns [PDA PublicKey, bump seed]
+   */
+  deriveDisputeRecordPDA(escrow: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('dispute'), escrow.toBuffer()],
+      this.programId
+    );
+  }
+
+  /**
+   * Derive oracle stake PDA for an oracle
+   * Seeds: ['oracle_stake', oracle_pubkey]
+   *
+   * @param oracle - Oracle public key
+   * @returns [PDA PublicKey, bump seed]
+   */
+  deriveOracleStakePDA(oracle: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('oracle_stake'), oracle.toBuffer()],
+      this.programId
+    );
+  }
+
   /**
    * Derive reputation PDA for an entity (agent or API provider)
    * Seeds: ['reputation', entity_pubkey]
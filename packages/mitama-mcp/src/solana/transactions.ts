@@ -71,14 +71,54 @@ export function isValidTransactionId(transactionId: string): boolean {
 /**
  * Parse escrow status from on-chain data
  */
-export function parseEscrowStatus(status: any): 'Active' | 'Released' | 'Disputed' | 'Resolved' {
+export function parseEscrowStatus(
+  status: any
+): 'Active' | 'Released' | 'Disputed' | 'Resolved' | 'PendingSettlement' {
   if ('active' in status) return 'Active';
   if ('released' in status) return 'Released';
   if ('disputed' in status) return 'Disputed';
   if ('resolved' in status) return 'Resolved';
+  if ('pendingSettlement' in status) return 'PendingSettlement';
   throw new Error('Unknown escrow status');
 }
 
+/**
+ * One step of the quality-to-refund curve
+ */
+export interface RefundTier {
+  minQuality: number;
+  refundPercentage: number;
+}
+
+/**
+ * Default refund curve of the on-chain program
+ */
+export const DEFAULT_REFUND_TIERS: RefundTier[] = [
+  { minQuality: 0, refundPercentage: 100 },
+  { minQuality: 50, refundPercentage: 75 },
+  { minQuality: 65, refundPercentage: 35 },
+  { minQuality: 80, refundPercentage: 0 },
+];
+
+/**
+ * Refund percentage for a quality score, using the same rule as the program:
+ * the refund of the highest tier whose minimum quality the score reaches.
+ * Pass the protocol config's tier table when the admin has changed it.
+ */
+export function refundFromQuality(
+  qualityScore: number,
+  tiers: RefundTier[] = DEFAULT_REFUND_TIERS
+): number {
+  const table = tiers.length > 0 ? tiers : DEFAULT_REFUND_TIERS;
+  let refund = 100;
+  for (const tier of table) {
+    if (qualityScore >= tier.minQuality) {
+      refund = tier.refundPercentage;
+    }
+  }
+  return refund;
+}
+
 /**
  * Calculate refund amount from percentage
  */
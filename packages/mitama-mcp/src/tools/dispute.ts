@@ -54,7 +54,7 @@ export async function fileDispute(
     }
 
     // Get escrow account to verify status
-    const [escrowPDA] = program.pda.deriveEscrowPDA(params.transactionId);
+    const [escrowPDA] = program.deriveEscrowPDA(params.transactionId);
     const escrow = await program.getEscrowAccount(escrowPDA);
     const status = parseEscrowStatus(escrow.status);
 
@@ -135,7 +135,7 @@ export async function checkEscrowStatus(
     if (params.escrowAddress) {
       escrowPDA = new PublicKey(params.escrowAddress);
     } else if (params.transactionId) {
-      [escrowPDA] = program.pda.deriveEscrowPDA(params.transactionId);
+      [escrowPDA] = program.deriveEscrowPDA(params.transactionId);
     } else {
       return { success: false, error: 'Either escrowAddress or transactionId is required' };
     }
@@ -171,7 +171,7 @@ export async function verifyPayment(
   program: X402Program
 ): Promise<VerifyPaymentResult> {
   try {
-    const [escrowPDA] = program.pda.deriveEscrowPDA(params.transactionId);
+    const [escrowPDA] = program.deriveEscrowPDA(params.transactionId);
 
     // Check if escrow exists
     const exists = await program.escrowExists(params.transactionId);
@@ -1,4 +1,4 @@
-import { calculateRefundAmount } from '../solana/transactions.js';
+import { calculateRefundAmount, refundFromQuality } from '../solana/transactions.js';
 
 export interface AssessDataQualityParams {
   apiResponse: Record<string, any>; // API response JSON
@@ -119,21 +119,8 @@ export async function assessDataQuality(
       completenessScore * 0.5 + schemaComplianceScore * 0.3 + freshnessScore * 0.2
     );
 
-    // Calculate refund percentage based on quality score
-    // Quality 80-100: 0% refund (high quality)
-    // Quality 50-79: 25% refund (medium quality)
-    // Quality 30-49: 50% refund (low quality)
-    // Quality 0-29: 100% refund (very poor quality)
-    let refundPercentage: number;
-    if (qualityScore >= 80) {
-      refundPercentage = 0;
-    } else if (qualityScore >= 50) {
-      refundPercentage = Math.round((80 - qualityScore) * (25 / 30));
-    } else if (qualityScore >= 30) {
-      refundPercentage = 25 + Math.round((50 - qualityScore) * (25 / 20));
-    } else {
-      refundPercentage = 50 + Math.round((30 - qualityScore) * (50 / 30));
-    }
+    // Refund follows the on-chain curve so the estimate matches settlement
+    const refundPercentage = refundFromQuality(qualityScore);
 
     // Generate rationale
     const rationale = generateRationale(
@@ -172,18 +159,7 @@ export async function estimateRefund(params: EstimateRefundParams): Promise<Esti
       return { success: false, error: 'Quality score must be between 0 and 100' };
     }
 
-    // Calculate refund percentage (inverse of quality)
-    // High quality (80-100): 0-20% refund
-    // Medium quality (50-79): 20-50% refund
-    // Low quality (0-49): 50-100% refund
-    let refundPercentage: number;
-    if (qualityScore >= 80) {
-      refundPercentage = Math.round((100 - qualityScore) * 0.2);
-    } else if (qualityScore >= 50) {
-      refundPercentage = 20 + Math.round((80 - qualityScore) * 0.3);
-    } else {
-      refundPercentage = 50 + Math.round((50 - qualityScore));
-    }
+    const refundPercentage = refundFromQuality(qualityScore);
 
     const amountLamports = amount * 1_000_000_000;
     const refundLamports = calculateRefundAmount(amountLamports, refundPercentage);
@@ -116,7 +116,7 @@ async function testPDADerivation() {
 
         // Test escrow PDA
         const [escrowPda, escrowBump] = solanaWeb3.PublicKey.findProgramAddressSync(
-            [Buffer.from('escrow'), Buffer.from(testTxId)],
+            [Buffer.from('escrow'), testEntity.toBuffer(), Buffer.from(testTxId)],
             PROGRAM_ID
         );
         console.log(`Escrow PDA: ${escrowPda.toString()} (bump: ${escrowBump})`);
@@ -208,12 +208,13 @@ describe('Security and Attack Vector Tests', () => {
   describe('PDA Security', () => {
     it('should derive PDAs deterministically', () => {
       const txId = 'pda_test';
+      const agent = Keypair.generate().publicKey;
       const [pda1, bump1] = PublicKey.findProgramAddressSync(
-        [Buffer.from('escrow'), Buffer.from(txId)],
+        [Buffer.from('escrow'), agent.toBuffer(), Buffer.from(txId)],
         programId
       );
       const [pda2, bump2] = PublicKey.findProgramAddressSync(
-        [Buffer.from('escrow'), Buffer.from(txId)],
+        [Buffer.from('escrow'), agent.toBuffer(), Buffer.from(txId)],
         programId
       );
 
@@ -223,9 +224,10 @@ describe('Security and Attack Vector Tests', () => {
 
     it('should prevent PDA collision attacks', () => {
       const txIds = ['test1', 'test2', 'test3'];
+      const agent = Keypair.generate().publicKey;
       const pdas = txIds.map(id => 
         PublicKey.findProgramAddressSync(
-          [Buffer.from('escrow'), Buffer.from(id)],
+          [Buffer.from('escrow'), agent.toBuffer(), Buffer.from(id)],
           programId
         )[0]
       );
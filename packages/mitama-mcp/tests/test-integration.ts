@@ -330,7 +330,7 @@ async function main() {
 
   // Test 14: Test PDA derivation
   await runTest('Test PDA derivation', async () => {
-    const [escrowPDA] = program.pda.deriveEscrowPDA(escrowResult.transactionId!);
+    const [escrowPDA] = program.deriveEscrowPDA(escrowResult.transactionId!);
     const [reputationPDA] = program.pda.deriveReputationPDA(keypair.publicKey);
     const [rateLimiterPDA] = program.pda.deriveRateLimiterPDA(keypair.publicKey);
 
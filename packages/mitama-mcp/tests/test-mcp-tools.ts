@@ -85,7 +85,7 @@ async function main() {
     const pda = new PDADeriver(programId);
     const transactionId = 'test-' + Date.now();
 
-    const [escrowPDA, escrowBump] = pda.deriveEscrowPDA(transactionId);
+    const [escrowPDA, escrowBump] = pda.deriveEscrowPDA(keypair.publicKey, transactionId);
     const [reputationPDA, repBump] = pda.deriveReputationPDA(keypair.publicKey);
     const [rateLimiterPDA, rateBump] = pda.deriveRateLimiterPDA(keypair.publicKey);
 
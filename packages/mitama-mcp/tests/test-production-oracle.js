@@ -249,7 +249,7 @@ function createEscrow(program, agent, api, amount, timeLock, transactionId) {
         return __generator(this, function (_a) {
             switch (_a.label) {
                 case 0:
-                    escrowPda = web3_js_1.PublicKey.findProgramAddressSync([Buffer.from('escrow'), Buffer.from(transactionId)], program.programId)[0];
+                    escrowPda = web3_js_1.PublicKey.findProgramAddressSync([Buffer.from('escrow'), agent.publicKey.toBuffer(), Buffer.from(transactionId)], program.programId)[0];
                     return [4 /*yield*/, program.methods
                             .initializeEscrow(amount, timeLock, transactionId)
                             .accounts({
@@ -271,7 +271,7 @@ function resolveDispute(program, agent, verifier, transactionId, qualityScore, r
         return __generator(this, function (_a) {
             switch (_a.label) {
                 case 0:
-                    escrowPda = web3_js_1.PublicKey.findProgramAddressSync([Buffer.from('escrow'), Buffer.from(transactionId)], program.programId)[0];
+                    escrowPda = web3_js_1.PublicKey.findProgramAddressSync([Buffer.from('escrow'), agent.publicKey.toBuffer(), Buffer.from(transactionId)], program.programId)[0];
                     return [4 /*yield*/, program.account.escrow.fetch(escrowPda)];
                 case 1:
                     escrowAccount = _a.sent();
@@ -184,7 +184,7 @@ async function createEscrow(
     transactionId: string
 ): Promise<string> {
     const [escrowPda] = PublicKey.findProgramAddressSync(
-        [Buffer.from('escrow'), Buffer.from(transactionId)],
+        [Buffer.from('escrow'), agent.publicKey.toBuffer(), Buffer.from(transactionId)],
         program.programId
     );
 
@@ -210,7 +210,7 @@ async function resolveDispute(
     signature: number[]
 ): Promise<string> {
     const [escrowPda] = PublicKey.findProgramAddressSync(
-        [Buffer.from('escrow'), Buffer.from(transactionId)],
+        [Buffer.from('escrow'), agent.publicKey.toBuffer(), Buffer.from(transactionId)],
         program.programId
     );
 
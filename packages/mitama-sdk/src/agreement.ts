@@ -10,10 +10,12 @@ import {
   AgreementStatus,
   CreateAgreementParams,
   ResolutionResult,
-  QUALITY_REFUND_SCALE,
+  RefundTier,
+  DEFAULT_REFUND_TIERS,
   MIN_TIME_LOCK_SECONDS,
   MAX_TIME_LOCK_SECONDS,
 } from "./types";
+import { MitamaUtils } from "./utils";
 
 /**
  * Agreement Manager - High-level agreement/escrow operations
@@ -122,19 +124,10 @@ export class AgreementManager {
    */
   calculateResolution(
     amount: BN,
-    qualityScore: number
+    qualityScore: number,
+    tiers: RefundTier[] = DEFAULT_REFUND_TIERS
   ): ResolutionResult {
-    let refundPercentage: number;
-
-    if (qualityScore <= QUALITY_REFUND_SCALE.POOR.maxQuality) {
-      refundPercentage = QUALITY_REFUND_SCALE.POOR.refund;
-    } else if (qualityScore <= QUALITY_REFUND_SCALE.BELOW_AVERAGE.maxQuality) {
-      refundPercentage = QUALITY_REFUND_SCALE.BELOW_AVERAGE.refund;
-    } else if (qualityScore <= QUALITY_REFUND_SCALE.AVERAGE.maxQuality) {
-      refundPercentage = QUALITY_REFUND_SCALE.AVERAGE.refund;
-    } else {
-      refundPercentage = QUALITY_REFUND_SCALE.GOOD.refund;
-    }
+    const refundPercentage = MitamaUtils.qualityToRefundPercentage(qualityScore, tiers);
 
     const amountNum = amount.toNumber();
     const refundAmount = Math.floor((amountNum * refundPercentage) / 100);
@@ -170,6 +163,8 @@ export class AgreementManager {
         return "Disputed";
       case AgreementStatus.Resolved:
         return "Resolved";
+      case AgreementStatus.PendingSettlement:
+        return "Pending Settlement";
       default:
         return "Unknown";
     }
@@ -64,11 +64,15 @@ export class MitamaClient {
   }
 
   /**
-   * Derive the agreement (escrow) PDA for a transaction ID
+   * Derive the agreement (escrow) PDA for an agent's transaction ID.
+   * The agent defaults to the connected wallet.
    */
-  getAgreementPDA(transactionId: string): [PublicKey, number] {
+  getAgreementPDA(
+    transactionId: string,
+    agent: PublicKey = this.wallet.publicKey
+  ): [PublicKey, number] {
     return PublicKey.findProgramAddressSync(
-      [Buffer.from("escrow"), Buffer.from(transactionId)],
+      [Buffer.from("escrow"), agent.toBuffer(), Buffer.from(transactionId)],
       this.programId
     );
   }
@@ -76,8 +80,11 @@ export class MitamaClient {
   /**
    * Alias for getAgreementPDA for backward compatibility
    */
-  deriveAgreementAddress(transactionId: string): [PublicKey, number] {
-    return this.getAgreementPDA(transactionId);
+  deriveAgreementAddress(
+    transactionId: string,
+    agent?: PublicKey
+  ): [PublicKey, number] {
+    return this.getAgreementPDA(transactionId, agent);
   }
 
   /**
@@ -110,6 +117,26 @@ export class MitamaClient {
     );
   }
 
+  /**
+   * Derive the dispute record PDA for an escrow
+   */
+  getDisputeRecordPDA(escrow: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("dispute"), escrow.toBuffer()],
+      this.programId
+    );
+  }
+
+  /**
+   * Derive the stake PDA for an oracle
+   */
+  getOracleStakePDA(oracle: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("oracle_stake"), oracle.toBuffer()],
+      this.programId
+    );
+  }
+
   /**
    * Derive the fee vault PDA
    */
@@ -163,9 +190,10 @@ export class MitamaClient {
    * Fetch agreement by transaction ID
    */
   async getAgreementByTransactionId(
-    transactionId: string
+    transactionId: string,
+    agent?: PublicKey
   ): Promise<Agreement | null> {
-    const [agreementPDA] = this.getAgreementPDA(transactionId);
+    const [agreementPDA] = this.getAgreementPDA(transactionId, agent);
     return this.getAgreement(agreementPDA);
   }
 
@@ -299,7 +327,7 @@ export class MitamaClient {
     agent: PublicKey,
     params: CreateAgreementParams
   ): TransactionInstruction {
-    const [agreementPDA] = this.getAgreementPDA(params.transactionId);
+    const [agreementPDA] = this.getAgreementPDA(params.transactionId, agent);
     const [protocolConfigPDA] = this.getProtocolConfigPDA();
     const [feeVaultPDA] = this.getFeeVaultPDA();
 
@@ -342,7 +370,9 @@ export class MitamaClient {
     transactionId: string,
     provider: PublicKey
   ): TransactionInstruction {
-    const [agreementPDA] = this.getAgreementPDA(transactionId);
+    const [agreementPDA] = this.getAgreementPDA(transactionId, agent);
+    const [agentReputationPDA] = this.getReputationPDA(agent);
+    const [providerReputationPDA] = this.getReputationPDA(provider);
 
     const discriminator = Buffer.from([
       0x8a, 0x9b, 0xac, 0xbd, 0xce, 0xdf, 0xe0, 0xf1,
@@ -354,6 +384,8 @@ export class MitamaClient {
         { pubkey: agent, isSigner: true, isWritable: true },
         { pubkey: provider, isSigner: false, isWritable: true },
         { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+        { pubkey: agentReputationPDA, isSigner: false, isWritable: true },
+        { pubkey: providerReputationPDA, isSigner: false, isWritable: true },
       ],
       programId: this.programId,
       data: discriminator,
@@ -367,7 +399,8 @@ export class MitamaClient {
     agent: PublicKey,
     transactionId: string
   ): TransactionInstruction {
-    const [agreementPDA] = this.getAgreementPDA(transactionId);
+    const [agreementPDA] = this.getAgreementPDA(transactionId, agent);
+    const [disputeRecordPDA] = this.getDisputeRecordPDA(agreementPDA);
     const [reputationPDA] = this.getReputationPDA(agent);
     const [protocolConfigPDA] = this.getProtocolConfigPDA();
     const [feeVaultPDA] = this.getFeeVaultPDA();
@@ -379,6 +412,7 @@ export class MitamaClient {
     return new TransactionInstruction({
       keys: [
         { pubkey: agreementPDA, isSigner: false, isWritable: true },
+        { pubkey: disputeRecordPDA, isSigner: false, isWritable: true },
         { pubkey: reputationPDA, isSigner: false, isWritable: true },
         { pubkey: agent, isSigner: true, isWritable: true },
         { pubkey: protocolConfigPDA, isSigner: false, isWritable: true },
@@ -538,6 +572,15 @@ export class MitamaClient {
     offset += 8;
 
     const bump = data[offset];
+    offset += 1;
+
+    const pendingUnstake = new BN(data.slice(offset, offset + 8), "le");
+    offset += 8;
+
+    const unstakeAvailableAt = new BN(data.slice(offset, offset + 8), "le");
+    offset += 8;
+
+    const openDisputes = data.readUInt32LE(offset);
 
     return {
       owner,
@@ -552,6 +595,9 @@ export class MitamaClient {
       successfulEscrows,
       disputedEscrows,
       bump,
+      pendingUnstake,
+      unstakeAvailableAt,
+      openDisputes,
     };
   }
 
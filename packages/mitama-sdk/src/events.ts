@@ -110,6 +110,8 @@ export interface MultiOracleDisputeResolvedEvent {
   oracleCount: number;
   individualScores: number[];
   oracles: PublicKey[];
+  weights: number[];
+  included: boolean[];
   consensusScore: number;
   refundPercentage: number;
   refundAmount: BN;
@@ -2,15 +2,19 @@
  * Oracle Management - Multi-Oracle Consensus
  */
 
+import { createHash } from "crypto";
 import { PublicKey } from "@solana/web3.js";
 import { MitamaClient } from "./client";
 import {
+  OracleAttestation,
   OracleRegistry,
   OracleConfig,
   OracleType,
   MAX_ORACLES,
   MIN_CONSENSUS_ORACLES,
   MAX_SCORE_DEVIATION,
+  ORACLE_ATTESTATION_DOMAIN,
+  ORACLE_ATTESTATION_VERSION,
 } from "./types";
 
 /**
@@ -119,6 +123,52 @@ export class OracleManager {
     return Math.round(weightedSum / totalWeight);
   }
 
+  /**
+   * Encode the message an oracle signs for a ruling: the attestation domain
+   * followed by the Borsh encoding of the attestation
+   */
+  encodeAttestation(attestation: OracleAttestation): Buffer {
+    const milestone =
+      attestation.milestoneIndex === null
+        ? Buffer.from([0])
+        : Buffer.from([1, attestation.milestoneIndex]);
+
+    return Buffer.concat([
+      Buffer.from(ORACLE_ATTESTATION_DOMAIN),
+      Buffer.from([ORACLE_ATTESTATION_VERSION]),
+      attestation.programId.toBuffer(),
+      Buffer.from(attestation.genesisHash),
+      attestation.escrow.toBuffer(),
+      milestone,
+      attestation.nonce.toArrayLike(Buffer, "le", 8),
+      Buffer.from([attestation.qualityScore, attestation.refundPercentage]),
+      Buffer.from(attestation.evidenceHash),
+      attestation.expiresAt.toTwos(64).toArrayLike(Buffer, "le", 8),
+    ]);
+  }
+
+  /**
+   * Hash an oracle commits to before revealing its score. The salt must be
+   * kept secret until the reveal.
+   */
+  scoreCommitment(
+    escrow: PublicKey,
+    oracle: PublicKey,
+    qualityScore: number,
+    salt: Uint8Array
+  ): Buffer {
+    if (salt.length !== 32) {
+      throw new Error("Salt must be 32 bytes");
+    }
+
+    return createHash("sha256")
+      .update(escrow.toBuffer())
+      .update(oracle.toBuffer())
+      .update(Buffer.from([qualityScore]))
+      .update(salt)
+      .digest();
+  }
+
   /**
    * Validate oracle count
    */
@@ -24,6 +24,7 @@ export enum AgreementStatus {
   Released = 1,
   Disputed = 2,
   Resolved = 3,
+  PendingSettlement = 4,
 }
 
 // Oracle Types
@@ -33,6 +34,26 @@ export enum OracleType {
   Custom = 2,
 }
 
+// Settlement policy for disputes nobody rules on in time
+export enum DisputeTimeoutPolicy {
+  FullRefund = 0,
+  Split = 1,
+}
+
+// Why an agent's stake was slashed
+export enum SlashReason {
+  LostDispute = 0,
+  FrivolousDispute = 1,
+}
+
+// Why an agent's reputation was adjusted
+export enum ReputationUpdateReason {
+  ServiceQuality = 0,
+  DisputeOutcome = 1,
+  Misconduct = 2,
+  Correction = 3,
+}
+
 // Entity Types (for reputation)
 export enum EntityType {
   Agent = 0,
@@ -61,6 +82,10 @@ export interface AgentIdentity {
   successfulEscrows: BN;
   disputedEscrows: BN;
   bump: number;
+  version: number;            // Layout version, set on init and migration
+  pendingUnstake: BN;
+  unstakeAvailableAt: BN;
+  openDisputes: number;
 }
 
 // Agreement (Escrow) Account
@@ -79,6 +104,7 @@ export interface Agreement {
   tokenMint: PublicKey | null;
   escrowTokenAccount: PublicKey | null;
   tokenDecimals: number;
+  agentIdentity: PublicKey | null; // Agent PDA whose escrow counters this agreement updates
 }
 
 // Oracle Submission
@@ -104,6 +130,9 @@ export interface OracleRegistry {
   createdAt: BN;
   updatedAt: BN;
   bump: number;
+  version: number;            // Layout version, set on init and migration
+  commitWindow: BN;           // Seconds oracles have to commit once a hearing closes
+  revealWindow: BN;           // Seconds oracles then have to reveal
 }
 
 // Entity Reputation
@@ -146,6 +175,33 @@ export interface ResolutionResult {
   paymentAmount: BN;
 }
 
+// Oracle Attestation (signed ruling on a dispute)
+export interface OracleAttestation {
+  programId: PublicKey;
+  genesisHash: Uint8Array;      // 32 bytes, identifies the cluster
+  escrow: PublicKey;
+  milestoneIndex: number | null;
+  nonce: BN;                    // Dispute record nonce
+  qualityScore: number;
+  refundPercentage: number;
+  evidenceHash: Uint8Array;     // 32 bytes, from the dispute record
+  expiresAt: BN;                // Unix timestamp
+}
+
+// Refund Tier: scores from minQuality up to the next tier refund refundPercentage
+export interface RefundTier {
+  minQuality: number;
+  refundPercentage: number;
+}
+
+// Default on-chain refund curve (matches QUALITY_REFUND_SCALE)
+export const DEFAULT_REFUND_TIERS: RefundTier[] = [
+  { minQuality: 0, refundPercentage: 100 },
+  { minQuality: 50, refundPercentage: 75 },
+  { minQuality: 65, refundPercentage: 35 },
+  { minQuality: 80, refundPercentage: 0 },
+];
+
 // Quality-Based Refund Scale
 export const QUALITY_REFUND_SCALE = {
   // 0-49% quality: Full refund (100%)
@@ -171,6 +227,30 @@ export interface ProtocolConfig {
   createdAt: BN;
   updatedAt: BN;
   bump: number;
+  version: number;            // Layout version, set on init and migration
+  disputesOpened: BN;         // Dispute counter, used as attestation nonces
+  refundTiers: RefundTier[];  // Quality-to-refund curve
+  strictRefunds: boolean;     // Reject rulings whose refund disagrees with the curve
+  oracleRewardBps: number;    // Share of each dispute fee paid to oracles
+  oracleSlashBps: number;     // Share of an outlier oracle's stake slashed per ruling
+  minOracleStake: BN;         // Stake an oracle needs to submit scores
+  resolutionTimeout: BN;      // Seconds a dispute may go without a ruling
+  timeoutPolicy: DisputeTimeoutPolicy; // How timed-out disputes settle
+  lostDisputeSlashBps: number;      // Agent stake slashed for a lost dispute
+  frivolousDisputeSlashBps: number; // Agent stake slashed for a dispute with no refund
+  slashCounterpartyBps: number;     // Share of each slash paid to the provider
+  reputationAuthority: PublicKey;   // Signer allowed to adjust agent reputation
+}
+
+// Oracle Stake Account
+export interface OracleStake {
+  oracle: PublicKey;
+  amount: BN;                 // Bonded stake, slashable
+  rewards: BN;                // Unclaimed dispute fee share
+  totalSlashed: BN;
+  createdAt: BN;
+  bump: number;
+  lockedUntil: BN;            // End of the latest dispute the oracle committed to
 }
 
 // Protocol Config Update Parameters
@@ -186,13 +266,25 @@ export interface UpdateProtocolConfigParams {
 export const MIN_TIME_LOCK_SECONDS = 3600; // 1 hour
 export const MAX_TIME_LOCK_SECONDS = 2_592_000; // 30 days
 export const MIN_STAKE_AMOUNT = 100_000_000; // 0.1 SOL
+export const AGENT_UNSTAKE_COOLDOWN = 604_800; // 7 days
 export const MAX_ORACLES = 5;
 export const MIN_CONSENSUS_ORACLES = 2;
 export const MAX_SCORE_DEVIATION = 15;
+export const ORACLE_ATTESTATION_VERSION = 1;
+export const ORACLE_ATTESTATION_DOMAIN = "mitama-oracle-attestation";
+export const DEFAULT_COMMIT_WINDOW_SECONDS = 86_400; // 24 hours
+export const DEFAULT_REVEAL_WINDOW_SECONDS = 86_400; // 24 hours
 
 // Protocol Fee Defaults
 export const DEFAULT_AGREEMENT_FEE_BPS = 50;      // 0.5%
 export const DEFAULT_DISPUTE_FEE_BPS = 100;       // 1%
 export const DEFAULT_DISPUTE_BASE_FEE = 10_000_000; // 0.01 SOL
 export const DEFAULT_IDENTITY_FEE = 5_000_000;    // 0.005 SOL
+export const DEFAULT_ORACLE_REWARD_BPS = 5_000;   // 50% of each dispute fee
+export const DEFAULT_ORACLE_SLASH_BPS = 1_000;    // 10% of stake per outlier score
+export const DEFAULT_MIN_ORACLE_STAKE = 1_000_000_000; // 1 SOL
+export const DEFAULT_RESOLUTION_TIMEOUT_SECONDS = 1_209_600; // 14 days
+export const DEFAULT_LOST_DISPUTE_SLASH_BPS = 500;       // 5% of stake
+export const DEFAULT_FRIVOLOUS_DISPUTE_SLASH_BPS = 1_000; // 10% of stake
+export const DEFAULT_SLASH_COUNTERPARTY_BPS = 5_000;     // 50% of each slash
 export const MAX_FEE_BPS = 500;                   // 5% max
@@ -4,6 +4,7 @@
 
 import { BN } from "@coral-xyz/anchor";
 import { PublicKey } from "@solana/web3.js";
+import { DEFAULT_REFUND_TIERS, RefundTier } from "./types";
 
 /**
  * Validation utilities for Mitama protocol
@@ -204,11 +205,19 @@ export class MitamaUtils {
   /**
    * Calculate refund percentage from quality score
    */
-  static qualityToRefundPercentage(qualityScore: number): number {
-    if (qualityScore <= 49) return 100;
-    if (qualityScore <= 64) return 75;
-    if (qualityScore <= 79) return 35;
-    return 0;
+  static qualityToRefundPercentage(
+    qualityScore: number,
+    tiers: RefundTier[] = DEFAULT_REFUND_TIERS
+  ): number {
+    // Same rule as the program: the highest tier the score reaches
+    const table = tiers.length > 0 ? tiers : DEFAULT_REFUND_TIERS;
+    let refund = 100;
+    for (const tier of table) {
+      if (qualityScore >= tier.minQuality) {
+        refund = tier.refundPercentage;
+      }
+    }
+    return refund;
   }
 
   /**
@@ -13,14 +13,16 @@
  *
  * Note: This uses Jaccard similarity with keyword boosting as a heuristic
  * approximation of the Python verifier's ML-based semantic matching
- * (SentenceTransformer all-MiniLM-L6-v2). Refund and freshness formulas
- * match the Python verifier exactly.
+ * (SentenceTransformer all-MiniLM-L6-v2). The freshness formula matches the
+ * Python verifier exactly, and the refund follows the on-chain tier table.
  */
 
 import {
   QualityScoringParams,
   QualityScoringResult,
   APIResponse,
+  RefundTier,
+  DEFAULT_REFUND_TIERS,
 } from './types';
 
 /**
@@ -362,24 +364,29 @@ function extractTimestamps(data: any): Date[] {
 /**
  * Calculate refund percentage based on quality score
  *
- * Thresholds:
+ * Uses the same rule as the program: the refund of the highest tier whose
+ * minimum quality the score reaches. Default tiers:
  * - 80-100: No refund (0%)
- * - 50-79: Sliding scale refund
+ * - 65-79: 35% refund
+ * - 50-64: 75% refund
  * - 0-49: Full refund (100%)
  *
  * @param qualityScore Quality score (0-100)
+ * @param tiers Protocol refund tier table, if the admin has changed it
  * @returns Refund percentage (0-100)
  */
-function calculateRefundPercentage(qualityScore: number): number {
-  if (qualityScore >= 80) {
-    return 0;
-  }
-
-  if (qualityScore >= 50) {
-    return Math.round(((80 - qualityScore) / 80) * 100);
+function calculateRefundPercentage(
+  qualityScore: number,
+  tiers: RefundTier[] = DEFAULT_REFUND_TIERS
+): number {
+  const table = tiers.length > 0 ? tiers : DEFAULT_REFUND_TIERS;
+  let refund = 100;
+  for (const tier of table) {
+    if (qualityScore >= tier.minQuality) {
+      refund = tier.refundPercentage;
+    }
   }
-
-  return 100;
+  return refund;
 }
 
 /**
@@ -46,6 +46,27 @@ export interface QualityScoringResult {
   };
 }
 
+/**
+ * One step of the on-chain quality-to-refund curve
+ */
+export interface RefundTier {
+  /** Lowest quality score the tier applies to */
+  minQuality: number;
+
+  /** Refund percentage for scores in the tier */
+  refundPercentage: number;
+}
+
+/**
+ * Default refund tier table of the Mitama program
+ */
+export const DEFAULT_REFUND_TIERS: RefundTier[] = [
+  { minQuality: 0, refundPercentage: 100 },
+  { minQuality: 50, refundPercentage: 75 },
+  { minQuality: 65, refundPercentage: 35 },
+  { minQuality: 80, refundPercentage: 0 },
+];
+
 /**
  * Exploit data structure (for crypto exploit use case)
  */
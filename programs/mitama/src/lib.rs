@@ -10,7 +10,7 @@
 //! - Trustless conflict resolution via multi-oracle consensus
 //! - Quality-based arbitration for fair dispute outcomes
 //! - On-chain reputation tracking for trust scoring
-//! - SPL token support (USDC, USDT, SOL)
+//! - SPL token support (USDC, USDT, SOL) including Token-2022 mints
 //!
 //! Core Concepts:
 //! - Agent: An autonomous entity with a PDA identity and staked collateral
@@ -21,13 +21,24 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{
     ed25519_program,
+    hash::{hashv, Hash},
     rent::Rent,
-    sysvar::instructions::{load_instruction_at_checked, ID as INSTRUCTIONS_ID},
+    sysvar::instructions::{
+        load_current_index_checked, load_instruction_at_checked, ID as INSTRUCTIONS_ID,
+    },
     sysvar::Sysvar,
 };
 use switchboard_on_demand::on_demand::accounts::pull_feed::PullFeedAccountData;
-use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer as SplTransfer};
-use anchor_spl::associated_token::AssociatedToken;
+use switchboard_on_demand::prelude::rust_decimal::prelude::ToPrimitive;
+use switchboard_on_demand::{ON_DEMAND_DEVNET_PID, ON_DEMAND_MAINNET_PID};
+use anchor_spl::token_interface::{
+    self, get_mint_extension_data, CloseAccount, HarvestWithheldTokensToMint, Mint,
+    TokenAccount, TokenInterface, TransferChecked,
+};
+use anchor_spl::token_2022::spl_token_2022::extension::{
+    non_transferable::NonTransferable, transfer_fee::TransferFeeConfig,
+    transfer_hook::TransferHook,
+};
 
 declare_id!("8z97gUtmy43FXLs5kWvqDAA6BjsHYDwKXFoM6LsngXoC");
 
@@ -63,15 +74,73 @@ const DEFAULT_DISPUTE_BASE_FEE: u64 = 10_000_000;   // 0.01 SOL
 const DEFAULT_IDENTITY_FEE: u64 = 5_000_000;        // 0.005 SOL
 const MAX_FEE_BPS: u16 = 500;                       // 5% max
 
+// Default quality-to-refund curve, used until the admin sets a tier table
+const MAX_REFUND_TIERS: usize = 8;
+const DEFAULT_REFUND_TIERS: [RefundTier; 4] = [
+    RefundTier { min_quality: 0, refund_percentage: 100 },
+    RefundTier { min_quality: 50, refund_percentage: 75 },
+    RefundTier { min_quality: 65, refund_percentage: 35 },
+    RefundTier { min_quality: 80, refund_percentage: 0 },
+];
+
 // Multi-oracle consensus constants
 const MAX_ORACLES: usize = 5;
 const MIN_CONSENSUS_ORACLES: u8 = 2;
 const MAX_SCORE_DEVIATION: u8 = 15;
 
+// Milestone escrow constants
+const MAX_MILESTONES: usize = 10;
+
+// Dispute evidence constants
+const DISPUTE_RESPONSE_WINDOW: i64 = 172_800;      // 48 hours
+const MAX_EVIDENCE_PER_PARTY: usize = 4;
+const MAX_EVIDENCE_URI_LENGTH: usize = 128;
+
+// Appeal constants
+const MAX_APPEAL_WINDOW: i64 = 604_800;             // 7 days
+
+// Dispute timeout constants
+const DEFAULT_RESOLUTION_TIMEOUT: i64 = 1_209_600;  // 14 days
+const MAX_RESOLUTION_TIMEOUT: i64 = 7_776_000;      // 90 days
+
+// Commit-reveal scoring constants
+const DEFAULT_COMMIT_WINDOW: i64 = 86_400;          // 24 hours
+const DEFAULT_REVEAL_WINDOW: i64 = 86_400;          // 24 hours
+const MAX_SCORING_WINDOW: i64 = 604_800;            // 7 days
+
+// Oracle staking constants (defaults)
+const DEFAULT_ORACLE_REWARD_BPS: u16 = 5_000;       // half of each dispute fee
+const DEFAULT_ORACLE_SLASH_BPS: u16 = 1_000;        // 10% of stake per outlier score
+const DEFAULT_MIN_ORACLE_STAKE: u64 = 1_000_000_000; // 1 SOL
+
+// Subscription constants
+const MAX_SUBSCRIPTION_PERIODS: u16 = 60;
+const MAX_SUBSCRIPTION_ID_LENGTH: usize = 24;   // leaves room for the period suffix in a 32-byte seed
+
+// Ed25519 program instruction layout
+const ED25519_OFFSETS_START: usize = 2;            // after signature count and padding
+const ED25519_OFFSETS_SIZE: usize = 14;            // seven u16 fields per signature
+const ED25519_THIS_INSTRUCTION: u16 = u16::MAX;    // offsets point into the Ed25519 instruction
+
+// Switchboard On-Demand constants
+const SWITCHBOARD_MAX_STALENESS_SLOTS: u64 = 150;  // ~1 minute
+const SWITCHBOARD_MIN_SAMPLES: u32 = MIN_CONSENSUS_ORACLES as u32;
+
 // Agent constants
 const MIN_STAKE_AMOUNT: u64 = 100_000_000;          // 0.1 SOL minimum stake
+const AGENT_UNSTAKE_COOLDOWN: i64 = 604_800;        // 7 days
+
+// Agent slashing constants (defaults)
+const LOST_DISPUTE_REFUND_THRESHOLD: u8 = 50;       // agent got back less than half
+const DEFAULT_LOST_DISPUTE_SLASH_BPS: u16 = 500;    // 5% of stake
+const DEFAULT_FRIVOLOUS_DISPUTE_SLASH_BPS: u16 = 1_000; // 10% of stake, no refund at all
+const DEFAULT_SLASH_COUNTERPARTY_BPS: u16 = 5_000;  // half of each slash to the API
 const MAX_AGENT_NAME_LENGTH: usize = 32;
 
+// Layout version written by this release. Accounts from earlier releases
+// carry no version and are brought forward by the migrate_* instructions.
+const ACCOUNT_VERSION: u8 = 1;
+
 // ============================================================================
 // Events
 // ============================================================================
@@ -92,12 +161,66 @@ pub struct AgentDeactivated {
     pub refunded_stake: u64,
 }
 
+#[event]
+pub struct AgentStakeIncreased {
+    pub agent_pda: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub stake_amount: u64,
+}
+
+#[event]
+pub struct AgentUnstakeRequested {
+    pub agent_pda: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub available_at: i64,
+}
+
+#[event]
+pub struct AgentUnstaked {
+    pub agent_pda: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub stake_amount: u64,
+}
+
+#[event]
+pub struct AgentSlashed {
+    pub agent_pda: Pubkey,
+    pub owner: Pubkey,
+    pub escrow: Pubkey,
+    pub reason: SlashReason,
+    pub refund_percentage: u8,
+    pub amount: u64,
+    pub to_counterparty: u64,
+    pub to_fee_vault: u64,
+    pub remaining_stake: u64,
+    pub deactivated: bool,
+}
+
+#[event]
+pub struct AgentSlashingPolicyUpdated {
+    pub config: Pubkey,
+    pub lost_dispute_slash_bps: u16,
+    pub frivolous_dispute_slash_bps: u16,
+    pub slash_counterparty_bps: u16,
+}
+
 #[event]
 pub struct AgentReputationUpdated {
     pub agent_pda: Pubkey,
+    pub authority: Pubkey,
     pub old_reputation: u64,
     pub new_reputation: u64,
     pub delta: i64,
+    pub reason: ReputationUpdateReason,
+}
+
+#[event]
+pub struct ReputationAuthorityUpdated {
+    pub config: Pubkey,
+    pub reputation_authority: Pubkey,
 }
 
 #[event]
@@ -112,6 +235,16 @@ pub struct EscrowInitialized {
     pub token_mint: Option<Pubkey>,
 }
 
+#[event]
+pub struct EscrowMigrated {
+    pub legacy_escrow: Pubkey,
+    pub escrow: Pubkey,
+    pub agent: Pubkey,
+    pub transaction_id: String,
+    pub status: EscrowStatus,
+    pub amount: u64,
+}
+
 #[event]
 pub struct DisputeMarked {
     pub escrow: Pubkey,
@@ -120,6 +253,17 @@ pub struct DisputeMarked {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct EvidenceSubmitted {
+    pub escrow: Pubkey,
+    pub dispute_record: Pubkey,
+    pub submitter: Pubkey,
+    pub content_hash: [u8; 32],
+    pub uri: String,
+    pub evidence_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct DisputeResolved {
     pub escrow: Pubkey,
@@ -131,6 +275,60 @@ pub struct DisputeResolved {
     pub verifier: Pubkey,
 }
 
+#[event]
+pub struct SwitchboardDisputeResolved {
+    pub escrow: Pubkey,
+    pub transaction_id: String,
+    pub feed: Pubkey,
+    pub quality_score: u8,
+    pub refund_percentage: u8,
+    pub refund_amount: u64,
+    pub payment_amount: u64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct SettlementPending {
+    pub escrow: Pubkey,
+    pub transaction_id: String,
+    pub quality_score: u8,
+    pub refund_percentage: u8,
+    pub refund_amount: u64,
+    pub payment_amount: u64,
+    pub settles_at: i64,
+}
+
+#[event]
+pub struct SettlementFinalized {
+    pub escrow: Pubkey,
+    pub transaction_id: String,
+    pub quality_score: u8,
+    pub refund_percentage: u8,
+    pub refund_amount: u64,
+    pub payment_amount: u64,
+}
+
+#[event]
+pub struct AppealFiled {
+    pub escrow: Pubkey,
+    pub appeal: Pubkey,
+    pub appellant: Pubkey,
+    pub bond: u64,
+    pub oracle_reward: u64,
+    pub original_quality_score: u8,
+    pub original_refund_percentage: u8,
+}
+
+#[event]
+pub struct AppealResolved {
+    pub escrow: Pubkey,
+    pub appellant: Pubkey,
+    pub original_refund_percentage: u8,
+    pub refund_percentage: u8,
+    pub bond: u64,
+    pub bond_refunded: bool,
+}
+
 #[event]
 pub struct FundsReleased {
     pub escrow: Pubkey,
@@ -140,12 +338,108 @@ pub struct FundsReleased {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct VestedFundsWithdrawn {
+    pub escrow: Pubkey,
+    pub transaction_id: String,
+    pub amount: u64,
+    pub total_withdrawn: u64,
+    pub api: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MilestoneReleased {
+    pub escrow: Pubkey,
+    pub transaction_id: String,
+    pub milestone_index: u8,
+    pub amount: u64,
+    pub api: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MilestoneDisputed {
+    pub escrow: Pubkey,
+    pub agent: Pubkey,
+    pub transaction_id: String,
+    pub milestone_index: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MilestoneDisputeResolved {
+    pub escrow: Pubkey,
+    pub transaction_id: String,
+    pub milestone_index: u8,
+    pub quality_score: u8,
+    pub refund_percentage: u8,
+    pub refund_amount: u64,
+    pub payment_amount: u64,
+    pub verifier: Pubkey,
+}
+
+#[event]
+pub struct SubscriptionCreated {
+    pub subscription: Pubkey,
+    pub agent: Pubkey,
+    pub api: Pubkey,
+    pub subscription_id: String,
+    pub amount_per_period: u64,
+    pub period_length: i64,
+    pub total_periods: u16,
+    pub token_mint: Option<Pubkey>,
+}
+
+#[event]
+pub struct SubscriptionPeriodOpened {
+    pub subscription: Pubkey,
+    pub escrow: Pubkey,
+    pub period: u16,
+    pub transaction_id: String,
+    pub amount: u64,
+    pub cranker: Pubkey,
+    pub rent_reimbursed: u64,
+}
+
+#[event]
+pub struct SubscriptionCancelled {
+    pub subscription: Pubkey,
+    pub cancelled_by: Pubkey,
+    pub periods_opened: u16,
+    pub total_periods: u16,
+    pub refunded: u64,
+}
+
+#[event]
+pub struct EscrowClosed {
+    pub escrow: Pubkey,
+    pub agent: Pubkey,
+    pub api: Pubkey,
+    pub transaction_id: String,
+    pub status: EscrowStatus,
+    pub amount: u64,
+    pub token_mint: Option<Pubkey>,
+    pub quality_score: Option<u8>,
+    pub refund_percentage: Option<u8>,
+    pub created_at: i64,
+    pub closed_at: i64,
+    pub rent_returned: u64,
+}
+
 #[event]
 pub struct OracleRegistryInitialized {
     pub registry: Pubkey,
     pub admin: Pubkey,
     pub min_consensus: u8,
     pub max_score_deviation: u8,
+    pub genesis_hash: [u8; 32],
+}
+
+#[event]
+pub struct AccountMigrated {
+    pub account: Pubkey,
+    pub version: u8,
 }
 
 #[event]
@@ -156,12 +450,107 @@ pub struct OracleAdded {
     pub weight: u16,
 }
 
+#[event]
+pub struct ConsensusModeUpdated {
+    pub registry: Pubkey,
+    pub old_mode: ConsensusMode,
+    pub new_mode: ConsensusMode,
+}
+
+#[event]
+pub struct DisputeTimedOut {
+    pub escrow: Pubkey,
+    pub transaction_id: String,
+    pub milestone_index: Option<u8>,
+    pub policy: DisputeTimeoutPolicy,
+    pub refund_percentage: u8,
+    pub refund_amount: u64,
+    pub payment_amount: u64,
+    pub fee_refund: u64,             // Oracle reward pool refunded to the agent
+    pub appeal_refund: u64,          // Appeal bond and oracle reward refunded to the appellant
+}
+
+#[event]
+pub struct DisputeTimeoutUpdated {
+    pub config: Pubkey,
+    pub resolution_timeout: i64,
+    pub timeout_policy: DisputeTimeoutPolicy,
+}
+
+#[event]
+pub struct ScoringWindowsUpdated {
+    pub registry: Pubkey,
+    pub commit_window: i64,
+    pub reveal_window: i64,
+}
+
+#[event]
+pub struct AppealParamsUpdated {
+    pub registry: Pubkey,
+    pub appeal_window: i64,
+    pub appeal_min_consensus: u8,
+    pub appeal_bond: u64,
+}
+
 #[event]
 pub struct OracleRemoved {
     pub registry: Pubkey,
     pub oracle: Pubkey,
 }
 
+#[event]
+pub struct OracleStaked {
+    pub oracle: Pubkey,
+    pub amount: u64,
+    pub total_stake: u64,
+}
+
+#[event]
+pub struct OracleUnstaked {
+    pub oracle: Pubkey,
+    pub amount: u64,
+    pub remaining_stake: u64,
+}
+
+#[event]
+pub struct OracleRewarded {
+    pub oracle: Pubkey,
+    pub escrow: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct OracleSlashed {
+    pub oracle: Pubkey,
+    pub escrow: Pubkey,
+    pub quality_score: u8,
+    pub consensus_score: u8,
+    pub amount: u64,
+    pub remaining_stake: u64,
+}
+
+#[event]
+pub struct OracleRevealMissed {
+    pub oracle: Pubkey,
+    pub escrow: Pubkey,
+    pub amount: u64,
+    pub remaining_stake: u64,
+}
+
+#[event]
+pub struct OracleRewardsClaimed {
+    pub oracle: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct OracleStakeParamsUpdated {
+    pub config: Pubkey,
+    pub oracle_reward_bps: u16,
+    pub oracle_slash_bps: u16,
+    pub min_oracle_stake: u64,
+}
+
 #[event]
 pub struct ProtocolConfigInitialized {
     pub config: Pubkey,
@@ -175,6 +564,13 @@ pub struct ProtocolConfigUpdated {
     pub admin: Pubkey,
 }
 
+#[event]
+pub struct RefundTiersUpdated {
+    pub config: Pubkey,
+    pub tiers: Vec<RefundTier>,
+    pub strict_refunds: bool,
+}
+
 #[event]
 pub struct ProtocolFeeCollected {
     pub fee_type: String,
@@ -183,6 +579,22 @@ pub struct ProtocolFeeCollected {
     pub treasury: Pubkey,
 }
 
+#[event]
+pub struct OracleScoreCommitted {
+    pub escrow: Pubkey,
+    pub oracle: Pubkey,
+    pub commitment_count: u8,
+}
+
+#[event]
+pub struct OracleScoreSubmitted {
+    pub escrow: Pubkey,
+    pub transaction_id: String,
+    pub oracle: Pubkey,
+    pub quality_score: u8,
+    pub submission_count: u8,
+}
+
 #[event]
 pub struct MultiOracleDisputeResolved {
     pub escrow: Pubkey,
@@ -190,6 +602,9 @@ pub struct MultiOracleDisputeResolved {
     pub oracle_count: u8,
     pub individual_scores: Vec<u8>,
     pub oracles: Vec<Pubkey>,
+    pub weights: Vec<u16>,
+    pub included: Vec<bool>,
+    pub consensus_mode: ConsensusMode,
     pub consensus_score: u8,
     pub refund_percentage: u8,
     pub refund_amount: u64,
@@ -200,43 +615,112 @@ pub struct MultiOracleDisputeResolved {
 // Helper Functions
 // ============================================================================
 
-/// Verify Ed25519 signature instruction
+/// Verify that an Ed25519 program instruction earlier in this transaction
+/// checked `signature` by `verifier_pubkey` over `message`. Every signature
+/// entry of every preceding Ed25519 instruction is searched, so one Ed25519
+/// instruction can carry the signatures of several oracles.
 pub fn verify_ed25519_signature(
     instructions_sysvar: &AccountInfo,
     signature: &[u8; 64],
     verifier_pubkey: &Pubkey,
     message: &[u8],
-    instruction_index: u16,
 ) -> Result<()> {
-    let ix = load_instruction_at_checked(instruction_index as usize, instructions_sysvar)
+    let current_index = load_current_index_checked(instructions_sysvar)
         .map_err(|_| error!(MitamaError::InvalidSignature))?;
 
-    require!(
-        ix.program_id == ed25519_program::ID,
-        MitamaError::InvalidSignature
-    );
+    for index in (0..current_index).rev() {
+        let ix = load_instruction_at_checked(index as usize, instructions_sysvar)
+            .map_err(|_| error!(MitamaError::InvalidSignature))?;
+        if ix.program_id != ed25519_program::ID {
+            continue;
+        }
 
-    require!(ix.data.len() >= 16, MitamaError::InvalidSignature);
-    require!(ix.data[0] == 1, MitamaError::InvalidSignature);
+        if ed25519_instruction_contains(
+            instructions_sysvar,
+            &ix.data,
+            signature,
+            verifier_pubkey,
+            message,
+        ) {
+            return Ok(());
+        }
+    }
 
-    let sig_offset = u16::from_le_bytes([ix.data[2], ix.data[3]]) as usize;
-    let pubkey_offset = u16::from_le_bytes([ix.data[6], ix.data[7]]) as usize;
-    let message_offset = u16::from_le_bytes([ix.data[10], ix.data[11]]) as usize;
-    let message_size = u16::from_le_bytes([ix.data[12], ix.data[13]]) as usize;
+    err!(MitamaError::InvalidSignature)
+}
 
-    let ix_signature = &ix.data[sig_offset..sig_offset + 64];
-    require!(ix_signature == signature, MitamaError::InvalidSignature);
+/// Whether any signature entry of an Ed25519 instruction covers this
+/// signature, public key and message. Truncated data and out-of-range
+/// offsets never match.
+fn ed25519_instruction_contains(
+    instructions_sysvar: &AccountInfo,
+    data: &[u8],
+    signature: &[u8; 64],
+    verifier_pubkey: &Pubkey,
+    message: &[u8],
+) -> bool {
+    let count = data.first().copied().unwrap_or(0) as usize;
 
-    let ix_pubkey = &ix.data[pubkey_offset..pubkey_offset + 32];
-    require!(ix_pubkey == verifier_pubkey.as_ref(), MitamaError::InvalidSignature);
+    (0..count).any(|entry| {
+        let start = ED25519_OFFSETS_START + entry * ED25519_OFFSETS_SIZE;
+        let Some(offsets) = data.get(start..start + ED25519_OFFSETS_SIZE) else {
+            return false;
+        };
+        let field = |i: usize| u16::from_le_bytes([offsets[2 * i], offsets[2 * i + 1]]);
 
-    let ix_message = &ix.data[message_offset..message_offset + message_size];
-    require!(ix_message == message, MitamaError::InvalidSignature);
+        // Offsets layout: signature, its instruction, public key, its
+        // instruction, message, message size, message instruction
+        let read = |instruction_index: u16, offset: u16, len: usize| {
+            ed25519_entry_bytes(instructions_sysvar, data, instruction_index, offset, len)
+        };
 
-    Ok(())
+        read(field(1), field(0), 64).as_deref() == Some(signature.as_slice())
+            && read(field(3), field(2), 32).as_deref() == Some(verifier_pubkey.as_ref())
+            && read(field(6), field(4), field(5) as usize).as_deref() == Some(message)
+    })
 }
 
-fn calculate_consensus_score(scores: &[u8], max_deviation: u8) -> Result<u8> {
+/// Bytes an Ed25519 offsets entry points at, read from the Ed25519 instruction
+/// itself or from the instruction it names. None when out of bounds.
+fn ed25519_entry_bytes(
+    instructions_sysvar: &AccountInfo,
+    ed25519_data: &[u8],
+    instruction_index: u16,
+    offset: u16,
+    len: usize,
+) -> Option<Vec<u8>> {
+    let start = offset as usize;
+    let end = start.checked_add(len)?;
+
+    if instruction_index == ED25519_THIS_INSTRUCTION {
+        return ed25519_data.get(start..end).map(<[u8]>::to_vec);
+    }
+
+    let ix = load_instruction_at_checked(instruction_index as usize, instructions_sysvar).ok()?;
+    ix.data.get(start..end).map(<[u8]>::to_vec)
+}
+
+/// Check an oracle attestation has not expired and that the Ed25519
+/// instruction carries the oracle's signature over its encoded message
+fn verify_oracle_attestation(
+    instructions_sysvar: &AccountInfo,
+    signature: &[u8; 64],
+    oracle: &Pubkey,
+    attestation: &OracleAttestation,
+    now: i64,
+) -> Result<()> {
+    attestation.check_expiry(now)?;
+    verify_ed25519_signature(
+        instructions_sysvar,
+        signature,
+        oracle,
+        &attestation.message()?,
+    )
+}
+
+/// Unweighted median consensus. Returns the consensus score and, for each
+/// input score, whether it counted or was discarded as an outlier.
+fn calculate_consensus_score(scores: &[u8], max_deviation: u8) -> Result<(u8, Vec<bool>)> {
     require!(scores.len() >= 2, MitamaError::InsufficientOracleConsensus);
 
     let mut sorted = scores.to_vec();
@@ -244,926 +728,5755 @@ fn calculate_consensus_score(scores: &[u8], max_deviation: u8) -> Result<u8> {
 
     if scores.len() == 2 {
         let avg = (sorted[0] as u16 + sorted[1] as u16) / 2;
-        return Ok(avg as u8);
+        return Ok((avg as u8, vec![true; 2]));
     }
 
     let median = sorted[sorted.len() / 2];
 
     let valid_scores: Vec<u8> = sorted.iter()
-        .filter(|&&score| {
-            let diff = if score > median { score - median } else { median - score };
-            diff <= max_deviation
-        })
+        .filter(|&&score| score.abs_diff(median) <= max_deviation)
         .copied()
         .collect();
 
     require!(valid_scores.len() >= 2, MitamaError::NoConsensusReached);
-    Ok(valid_scores[valid_scores.len() / 2])
+
+    let included = scores.iter()
+        .map(|score| score.abs_diff(median) <= max_deviation)
+        .collect();
+    Ok((valid_scores[valid_scores.len() / 2], included))
 }
 
-fn calculate_refund_from_quality(quality_score: u8) -> u8 {
-    match quality_score {
-        0..=49 => 100,
-        50..=64 => 75,
-        65..=79 => 35,
-        80..=100 => 0,
-        _ => 0,
+/// Weighted median of (score, weight) pairs sorted by score. When the
+/// cumulative weight lands exactly on the midpoint, the two neighbouring
+/// scores are averaged, matching the unweighted two-oracle case.
+fn weighted_median(sorted: &[(u8, u16)]) -> Option<u8> {
+    let total: u64 = sorted.iter().map(|&(_, w)| w as u64).sum();
+    if total == 0 {
+        return None;
     }
-}
 
-fn calculate_dispute_cost(reputation: &EntityReputation) -> u64 {
-    if reputation.total_transactions == 0 {
-        return BASE_DISPUTE_COST;
+    let mut cumulative: u64 = 0;
+    for (i, &(score, weight)) in sorted.iter().enumerate() {
+        cumulative += weight as u64;
+        if cumulative * 2 == total {
+            let next = sorted.get(i + 1).map(|&(s, _)| s).unwrap_or(score);
+            return Some(((score as u16 + next as u16) / 2) as u8);
+        }
+        if cumulative * 2 > total {
+            return Some(score);
+        }
     }
-    let dispute_rate = (reputation.disputes_filed * 100) / reputation.total_transactions;
-    let multiplier = match dispute_rate {
-        0..=20 => 1,
-        21..=40 => 2,
-        41..=60 => 5,
-        _ => 10,
-    };
-    BASE_DISPUTE_COST.saturating_mul(multiplier)
+    None
 }
 
-fn calculate_reputation_score(reputation: &EntityReputation) -> u16 {
-    if reputation.total_transactions == 0 {
-        return 500;
-    }
-    let tx_score = reputation.total_transactions.min(100) as u16 * 5;
-    let dispute_score = if reputation.disputes_filed > 0 {
-        let win_rate = (reputation.disputes_won * 100) / reputation.disputes_filed;
-        (win_rate as u16 * 3).min(300)
+/// Weighted consensus honoring each oracle's registry weight. Scores further
+/// than `max_deviation` from the weighted median are discarded, then the
+/// remaining scores are reduced by weighted median or weighted mean.
+/// Returns the consensus score and whether each input score counted.
+fn calculate_weighted_consensus_score(
+    scores: &[u8],
+    weights: &[u16],
+    max_deviation: u8,
+    trimmed_mean: bool,
+) -> Result<(u8, Vec<bool>)> {
+    require!(scores.len() >= 2, MitamaError::InsufficientOracleConsensus);
+    require!(scores.len() == weights.len(), MitamaError::InvalidOracleWeight);
+
+    let mut sorted: Vec<(u8, u16)> = scores.iter().copied().zip(weights.iter().copied()).collect();
+    sorted.sort_unstable_by_key(|&(score, _)| score);
+
+    let median = weighted_median(&sorted).ok_or(MitamaError::InvalidOracleWeight)?;
+
+    let valid: Vec<(u8, u16)> = sorted.iter()
+        .filter(|&&(score, _)| score.abs_diff(median) <= max_deviation)
+        .copied()
+        .collect();
+
+    require!(valid.len() >= 2, MitamaError::NoConsensusReached);
+
+    let consensus = if trimmed_mean {
+        let total_weight: u64 = valid.iter().map(|&(_, w)| w as u64).sum();
+        let weighted_sum: u64 = valid.iter().map(|&(s, w)| s as u64 * w as u64).sum();
+        // Round half up
+        ((weighted_sum * 2 + total_weight) / (total_weight * 2)) as u8
     } else {
-        150
+        weighted_median(&valid).ok_or(MitamaError::NoConsensusReached)?
     };
-    let quality_score = (reputation.average_quality_received as u16 * 2).min(200);
-    (tx_score + dispute_score + quality_score).min(1000)
-}
 
-fn get_rate_limits(verification: VerificationLevel) -> (u16, u16, u16) {
-    match verification {
-        VerificationLevel::Basic => (1, 10, 3),
-        VerificationLevel::Staked => (10, 100, 10),
-        VerificationLevel::Social => (50, 500, 50),
-        VerificationLevel::KYC => (1000, 10000, 1000),
-    }
+    let included = scores.iter()
+        .map(|score| score.abs_diff(median) <= max_deviation)
+        .collect();
+    Ok((consensus, included))
 }
 
-fn update_agent_reputation(
-    reputation: &mut EntityReputation,
-    quality_score: u8,
-    refund_percentage: u8,
-) -> Result<()> {
-    let clock = Clock::get()?;
-    reputation.total_transactions = reputation.total_transactions.saturating_add(1);
-
-    let total_quality = (reputation.average_quality_received as u64)
-        .saturating_mul(reputation.total_transactions.saturating_sub(1) as u64)
-        .saturating_add(quality_score as u64);
-    reputation.average_quality_received =
-        (total_quality / reputation.total_transactions as u64) as u8;
-
-    if refund_percentage >= 75 {
-        reputation.disputes_won = reputation.disputes_won.saturating_add(1);
-    } else if refund_percentage >= 25 {
-        reputation.disputes_partial = reputation.disputes_partial.saturating_add(1);
+/// Refund percentage for a quality score: the refund of the highest tier
+/// whose minimum quality the score reaches. An empty table falls back to the
+/// default curve.
+fn calculate_refund_from_quality(tiers: &[RefundTier], quality_score: u8) -> u8 {
+    let tiers = if tiers.is_empty() {
+        &DEFAULT_REFUND_TIERS[..]
     } else {
-        reputation.disputes_lost = reputation.disputes_lost.saturating_add(1);
-    }
+        tiers
+    };
+    tiers
+        .iter()
+        .rev()
+        .find(|tier| quality_score >= tier.min_quality)
+        .map(|tier| tier.refund_percentage)
+        .unwrap_or(100)
+}
 
-    reputation.last_updated = clock.unix_timestamp;
+/// A tier table must start at quality 0, rise strictly in quality and never
+/// refund more for better quality
+fn validate_refund_tiers(tiers: &[RefundTier]) -> Result<()> {
+    require!(
+        !tiers.is_empty() && tiers.len() <= MAX_REFUND_TIERS,
+        MitamaError::InvalidRefundTiers
+    );
+    require!(tiers[0].min_quality == 0, MitamaError::InvalidRefundTiers);
+    require!(
+        tiers.iter().all(|t| t.min_quality <= 100 && t.refund_percentage <= 100),
+        MitamaError::InvalidRefundTiers
+    );
+    require!(
+        tiers.windows(2).all(|pair| {
+            pair[0].min_quality < pair[1].min_quality
+                && pair[0].refund_percentage >= pair[1].refund_percentage
+        }),
+        MitamaError::InvalidRefundTiers
+    );
     Ok(())
 }
 
-fn update_api_reputation(
-    reputation: &mut EntityReputation,
-    refund_percentage: u8,
+/// Move an agent's SOL deposit into the escrow account
+fn deposit_escrow_lamports<'info>(
+    agent: &Signer<'info>,
+    escrow: &AccountInfo<'info>,
+    amount: u64,
 ) -> Result<()> {
-    let clock = Clock::get()?;
-    reputation.total_transactions = reputation.total_transactions.saturating_add(1);
+    let transfer_ix = anchor_lang::solana_program::system_instruction::transfer(
+        &agent.key(),
+        &escrow.key(),
+        amount,
+    );
+    anchor_lang::solana_program::program::invoke(
+        &transfer_ix,
+        &[agent.to_account_info(), escrow.clone()],
+    )?;
+    Ok(())
+}
 
-    let quality_delivered = 100u8.saturating_sub(refund_percentage);
-    let total_quality = (reputation.average_quality_received as u64)
-        .saturating_mul(reputation.total_transactions.saturating_sub(1) as u64)
-        .saturating_add(quality_delivered as u64);
-    reputation.average_quality_received =
-        (total_quality / reputation.total_transactions as u64) as u8;
+/// Move an agent's token deposit into the escrow vault and return the amount
+/// that actually arrived, which is less than `amount` for transfer-fee mints
+fn deposit_escrow_tokens<'info>(
+    agent: &Signer<'info>,
+    token_mint: &InterfaceAccount<'info, Mint>,
+    agent_token_account: &InterfaceAccount<'info, TokenAccount>,
+    escrow_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<u64> {
+    require!(
+        agent_token_account.mint == token_mint.key(),
+        MitamaError::TokenMintMismatch
+    );
+    validate_escrow_mint(token_mint)?;
 
-    if refund_percentage <= 25 {
-        reputation.disputes_won = reputation.disputes_won.saturating_add(1);
-    } else if refund_percentage <= 75 {
-        reputation.disputes_partial = reputation.disputes_partial.saturating_add(1);
-    } else {
-        reputation.disputes_lost = reputation.disputes_lost.saturating_add(1);
-    }
+    let balance_before = escrow_token_account.amount;
 
-    reputation.last_updated = clock.unix_timestamp;
-    Ok(())
+    let cpi_accounts = TransferChecked {
+        from: agent_token_account.to_account_info(),
+        mint: token_mint.to_account_info(),
+        to: escrow_token_account.to_account_info(),
+        authority: agent.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new(token_program.to_account_info(), cpi_accounts);
+    token_interface::transfer_checked(cpi_ctx, amount, token_mint.decimals)?;
+
+    escrow_token_account.reload()?;
+    let received = escrow_token_account.amount
+        .checked_sub(balance_before)
+        .ok_or(MitamaError::ArithmeticOverflow)?;
+    require!(received > 0, MitamaError::InvalidAmount);
+    Ok(received)
 }
 
-// ============================================================================
-// Program
-// ============================================================================
+/// Agreement fee owed on an escrow amount at a rate in basis points
+fn calculate_agreement_fee(fee_bps: u16, amount: u64) -> Result<u64> {
+    Ok((amount as u128)
+        .checked_mul(fee_bps as u128)
+        .ok_or(MitamaError::ArithmeticOverflow)?
+        .checked_div(10_000)
+        .ok_or(MitamaError::ArithmeticOverflow)? as u64)
+}
 
-#[program]
-pub mod mitama {
-    use super::*;
+/// Collect the agreement fee (SOL only for simplicity) on a new escrow
+fn collect_agreement_fee<'info>(
+    protocol_config: &mut Account<'info, ProtocolConfig>,
+    agent: &Signer<'info>,
+    fee_vault: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let agreement_fee = calculate_agreement_fee(protocol_config.agreement_fee_bps, amount)?;
+    if agreement_fee == 0 {
+        return Ok(());
+    }
 
-    // ========================================================================
-    // Agent Identity Instructions
-    // ========================================================================
+    let fee_ix = anchor_lang::solana_program::system_instruction::transfer(
+        &agent.key(),
+        &fee_vault.key(),
+        agreement_fee,
+    );
+    anchor_lang::solana_program::program::invoke(
+        &fee_ix,
+        &[agent.to_account_info(), fee_vault.clone()],
+    )?;
+
+    protocol_config.total_fees_collected = protocol_config
+        .total_fees_collected
+        .saturating_add(agreement_fee);
+
+    emit!(ProtocolFeeCollected {
+        fee_type: "agreement".to_string(),
+        amount: agreement_fee,
+        payer: agent.key(),
+        treasury: protocol_config.treasury,
+    });
 
-    /// Create a new agent identity with PDA
-    pub fn create_agent(
-        ctx: Context<CreateAgent>,
-        name: String,
-        agent_type: AgentType,
-        stake_amount: u64,
-    ) -> Result<()> {
-        require!(
-            !name.is_empty() && name.len() <= MAX_AGENT_NAME_LENGTH,
-            MitamaError::InvalidAgentName
-        );
-        require!(
-            stake_amount >= MIN_STAKE_AMOUNT,
-            MitamaError::InsufficientStake
-        );
+    Ok(())
+}
 
-        let protocol_config = &mut ctx.accounts.protocol_config;
-        require!(protocol_config.is_active, MitamaError::ProtocolNotActive);
+/// Collect the dispute fee: base fee plus a percentage of the disputed amount,
+/// plus a reputation-based cost multiplier. The oracles' share is parked in
+/// `oracle_pool` (the dispute record) and returned; the rest goes to the vault.
+fn collect_dispute_fee<'info>(
+    protocol_config: &mut Account<'info, ProtocolConfig>,
+    reputation: &EntityReputation,
+    agent: &Signer<'info>,
+    fee_vault: &AccountInfo<'info>,
+    oracle_pool: &AccountInfo<'info>,
+    disputed_amount: u64,
+) -> Result<u64> {
+    let percentage_fee = (disputed_amount as u128)
+        .checked_mul(protocol_config.dispute_fee_bps as u128)
+        .ok_or(MitamaError::ArithmeticOverflow)?
+        .checked_div(10_000)
+        .ok_or(MitamaError::ArithmeticOverflow)? as u64;
+
+    let dispute_fee = protocol_config.dispute_base_fee.saturating_add(percentage_fee);
+
+    // Also factor in reputation-based cost multiplier
+    let reputation_multiplier = calculate_dispute_cost(reputation);
+    let total_dispute_cost = dispute_fee.saturating_add(reputation_multiplier);
 
-        let identity_fee = protocol_config.identity_fee;
+    require!(
+        agent.lamports() >= total_dispute_cost,
+        MitamaError::InsufficientDisputeFunds
+    );
 
-        let clock = Clock::get()?;
-        let agent = &mut ctx.accounts.agent;
+    if total_dispute_cost == 0 {
+        return Ok(0);
+    }
 
-        agent.owner = ctx.accounts.owner.key();
-        agent.name = name.clone();
-        agent.agent_type = agent_type;
-        agent.reputation = 500;
-        agent.stake_amount = stake_amount;
-        agent.is_active = true;
-        agent.created_at = clock.unix_timestamp;
-        agent.last_active = clock.unix_timestamp;
-        agent.total_escrows = 0;
-        agent.successful_escrows = 0;
-        agent.disputed_escrows = 0;
-        agent.bump = ctx.bumps.agent;
+    let oracle_share = (total_dispute_cost as u128)
+        .checked_mul(protocol_config.oracle_reward_bps as u128)
+        .ok_or(MitamaError::ArithmeticOverflow)?
+        .checked_div(10_000)
+        .ok_or(MitamaError::ArithmeticOverflow)? as u64;
+    let protocol_share = total_dispute_cost - oracle_share;
 
-        // Transfer stake to agent PDA
-        let transfer_ix = anchor_lang::solana_program::system_instruction::transfer(
-            &ctx.accounts.owner.key(),
+    for (recipient, amount) in [(fee_vault, protocol_share), (oracle_pool, oracle_share)] {
+        if amount == 0 {
+            continue;
+        }
+        let fee_ix = anchor_lang::solana_program::system_instruction::transfer(
             &agent.key(),
-            stake_amount,
+            &recipient.key(),
+            amount,
         );
         anchor_lang::solana_program::program::invoke(
-            &transfer_ix,
-            &[
-                ctx.accounts.owner.to_account_info(),
-                agent.to_account_info(),
-            ],
+            &fee_ix,
+            &[agent.to_account_info(), recipient.clone()],
         )?;
+    }
 
-        // Collect identity creation fee
-        if identity_fee > 0 {
-            let fee_ix = anchor_lang::solana_program::system_instruction::transfer(
-                &ctx.accounts.owner.key(),
-                &ctx.accounts.fee_vault.key(),
-                identity_fee,
-            );
-            anchor_lang::solana_program::program::invoke(
-                &fee_ix,
-                &[
-                    ctx.accounts.owner.to_account_info(),
-                    ctx.accounts.fee_vault.to_account_info(),
-                ],
-            )?;
-
-            protocol_config.total_fees_collected = protocol_config
-                .total_fees_collected
-                .saturating_add(identity_fee);
+    protocol_config.total_fees_collected = protocol_config
+        .total_fees_collected
+        .saturating_add(protocol_share);
 
-            emit!(ProtocolFeeCollected {
-                fee_type: "identity".to_string(),
-                amount: identity_fee,
-                payer: ctx.accounts.owner.key(),
-                treasury: protocol_config.treasury,
-            });
-        }
+    emit!(ProtocolFeeCollected {
+        fee_type: "dispute".to_string(),
+        amount: protocol_share,
+        payer: agent.key(),
+        treasury: protocol_config.treasury,
+    });
 
-        emit!(AgentCreated {
-            agent_pda: agent.key(),
-            owner: agent.owner,
-            name,
-            agent_type: agent_type as u8,
-            stake_amount,
-        });
+    Ok(oracle_share)
+}
 
-        Ok(())
+/// Scale milestone amounts down pro rata when a transfer-fee mint delivered
+/// less than their total, leaving any rounding remainder on the last milestone
+fn scale_milestone_amounts(milestones: &mut [Milestone], sent: u64, received: u64) -> Result<()> {
+    if received == sent {
+        return Ok(());
     }
 
-    /// Deactivate agent and return stake
-    pub fn deactivate_agent(ctx: Context<DeactivateAgent>) -> Result<()> {
-        let agent = &mut ctx.accounts.agent;
-
-        require!(
-            ctx.accounts.owner.key() == agent.owner,
-            MitamaError::Unauthorized
-        );
-        require!(agent.is_active, MitamaError::AgentNotActive);
+    let last = milestones.len().saturating_sub(1);
+    let mut remaining = received;
+    for (i, milestone) in milestones.iter_mut().enumerate() {
+        milestone.amount = if i == last {
+            remaining
+        } else {
+            ((milestone.amount as u128)
+                .checked_mul(received as u128)
+                .ok_or(MitamaError::ArithmeticOverflow)?
+                / sent as u128) as u64
+        };
+        require!(milestone.amount > 0, MitamaError::InvalidAmount);
+        remaining = remaining
+            .checked_sub(milestone.amount)
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+    }
 
-        let stake_to_return = agent.stake_amount;
+    Ok(())
+}
 
-        // Transfer stake back to owner
-        **agent.to_account_info().try_borrow_mut_lamports()? -= stake_to_return;
-        **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? += stake_to_return;
+/// Split an escrowed amount into (refund to agent, payment to API)
+fn split_escrow_amount(amount: u64, refund_percentage: u8) -> Result<(u64, u64)> {
+    let refund_amount = (amount as u128)
+        .checked_mul(refund_percentage as u128)
+        .ok_or(MitamaError::ArithmeticOverflow)?
+        .checked_div(100)
+        .ok_or(MitamaError::ArithmeticOverflow)? as u64;
+    let payment_amount = amount
+        .checked_sub(refund_amount)
+        .ok_or(MitamaError::ArithmeticOverflow)?;
+    Ok((refund_amount, payment_amount))
+}
 
-        agent.is_active = false;
-        agent.stake_amount = 0;
+/// Split a dispute settlement into (refund to agent, payment to API). Only the
+/// unvested remainder of a streaming escrow is in dispute; anything vested but
+/// not yet withdrawn is paid to the API on top of its share.
+fn split_disputed_escrow(escrow: &Escrow, refund_percentage: u8) -> Result<(u64, u64)> {
+    let vested = escrow.vested_amount(Clock::get()?.unix_timestamp)?;
+    let disputed = escrow.amount
+        .checked_sub(vested)
+        .ok_or(MitamaError::ArithmeticOverflow)?;
+    let vested_owed = vested
+        .checked_sub(escrow.withdrawn)
+        .ok_or(MitamaError::ArithmeticOverflow)?;
+
+    let (refund_amount, payment_amount) = split_escrow_amount(disputed, refund_percentage)?;
+    let payment_amount = payment_amount
+        .checked_add(vested_owed)
+        .ok_or(MitamaError::ArithmeticOverflow)?;
+    Ok((refund_amount, payment_amount))
+}
 
-        emit!(AgentDeactivated {
-            agent_pda: agent.key(),
-            owner: agent.owner,
-            refunded_stake: stake_to_return,
-        });
+/// Move lamports out of a program-owned escrow account
+fn pay_out_lamports(
+    escrow: &AccountInfo,
+    agent: &AccountInfo,
+    api: &AccountInfo,
+    refund_amount: u64,
+    payment_amount: u64,
+) -> Result<()> {
+    if refund_amount > 0 {
+        **escrow.try_borrow_mut_lamports()? -= refund_amount;
+        **agent.try_borrow_mut_lamports()? += refund_amount;
+    }
 
-        Ok(())
+    if payment_amount > 0 {
+        **escrow.try_borrow_mut_lamports()? -= payment_amount;
+        **api.try_borrow_mut_lamports()? += payment_amount;
     }
 
-    /// Update agent reputation (internal use)
-    pub fn update_agent_rep(
-        ctx: Context<UpdateAgentRep>,
-        delta: i64,
-    ) -> Result<()> {
-        let agent = &mut ctx.accounts.agent;
-        let old_rep = agent.reputation;
+    Ok(())
+}
 
-        if delta >= 0 {
-            agent.reputation = agent.reputation.saturating_add(delta as u64);
-        } else {
-            agent.reputation = agent.reputation.saturating_sub((-delta) as u64);
-        }
+/// Reject Token-2022 mints whose extensions the escrow cannot honor: transfer
+/// hooks need extra accounts on every transfer and non-transferable mints can
+/// never leave the escrow. Transfer-fee and interest-bearing mints are fine, as
+/// escrow accounting works in raw token amounts actually received.
+fn validate_escrow_mint(mint: &InterfaceAccount<Mint>) -> Result<()> {
+    let mint_info = mint.to_account_info();
 
-        // Clamp to 0-1000
-        agent.reputation = agent.reputation.min(1000);
+    if let Ok(hook) = get_mint_extension_data::<TransferHook>(&mint_info) {
+        require!(
+            Option::<Pubkey>::from(hook.program_id).is_none(),
+            MitamaError::UnsupportedMintExtension
+        );
+    }
+    require!(
+        get_mint_extension_data::<NonTransferable>(&mint_info).is_err(),
+        MitamaError::UnsupportedMintExtension
+    );
 
-        let clock = Clock::get()?;
-        agent.last_active = clock.unix_timestamp;
+    Ok(())
+}
 
-        emit!(AgentReputationUpdated {
-            agent_pda: agent.key(),
-            old_reputation: old_rep,
-            new_reputation: agent.reputation,
-            delta,
-        });
+/// Token accounts needed to move funds out of a token escrow
+#[derive(Clone, Copy)]
+struct EscrowTokenAccounts<'a, 'info> {
+    token_mint: Option<&'a InterfaceAccount<'info, Mint>>,
+    escrow_token_account: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    agent_token_account: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    api_token_account: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    token_program: Option<&'a Interface<'info, TokenInterface>>,
+}
 
-        Ok(())
+/// Transfer tokens out of the escrow token account, signed by the escrow PDA.
+/// Works for both SPL Token and Token-2022; any transfer fee is withheld from
+/// the amount the destination receives.
+fn transfer_escrow_tokens<'info>(
+    escrow: &Account<'info, Escrow>,
+    token_mint: &InterfaceAccount<'info, Mint>,
+    escrow_token_account: &InterfaceAccount<'info, TokenAccount>,
+    destination: &InterfaceAccount<'info, TokenAccount>,
+    destination_owner: Pubkey,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
     }
 
-    // ========================================================================
-    // Escrow Instructions
-    // ========================================================================
+    require!(
+        escrow.token_mint == Some(token_mint.key()),
+        MitamaError::TokenMintMismatch
+    );
+    require!(
+        escrow.escrow_token_account == Some(escrow_token_account.key()),
+        MitamaError::InvalidTokenAccount
+    );
+    require!(
+        destination.mint == token_mint.key(),
+        MitamaError::TokenMintMismatch
+    );
+    require!(
+        destination.owner == destination_owner,
+        MitamaError::InvalidTokenAccount
+    );
 
-    /// Initialize a new escrow for agent-to-API payment
-    pub fn initialize_escrow(
-        ctx: Context<InitializeEscrow>,
-        amount: u64,
-        time_lock: i64,
-        transaction_id: String,
-        use_spl_token: bool,
-    ) -> Result<()> {
-        require!(amount > 0, MitamaError::InvalidAmount);
-        require!(
-            time_lock >= MIN_TIME_LOCK && time_lock <= MAX_TIME_LOCK,
-            MitamaError::InvalidTimeLock
-        );
-        require!(
-            !transaction_id.is_empty() && transaction_id.len() <= 64,
-            MitamaError::InvalidTransactionId
+    let seeds = &[
+        b"escrow".as_ref(),
+        escrow.agent.as_ref(),
+        escrow.transaction_id.as_bytes(),
+        &[escrow.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_accounts = TransferChecked {
+        from: escrow_token_account.to_account_info(),
+        mint: token_mint.to_account_info(),
+        to: destination.to_account_info(),
+        authority: escrow.to_account_info(),
+    };
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        cpi_accounts,
+        signer,
+    );
+    token_interface::transfer_checked(cpi_ctx, amount, escrow.token_decimals)
+}
+
+/// Close a fully paid-out escrow vault and return its rent to the agent.
+/// Tokens sent to the vault outside of funding are swept to `sweep_to` first,
+/// and Token-2022 withheld transfer fees are harvested to the mint, since the
+/// token program refuses to close an account holding either.
+fn close_escrow_vault<'info>(
+    escrow: &Account<'info, Escrow>,
+    token_mint: &InterfaceAccount<'info, Mint>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    sweep_to: Option<(&InterfaceAccount<'info, TokenAccount>, Pubkey)>,
+    rent_recipient: &AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    let vault_info = vault.to_account_info();
+    let remaining = {
+        let data = vault_info.try_borrow_data()?;
+        TokenAccount::try_deserialize(&mut &data[..])?.amount
+    };
+    if remaining > 0 {
+        let (destination, owner) = sweep_to.ok_or(MitamaError::MissingTokenAccount)?;
+        transfer_escrow_tokens(
+            escrow,
+            token_mint,
+            vault,
+            destination,
+            owner,
+            token_program,
+            remaining,
+        )?;
+    }
+
+    let seeds = &[
+        b"escrow".as_ref(),
+        escrow.agent.as_ref(),
+        escrow.transaction_id.as_bytes(),
+        &[escrow.bump],
+    ];
+
+    harvest_and_close_vault(
+        vault_info,
+        token_mint,
+        escrow.to_account_info(),
+        &[&seeds[..]],
+        rent_recipient.clone(),
+        token_program,
+    )
+}
+
+/// Harvest Token-2022 withheld transfer fees out of an emptied PDA-owned vault
+/// and close it, sending its rent to `rent_recipient`
+fn harvest_and_close_vault<'info>(
+    vault: AccountInfo<'info>,
+    token_mint: &InterfaceAccount<'info, Mint>,
+    authority: AccountInfo<'info>,
+    signer: &[&[&[u8]]],
+    rent_recipient: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    let mint_info = token_mint.to_account_info();
+    if get_mint_extension_data::<TransferFeeConfig>(&mint_info).is_ok() {
+        let cpi_ctx = CpiContext::new(
+            token_program.to_account_info(),
+            HarvestWithheldTokensToMint {
+                token_program_id: token_program.to_account_info(),
+                mint: mint_info,
+            },
         );
+        token_interface::harvest_withheld_tokens_to_mint(cpi_ctx, vec![vault.clone()])?;
+    }
 
-        let protocol_config = &mut ctx.accounts.protocol_config;
-        require!(protocol_config.is_active, MitamaError::ProtocolNotActive);
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: vault,
+            destination: rent_recipient,
+            authority,
+        },
+        signer,
+    );
+    token_interface::close_account(cpi_ctx)
+}
 
-        // Calculate agreement fee (basis points)
-        let agreement_fee = (amount as u128)
-            .checked_mul(protocol_config.agreement_fee_bps as u128)
-            .ok_or(MitamaError::ArithmeticOverflow)?
-            .checked_div(10_000)
-            .ok_or(MitamaError::ArithmeticOverflow)? as u64;
+/// Transfer tokens out of a subscription's vault, signed by the subscription PDA
+fn transfer_subscription_tokens<'info>(
+    subscription: &Account<'info, Subscription>,
+    token_mint: &InterfaceAccount<'info, Mint>,
+    subscription_token_account: &InterfaceAccount<'info, TokenAccount>,
+    destination: &InterfaceAccount<'info, TokenAccount>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[
+        b"subscription".as_ref(),
+        subscription.agent.as_ref(),
+        subscription.subscription_id.as_bytes(),
+        &[subscription.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        TransferChecked {
+            from: subscription_token_account.to_account_info(),
+            mint: token_mint.to_account_info(),
+            to: destination.to_account_info(),
+            authority: subscription.to_account_info(),
+        },
+        signer,
+    );
+    token_interface::transfer_checked(cpi_ctx, amount, subscription.token_decimals)
+}
 
-        let clock = Clock::get()?;
-        let escrow = &mut ctx.accounts.escrow;
+/// Split a settled escrow between agent and API in whichever asset it holds.
+/// For token escrows the vault is closed afterwards and its rent returned to
+/// `rent_recipient`, which must be the escrow's agent wallet.
+fn pay_out_escrow<'info>(
+    escrow: &Account<'info, Escrow>,
+    agent: &AccountInfo<'info>,
+    api: &AccountInfo<'info>,
+    tokens: EscrowTokenAccounts<'_, 'info>,
+    rent_recipient: Option<AccountInfo<'info>>,
+    refund_amount: u64,
+    payment_amount: u64,
+) -> Result<()> {
+    transfer_escrow_payout(escrow, agent, api, tokens, refund_amount, payment_amount)?;
+    close_settled_vault(escrow, tokens, rent_recipient)
+}
 
-        escrow.agent = ctx.accounts.agent.key();
-        escrow.api = ctx.accounts.api.key();
-        escrow.amount = amount;
-        escrow.status = EscrowStatus::Active;
-        escrow.created_at = clock.unix_timestamp;
-        escrow.expires_at = clock.unix_timestamp + time_lock;
-        escrow.transaction_id = transaction_id.clone();
-        escrow.bump = ctx.bumps.escrow;
-        escrow.quality_score = None;
-        escrow.refund_percentage = None;
-        escrow.oracle_submissions = Vec::new();
+/// Pay a refund to the agent and a payment to the API out of the escrow in
+/// whichever asset it holds, leaving any token vault open
+fn transfer_escrow_payout<'info>(
+    escrow: &Account<'info, Escrow>,
+    agent: &AccountInfo<'info>,
+    api: &AccountInfo<'info>,
+    tokens: EscrowTokenAccounts<'_, 'info>,
+    refund_amount: u64,
+    payment_amount: u64,
+) -> Result<()> {
+    if escrow.token_mint.is_none() {
+        return pay_out_lamports(
+            &escrow.to_account_info(),
+            agent,
+            api,
+            refund_amount,
+            payment_amount,
+        );
+    }
 
-        if use_spl_token {
-            let token_mint = ctx.accounts.token_mint.as_ref()
-                .ok_or(MitamaError::MissingTokenMint)?;
-            let escrow_token_account = ctx.accounts.escrow_token_account.as_ref()
-                .ok_or(MitamaError::MissingTokenAccount)?;
-            let agent_token_account = ctx.accounts.agent_token_account.as_ref()
-                .ok_or(MitamaError::MissingTokenAccount)?;
-            let token_program = ctx.accounts.token_program.as_ref()
-                .ok_or(MitamaError::MissingTokenProgram)?;
+    let token_mint = tokens.token_mint.ok_or(MitamaError::MissingTokenMint)?;
+    let escrow_token_account = tokens.escrow_token_account
+        .ok_or(MitamaError::MissingTokenAccount)?;
+    let token_program = tokens.token_program.ok_or(MitamaError::MissingTokenProgram)?;
+
+    if refund_amount > 0 {
+        let agent_token_account = tokens.agent_token_account
+            .ok_or(MitamaError::MissingTokenAccount)?;
+        transfer_escrow_tokens(
+            escrow,
+            token_mint,
+            escrow_token_account,
+            agent_token_account,
+            escrow.agent,
+            token_program,
+            refund_amount,
+        )?;
+    }
 
-            require!(
-                escrow_token_account.mint == token_mint.key(),
-                MitamaError::TokenMintMismatch
-            );
-            require!(
-                agent_token_account.mint == token_mint.key(),
-                MitamaError::TokenMintMismatch
-            );
+    if payment_amount > 0 {
+        let api_token_account = tokens.api_token_account
+            .ok_or(MitamaError::MissingTokenAccount)?;
+        transfer_escrow_tokens(
+            escrow,
+            token_mint,
+            escrow_token_account,
+            api_token_account,
+            escrow.api,
+            token_program,
+            payment_amount,
+        )?;
+    }
 
-            escrow.token_mint = Some(token_mint.key());
-            escrow.escrow_token_account = Some(escrow_token_account.key());
-            escrow.token_decimals = token_mint.decimals;
+    Ok(())
+}
 
-            let cpi_accounts = SplTransfer {
-                from: agent_token_account.to_account_info(),
-                to: escrow_token_account.to_account_info(),
-                authority: ctx.accounts.agent.to_account_info(),
-            };
-            let cpi_ctx = CpiContext::new(token_program.to_account_info(), cpi_accounts);
-            token::transfer(cpi_ctx, amount)?;
-        } else {
-            escrow.token_mint = None;
-            escrow.escrow_token_account = None;
-            escrow.token_decimals = 9;
+/// Close the vault of a token escrow that owes nothing more, returning its
+/// rent to `rent_recipient`. SOL escrows have no vault and are left as is.
+fn close_settled_vault<'info>(
+    escrow: &Account<'info, Escrow>,
+    tokens: EscrowTokenAccounts<'_, 'info>,
+    rent_recipient: Option<AccountInfo<'info>>,
+) -> Result<()> {
+    if escrow.token_mint.is_none() {
+        return Ok(());
+    }
 
-            let transfer_ix = anchor_lang::solana_program::system_instruction::transfer(
-                &ctx.accounts.agent.key(),
-                &escrow.key(),
-                amount,
-            );
-            anchor_lang::solana_program::program::invoke(
-                &transfer_ix,
-                &[
-                    ctx.accounts.agent.to_account_info(),
-                    escrow.to_account_info(),
-                ],
-            )?;
-        }
+    let token_mint = tokens.token_mint.ok_or(MitamaError::MissingTokenMint)?;
+    let escrow_token_account = tokens.escrow_token_account
+        .ok_or(MitamaError::MissingTokenAccount)?;
+    let token_program = tokens.token_program.ok_or(MitamaError::MissingTokenProgram)?;
+
+    let rent_recipient = rent_recipient.ok_or(MitamaError::MissingAgentWallet)?;
+    require!(rent_recipient.key() == escrow.agent, MitamaError::Unauthorized);
+
+    let sweep_to = tokens.api_token_account
+        .map(|account| (account, escrow.api))
+        .or(tokens.agent_token_account.map(|account| (account, escrow.agent)));
+
+    close_escrow_vault(
+        escrow,
+        token_mint,
+        escrow_token_account,
+        sweep_to,
+        &rent_recipient,
+        token_program,
+    )
+}
 
-        // Collect agreement fee (SOL only for simplicity)
-        if agreement_fee > 0 {
-            let fee_ix = anchor_lang::solana_program::system_instruction::transfer(
-                &ctx.accounts.agent.key(),
-                &ctx.accounts.fee_vault.key(),
-                agreement_fee,
-            );
-            anchor_lang::solana_program::program::invoke(
-                &fee_ix,
-                &[
-                    ctx.accounts.agent.to_account_info(),
-                    ctx.accounts.fee_vault.to_account_info(),
-                ],
-            )?;
+/// Bind a new escrow to the agent identity, if one is given. The identity
+/// must be active; its escrow count and last activity are updated.
+fn bind_agent_identity(
+    agent_identity: Option<&mut Account<AgentIdentity>>,
+    now: i64,
+) -> Result<Option<Pubkey>> {
+    let Some(identity) = agent_identity else {
+        return Ok(None);
+    };
+    require!(identity.is_active, MitamaError::AgentNotActive);
+    identity.total_escrows = identity.total_escrows.saturating_add(1);
+    identity.last_active = now;
+    Ok(Some(identity.key()))
+}
 
-            protocol_config.total_fees_collected = protocol_config
-                .total_fees_collected
-                .saturating_add(agreement_fee);
+/// Escrow lifecycle transitions counted on the agent identity
+enum AgentActivity {
+    Released,
+    Disputed,
+    Settled,
+}
 
-            emit!(ProtocolFeeCollected {
-                fee_type: "agreement".to_string(),
-                amount: agreement_fee,
-                payer: ctx.accounts.agent.key(),
-                treasury: protocol_config.treasury,
-            });
+/// Record an escrow transition on the agent identity it is bound to. Escrows
+/// opened without an identity need no identity account.
+fn record_agent_activity(
+    escrow: &Escrow,
+    agent_identity: Option<&mut Account<AgentIdentity>>,
+    activity: AgentActivity,
+) -> Result<()> {
+    let Some(bound_identity) = escrow.agent_identity else {
+        return Ok(());
+    };
+    let identity = agent_identity.ok_or(MitamaError::MissingAgentIdentity)?;
+    require_keys_eq!(identity.key(), bound_identity, MitamaError::AgentIdentityMismatch);
+
+    match activity {
+        AgentActivity::Released => {
+            identity.successful_escrows = identity.successful_escrows.saturating_add(1);
+        }
+        AgentActivity::Disputed => {
+            identity.disputed_escrows = identity.disputed_escrows.saturating_add(1);
+            identity.open_disputes = identity.open_disputes.saturating_add(1);
+        }
+        AgentActivity::Settled => {
+            identity.open_disputes = identity.open_disputes.saturating_sub(1);
         }
+    }
+    identity.last_active = Clock::get()?.unix_timestamp;
 
-        emit!(EscrowInitialized {
-            escrow: escrow.key(),
-            agent: escrow.agent,
-            api: escrow.api,
-            amount: escrow.amount,
-            expires_at: escrow.expires_at,
-            transaction_id,
-            is_token: use_spl_token,
-            token_mint: escrow.token_mint,
-        });
+    Ok(())
+}
 
-        Ok(())
-    }
+/// Slash the agent identity bound to a settled escrow when the agent lost the
+/// dispute it filed. The slash is split between the API and the fee vault; an
+/// agent left below the minimum stake is deactivated and the rest of its
+/// stake returned to its owner.
+fn slash_agent_for_dispute<'info>(
+    escrow: &Account<'info, Escrow>,
+    agent_identity: Option<&mut Account<'info, AgentIdentity>>,
+    protocol_config: &ProtocolConfig,
+    agent: &AccountInfo<'info>,
+    api: &AccountInfo<'info>,
+    fee_vault: &AccountInfo<'info>,
+    refund_percentage: u8,
+) -> Result<()> {
+    let Some(bound_identity) = escrow.agent_identity else {
+        return Ok(());
+    };
+    let identity = agent_identity.ok_or(MitamaError::MissingAgentIdentity)?;
+    require_keys_eq!(identity.key(), bound_identity, MitamaError::AgentIdentityMismatch);
 
-    /// Release funds to API (happy path)
-    pub fn release_funds(ctx: Context<ReleaseFunds>) -> Result<()> {
-        let clock = Clock::get()?;
+    let Some((reason, slash_bps)) = protocol_config.agent_slash(refund_percentage) else {
+        return Ok(());
+    };
 
-        let (status, agent_key, expires_at, transfer_amount, transaction_id, bump, token_mint) = {
-            let escrow = &ctx.accounts.escrow;
-            (
-                escrow.status,
-                escrow.agent,
-                escrow.expires_at,
-                escrow.amount,
-                escrow.transaction_id.clone(),
-                escrow.bump,
-                escrow.token_mint,
-            )
+    let amount = (identity.stake_amount as u128)
+        .checked_mul(slash_bps as u128)
+        .ok_or(MitamaError::ArithmeticOverflow)?
+        .checked_div(10_000)
+        .ok_or(MitamaError::ArithmeticOverflow)? as u64;
+    if amount == 0 {
+        return Ok(());
+    }
+    let to_counterparty = (amount as u128)
+        .checked_mul(protocol_config.slash_counterparty_bps as u128)
+        .ok_or(MitamaError::ArithmeticOverflow)?
+        .checked_div(10_000)
+        .ok_or(MitamaError::ArithmeticOverflow)? as u64;
+    let to_fee_vault = amount - to_counterparty;
+
+    **identity.to_account_info().try_borrow_mut_lamports()? -= amount;
+    **api.try_borrow_mut_lamports()? += to_counterparty;
+    **fee_vault.try_borrow_mut_lamports()? += to_fee_vault;
+    identity.stake_amount -= amount;
+
+    let deactivated = identity.is_active && identity.stake_amount < MIN_STAKE_AMOUNT;
+    if deactivated {
+        // The residual stake stays slashable while other disputes are open;
+        // the owner withdraws it through `deactivate_agent` once they settle
+        let refunded_stake = if identity.open_disputes == 0 {
+            identity.stake_amount
+        } else {
+            0
         };
+        **identity.to_account_info().try_borrow_mut_lamports()? -= refunded_stake;
+        **agent.try_borrow_mut_lamports()? += refunded_stake;
+        identity.is_active = false;
+        identity.stake_amount -= refunded_stake;
+        identity.pending_unstake = 0;
+        identity.unstake_available_at = 0;
 
-        require!(status == EscrowStatus::Active, MitamaError::InvalidStatus);
+        emit!(AgentDeactivated {
+            agent_pda: identity.key(),
+            owner: identity.owner,
+            refunded_stake,
+        });
+    }
 
-        let is_agent = ctx.accounts.agent.key() == agent_key;
-        let time_lock_expired = clock.unix_timestamp >= expires_at;
+    emit!(AgentSlashed {
+        agent_pda: identity.key(),
+        owner: identity.owner,
+        escrow: escrow.key(),
+        reason,
+        refund_percentage,
+        amount,
+        to_counterparty,
+        to_fee_vault,
+        remaining_stake: identity.stake_amount,
+        deactivated,
+    });
 
-        if !is_agent {
-            require!(time_lock_expired, MitamaError::TimeLockNotExpired);
-        }
+    Ok(())
+}
 
-        require!(is_agent || time_lock_expired, MitamaError::Unauthorized);
+/// Record a dispute settlement on both parties' reputation accounts
+fn record_settlement(
+    agent_reputation: &mut EntityReputation,
+    api_reputation: &mut EntityReputation,
+    quality_score: u8,
+    refund_percentage: u8,
+) -> Result<()> {
+    update_agent_reputation(agent_reputation, quality_score, Some(refund_percentage))?;
+    update_api_reputation(api_reputation, Some(refund_percentage))
+}
 
-        let seeds = &[b"escrow", transaction_id.as_bytes(), &[bump]];
-        let signer = &[&seeds[..]];
+/// Record an undisputed release as a full-quality transaction for each party
+/// with a reputation account. Releases never needed one, so parties that have
+/// not opened theirs can still release.
+fn record_release(
+    agent_reputation: Option<&mut Account<EntityReputation>>,
+    api_reputation: Option<&mut Account<EntityReputation>>,
+) -> Result<()> {
+    if let Some(reputation) = agent_reputation {
+        update_agent_reputation(reputation, 100, None)?;
+    }
+    if let Some(reputation) = api_reputation {
+        update_api_reputation(reputation, None)?;
+    }
+    Ok(())
+}
 
-        if token_mint.is_some() {
-            let escrow_token_account = ctx.accounts.escrow_token_account.as_ref()
-                .ok_or(MitamaError::MissingTokenAccount)?;
-            let api_token_account = ctx.accounts.api_token_account.as_ref()
-                .ok_or(MitamaError::MissingTokenAccount)?;
-            let token_program = ctx.accounts.token_program.as_ref()
-                .ok_or(MitamaError::MissingTokenProgram)?;
+/// Accounts a dispute settlement pays out of and records against
+struct DisputeSettlement<'a, 'info> {
+    escrow: &'a mut Account<'info, Escrow>,
+    agent: AccountInfo<'info>,
+    api: AccountInfo<'info>,
+    tokens: EscrowTokenAccounts<'a, 'info>,
+    agent_reputation: &'a mut EntityReputation,
+    api_reputation: &'a mut EntityReputation,
+    agent_identity: Option<&'a mut Account<'info, AgentIdentity>>,
+    protocol_config: &'a ProtocolConfig,
+    // Takes the fee vault's share of an agent slash. Rulings pass it; timeouts
+    // never slash the agent and pass none.
+    fee_vault: Option<&'a AccountInfo<'info>>,
+}
 
-            let cpi_accounts = SplTransfer {
-                from: escrow_token_account.to_account_info(),
-                to: api_token_account.to_account_info(),
-                authority: ctx.accounts.escrow.to_account_info(),
-            };
-            let cpi_ctx = CpiContext::new_with_signer(
-                token_program.to_account_info(),
-                cpi_accounts,
-                signer,
-            );
-            token::transfer(cpi_ctx, transfer_amount)?;
-        } else {
-            let cpi_context = CpiContext::new_with_signer(
-                ctx.accounts.system_program.to_account_info(),
-                anchor_lang::system_program::Transfer {
-                    from: ctx.accounts.escrow.to_account_info(),
-                    to: ctx.accounts.api.to_account_info(),
-                },
-                signer,
-            );
-            anchor_lang::system_program::transfer(cpi_context, transfer_amount)?;
+/// Settle a dispute over the whole escrow or one milestone: split it by the
+/// refund percentage, pay both parties, close the vault once nothing more is
+/// owed, then record the outcome on both reputations and the agent identity
+/// and slash an agent that lost. A timeout has no quality score and records
+/// the quality its payout implies. Returns the refund and payment amounts.
+fn settle_dispute_outcome(
+    settlement: DisputeSettlement,
+    milestone_index: Option<u8>,
+    quality_score: Option<u8>,
+    refund_percentage: u8,
+) -> Result<(u64, u64)> {
+    let DisputeSettlement {
+        escrow,
+        agent,
+        api,
+        tokens,
+        agent_reputation,
+        api_reputation,
+        mut agent_identity,
+        protocol_config,
+        fee_vault,
+    } = settlement;
+
+    let (refund_amount, payment_amount) = match milestone_index {
+        None => {
+            let (refund_amount, payment_amount) = split_disputed_escrow(escrow, refund_percentage)?;
+            pay_out_escrow(
+                escrow,
+                &agent,
+                &api,
+                tokens,
+                Some(agent.clone()),
+                refund_amount,
+                payment_amount,
+            )?;
+
+            escrow.status = EscrowStatus::Resolved;
+            if quality_score.is_some() {
+                escrow.quality_score = quality_score;
+            }
+            escrow.refund_percentage = Some(refund_percentage);
+            (refund_amount, payment_amount)
+        }
+        Some(milestone_index) => {
+            let (refund_amount, payment_amount) =
+                escrow.resolve_milestone(milestone_index, quality_score, refund_percentage)?;
+            transfer_escrow_payout(escrow, &agent, &api, tokens, refund_amount, payment_amount)?;
+
+            if let Some(final_status) = escrow.final_milestone_status() {
+                escrow.status = final_status;
+                close_settled_vault(escrow, tokens, Some(agent.clone()))?;
+            }
+            (refund_amount, payment_amount)
         }
+    };
 
-        let escrow = &mut ctx.accounts.escrow;
-        escrow.status = EscrowStatus::Released;
+    record_settlement(
+        agent_reputation,
+        api_reputation,
+        quality_score.unwrap_or(100u8.saturating_sub(refund_percentage)),
+        refund_percentage,
+    )?;
+    record_agent_activity(escrow, agent_identity.as_deref_mut(), AgentActivity::Settled)?;
+    if let Some(fee_vault) = fee_vault {
+        slash_agent_for_dispute(
+            escrow,
+            agent_identity,
+            protocol_config,
+            &agent,
+            &api,
+            fee_vault,
+            refund_percentage,
+        )?;
+    }
 
-        emit!(FundsReleased {
-            escrow: escrow.key(),
-            transaction_id: escrow.transaction_id.clone(),
-            amount: escrow.amount,
-            api: escrow.api,
-            timestamp: clock.unix_timestamp,
-        });
+    Ok((refund_amount, payment_amount))
+}
 
-        Ok(())
+/// Open the evidence record for a newly disputed escrow or milestone
+fn open_dispute_record(
+    record: &mut DisputeRecord,
+    escrow: &Escrow,
+    escrow_key: Pubkey,
+    milestone_index: Option<u8>,
+    protocol_config: &mut ProtocolConfig,
+    now: i64,
+    bump: u8,
+) {
+    record.escrow = escrow_key;
+    record.milestone_index = milestone_index;
+    record.nonce = protocol_config.next_dispute_nonce();
+    record.agent = escrow.agent;
+    record.api = escrow.api;
+    record.opened_at = now;
+    record.response_deadline = now + DISPUTE_RESPONSE_WINDOW;
+    record.evidence = Vec::new();
+    record.bump = bump;
+    record.scoring_opens_at = record.response_deadline;
+    record.commitments = Vec::new();
+    record.resolution_deadline = now.saturating_add(protocol_config.resolution_timeout);
+}
+
+/// Hold a first-round ruling in `PendingSettlement` while the registry's
+/// appeal window is open. Returns false when the ruling should be paid out
+/// immediately: appeals are disabled or this is already the appeal round.
+fn hold_for_appeal(
+    escrow: &mut Account<Escrow>,
+    registry: &OracleRegistry,
+    quality_score: u8,
+    refund_percentage: u8,
+) -> Result<bool> {
+    if registry.appeal_window == 0 || escrow.appealed {
+        return Ok(false);
     }
 
-    /// Mark escrow as disputed
-    pub fn mark_disputed(ctx: Context<MarkDisputed>) -> Result<()> {
-        let escrow = &mut ctx.accounts.escrow;
-        let reputation = &mut ctx.accounts.reputation;
-        let protocol_config = &mut ctx.accounts.protocol_config;
+    let (refund_amount, payment_amount) = split_disputed_escrow(escrow, refund_percentage)?;
+    let settles_at = Clock::get()?.unix_timestamp
+        .checked_add(registry.appeal_window)
+        .ok_or(MitamaError::ArithmeticOverflow)?;
+
+    escrow.status = EscrowStatus::PendingSettlement;
+    escrow.quality_score = Some(quality_score);
+    escrow.refund_percentage = Some(refund_percentage);
+    escrow.settles_at = settles_at;
+
+    emit!(SettlementPending {
+        escrow: escrow.key(),
+        transaction_id: escrow.transaction_id.clone(),
+        quality_score,
+        refund_percentage,
+        refund_amount,
+        payment_amount,
+        settles_at,
+    });
+
+    Ok(true)
+}
 
-        require!(protocol_config.is_active, MitamaError::ProtocolNotActive);
-        require!(escrow.status == EscrowStatus::Active, MitamaError::InvalidStatus);
-        require!(ctx.accounts.agent.key() == escrow.agent, MitamaError::Unauthorized);
+/// Settle the appeal bond once the appeal round has ruled. The bond goes back
+/// to the appellant if the ruling moved in their favour and to the other party
+/// otherwise; the appeal account's rent always returns to the appellant.
+fn settle_appeal_bond<'info>(
+    appeal: &Account<'info, Appeal>,
+    agent: &AccountInfo<'info>,
+    api: &AccountInfo<'info>,
+    refund_percentage: u8,
+) -> Result<bool> {
+    let appellant_is_agent = appeal.appellant == agent.key();
+    let (appellant, counterparty) = if appellant_is_agent {
+        (agent, api)
+    } else {
+        (api, agent)
+    };
+    let bond_refunded = if appellant_is_agent {
+        refund_percentage > appeal.original_refund_percentage
+    } else {
+        refund_percentage < appeal.original_refund_percentage
+    };
 
-        let clock = Clock::get()?;
-        require!(clock.unix_timestamp < escrow.expires_at, MitamaError::DisputeWindowExpired);
+    if !bond_refunded {
+        let appeal_info = appeal.to_account_info();
+        **appeal_info.try_borrow_mut_lamports()? -= appeal.bond;
+        **counterparty.try_borrow_mut_lamports()? += appeal.bond;
+    }
+    appeal.close(appellant.clone())?;
 
-        // Calculate dispute fee: base fee + percentage of disputed amount
-        let percentage_fee = (escrow.amount as u128)
-            .checked_mul(protocol_config.dispute_fee_bps as u128)
-            .ok_or(MitamaError::ArithmeticOverflow)?
-            .checked_div(10_000)
-            .ok_or(MitamaError::ArithmeticOverflow)? as u64;
+    Ok(bond_refunded)
+}
 
-        let dispute_fee = protocol_config.dispute_base_fee.saturating_add(percentage_fee);
+/// Load an oracle's stake account from the remaining accounts. The account
+/// must be the oracle's stake address; if nothing is staked there it is
+/// skipped rather than failing the ruling.
+fn load_oracle_stake<'info>(
+    info: &'info AccountInfo<'info>,
+    oracle: &Pubkey,
+) -> Result<Option<Account<'info, OracleStake>>> {
+    let (expected, _) =
+        Pubkey::find_program_address(&[b"oracle_stake", oracle.as_ref()], &crate::ID);
+    require_keys_eq!(info.key(), expected, MitamaError::OracleStakeMismatch);
+
+    if info.owner != &crate::ID || info.data_is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Account::try_from(info)?))
+}
 
-        // Also factor in reputation-based cost multiplier
-        let reputation_multiplier = calculate_dispute_cost(reputation);
-        let total_dispute_cost = dispute_fee.saturating_add(reputation_multiplier);
+/// Split the dispute's oracle reward pool among oracles whose score landed
+/// within `max_deviation` of consensus and slash the stake of those outside
+/// it into the fee vault. `stakes` are the oracles' stake accounts, in the
+/// same order as `submissions`; the share of an oracle with no stake account
+/// stays in the pool.
+fn settle_oracle_stakes<'info>(
+    stakes: &'info [AccountInfo<'info>],
+    submissions: &[OracleSubmission],
+    dispute_record: &mut Account<'info, DisputeRecord>,
+    fee_vault: &AccountInfo<'info>,
+    slash_bps: u16,
+    consensus_score: u8,
+    max_deviation: u8,
+) -> Result<()> {
+    require!(stakes.len() == submissions.len(), MitamaError::OracleStakeMismatch);
+
+    let aligned = submissions.iter()
+        .filter(|s| s.quality_score.abs_diff(consensus_score) <= max_deviation)
+        .count() as u64;
+    let reward_share = dispute_record.oracle_reward_pool
+        .checked_div(aligned)
+        .unwrap_or(0);
+
+    for (info, submission) in stakes.iter().zip(submissions) {
+        let Some(mut stake) = load_oracle_stake(info, &submission.oracle)? else {
+            continue;
+        };
 
-        require!(
-            ctx.accounts.agent.lamports() >= total_dispute_cost,
-            MitamaError::InsufficientDisputeFunds
-        );
+        if submission.quality_score.abs_diff(consensus_score) <= max_deviation {
+            if reward_share == 0 {
+                continue;
+            }
+            **dispute_record.to_account_info().try_borrow_mut_lamports()? -= reward_share;
+            **info.try_borrow_mut_lamports()? += reward_share;
+            dispute_record.oracle_reward_pool -= reward_share;
+            stake.rewards = stake.rewards.saturating_add(reward_share);
+
+            emit!(OracleRewarded {
+                oracle: stake.oracle,
+                escrow: dispute_record.escrow,
+                amount: reward_share,
+            });
+        } else {
+            let slashed = slash_oracle_stake(&mut stake, fee_vault, slash_bps)?;
+            if slashed == 0 {
+                continue;
+            }
+
+            emit!(OracleSlashed {
+                oracle: stake.oracle,
+                escrow: dispute_record.escrow,
+                quality_score: submission.quality_score,
+                consensus_score,
+                amount: slashed,
+                remaining_stake: stake.amount,
+            });
+        }
 
-        // Collect dispute fee
-        if total_dispute_cost > 0 {
-            let fee_ix = anchor_lang::solana_program::system_instruction::transfer(
-                &ctx.accounts.agent.key(),
-                &ctx.accounts.fee_vault.key(),
-                total_dispute_cost,
-            );
-            anchor_lang::solana_program::program::invoke(
-                &fee_ix,
-                &[
-                    ctx.accounts.agent.to_account_info(),
-                    ctx.accounts.fee_vault.to_account_info(),
-                ],
-            )?;
+        stake.exit(&crate::ID)?;
+    }
 
-            protocol_config.total_fees_collected = protocol_config
-                .total_fees_collected
-                .saturating_add(total_dispute_cost);
+    Ok(())
+}
 
-            emit!(ProtocolFeeCollected {
-                fee_type: "dispute".to_string(),
-                amount: total_dispute_cost,
-                payer: ctx.accounts.agent.key(),
-                treasury: protocol_config.treasury,
-            });
-        }
+/// Slash oracles that committed a score but never revealed it. `stakes` are
+/// their stake accounts, in the same order as `absent`.
+fn slash_missing_reveals<'info>(
+    stakes: &'info [AccountInfo<'info>],
+    absent: &[Pubkey],
+    escrow: Pubkey,
+    fee_vault: &AccountInfo<'info>,
+    slash_bps: u16,
+) -> Result<()> {
+    require!(stakes.len() == absent.len(), MitamaError::OracleStakeMismatch);
 
-        reputation.disputes_filed = reputation.disputes_filed.saturating_add(1);
-        escrow.status = EscrowStatus::Disputed;
+    for (info, oracle) in stakes.iter().zip(absent) {
+        let Some(mut stake) = load_oracle_stake(info, oracle)? else {
+            continue;
+        };
 
-        emit!(DisputeMarked {
-            escrow: escrow.key(),
-            agent: escrow.agent,
-            transaction_id: escrow.transaction_id.clone(),
-            timestamp: clock.unix_timestamp,
-        });
+        let slashed = slash_oracle_stake(&mut stake, fee_vault, slash_bps)?;
+        stake.exit(&crate::ID)?;
 
-        Ok(())
+        emit!(OracleRevealMissed {
+            oracle: *oracle,
+            escrow,
+            amount: slashed,
+            remaining_stake: stake.amount,
+        });
     }
 
-    /// Resolve dispute with verifier oracle signature
-    pub fn resolve_dispute(
-        ctx: Context<ResolveDispute>,
-        quality_score: u8,
-        refund_percentage: u8,
-        signature: [u8; 64],
-    ) -> Result<()> {
-        // Extract values we need before mutating
-        let (status, transaction_id, amount, escrow_key) = {
-            let escrow = &ctx.accounts.escrow;
-            (
-                escrow.status,
-                escrow.transaction_id.clone(),
-                escrow.amount,
-                escrow.key(),
-            )
-        };
+    Ok(())
+}
 
-        require!(
-            status == EscrowStatus::Active || status == EscrowStatus::Disputed,
-            MitamaError::InvalidStatus
-        );
-        require!(quality_score <= 100, MitamaError::InvalidQualityScore);
-        require!(refund_percentage <= 100, MitamaError::InvalidRefundPercentage);
+/// Move `slash_bps` of an oracle's bonded stake into the fee vault and return
+/// the amount taken
+fn slash_oracle_stake<'info>(
+    stake: &mut Account<'info, OracleStake>,
+    fee_vault: &AccountInfo<'info>,
+    slash_bps: u16,
+) -> Result<u64> {
+    let slashed = (stake.amount as u128)
+        .checked_mul(slash_bps as u128)
+        .ok_or(MitamaError::ArithmeticOverflow)?
+        .checked_div(10_000)
+        .ok_or(MitamaError::ArithmeticOverflow)? as u64;
+
+    if slashed > 0 {
+        **stake.to_account_info().try_borrow_mut_lamports()? -= slashed;
+        **fee_vault.try_borrow_mut_lamports()? += slashed;
+        stake.amount -= slashed;
+        stake.total_slashed = stake.total_slashed.saturating_add(slashed);
+    }
 
-        let message = format!("{}:{}", transaction_id, quality_score);
-        verify_ed25519_signature(
-            &ctx.accounts.instructions_sysvar,
-            &signature,
-            ctx.accounts.verifier.key,
-            message.as_bytes(),
-            0,
-        )?;
+    Ok(slashed)
+}
 
-        let refund_amount = (amount as u128)
-            .checked_mul(refund_percentage as u128)
-            .ok_or(MitamaError::ArithmeticOverflow)?
-            .checked_div(100)
-            .ok_or(MitamaError::ArithmeticOverflow)? as u64;
-        let payment_amount = amount - refund_amount;
+/// Close a dispute record outliving its settled escrow, returning its rent to
+/// the agent. Oracle rewards nobody earned go to the protocol, as in
+/// close_dispute_record. Records already closed are skipped.
+fn close_open_dispute_record<'info>(
+    record_info: &AccountInfo<'info>,
+    fee_vault: &AccountInfo<'info>,
+    agent: &AccountInfo<'info>,
+) -> Result<()> {
+    if record_info.owner != &crate::ID {
+        return Ok(());
+    }
 
-        // Transfer funds using account info directly
-        if refund_amount > 0 {
-            **ctx.accounts.escrow.to_account_info().try_borrow_mut_lamports()? -= refund_amount;
-            **ctx.accounts.agent.to_account_info().try_borrow_mut_lamports()? += refund_amount;
-        }
+    let record = DisputeRecord::try_deserialize(&mut &record_info.data.borrow()[..])?;
+    let unclaimed = record.oracle_reward_pool;
+    if unclaimed > 0 {
+        **record_info.try_borrow_mut_lamports()? -= unclaimed;
+        **fee_vault.try_borrow_mut_lamports()? += unclaimed;
+    }
 
-        if payment_amount > 0 {
-            **ctx.accounts.escrow.to_account_info().try_borrow_mut_lamports()? -= payment_amount;
-            **ctx.accounts.api.to_account_info().try_borrow_mut_lamports()? += payment_amount;
-        }
+    let rent = record_info.lamports();
+    **record_info.try_borrow_mut_lamports()? = 0;
+    **agent.try_borrow_mut_lamports()? += rent;
+    record_info.assign(&System::id());
+    record_info.resize(0)?;
+    Ok(())
+}
 
-        // Now we can mutate the escrow account state
-        let escrow = &mut ctx.accounts.escrow;
-        escrow.status = EscrowStatus::Resolved;
-        escrow.quality_score = Some(quality_score);
-        escrow.refund_percentage = Some(refund_percentage);
+/// Gate the single-verifier resolution paths. They skip commit-reveal and
+/// consensus, so they are only open to a registered oracle bonding at least
+/// the protocol minimum, and only before a commit-reveal round has started on
+/// the dispute.
+fn check_single_verifier(
+    registry: &OracleRegistry,
+    dispute_record: &DisputeRecord,
+    verifier: &Pubkey,
+    verifier_stake: &OracleStake,
+    min_oracle_stake: u64,
+) -> Result<()> {
+    require!(registry.is_registered(verifier), MitamaError::UnregisteredOracle);
+    require!(
+        verifier_stake.amount >= min_oracle_stake,
+        MitamaError::InsufficientOracleStake
+    );
+    require!(
+        dispute_record.commitments.is_empty(),
+        MitamaError::SingleVerifierDisabled
+    );
+    Ok(())
+}
 
-        // Update reputations
-        let clock = Clock::get()?;
-        let agent_reputation = &mut ctx.accounts.agent_reputation;
-        agent_reputation.total_transactions = agent_reputation.total_transactions.saturating_add(1);
-        agent_reputation.reputation_score = calculate_reputation_score(agent_reputation);
-        agent_reputation.last_updated = clock.unix_timestamp;
+/// Ensure an account is a Switchboard On-Demand pull feed
+fn validate_switchboard_feed(feed: &AccountInfo) -> Result<()> {
+    let owner = feed.owner.to_bytes();
+    require!(
+        owner == ON_DEMAND_MAINNET_PID.to_bytes() || owner == ON_DEMAND_DEVNET_PID.to_bytes(),
+        MitamaError::InvalidSwitchboardFeed
+    );
+    PullFeedAccountData::parse(feed.data.borrow())
+        .map_err(|_| error!(MitamaError::InvalidSwitchboardFeed))?;
+    Ok(())
+}
 
-        let api_reputation = &mut ctx.accounts.api_reputation;
-        api_reputation.total_transactions = api_reputation.total_transactions.saturating_add(1);
-        api_reputation.reputation_score = calculate_reputation_score(api_reputation);
-        api_reputation.last_updated = clock.unix_timestamp;
+/// Read a 0-100 quality score from a Switchboard pull feed, rejecting stale
+/// results and results backed by too few oracle samples
+fn read_switchboard_quality_score(feed: &AccountInfo, clock_slot: u64) -> Result<u8> {
+    validate_switchboard_feed(feed)?;
+    let data = PullFeedAccountData::parse(feed.data.borrow())
+        .map_err(|_| error!(MitamaError::InvalidSwitchboardFeed))?;
+
+    let value = data
+        .get_value(
+            clock_slot,
+            SWITCHBOARD_MAX_STALENESS_SLOTS,
+            SWITCHBOARD_MIN_SAMPLES,
+            false,
+        )
+        .map_err(|_| error!(MitamaError::StaleSwitchboardFeed))?;
+
+    let score = value
+        .round()
+        .to_u8()
+        .ok_or(MitamaError::InvalidQualityScore)?;
+    require!(score <= 100, MitamaError::InvalidQualityScore);
+    Ok(score)
+}
 
-        emit!(DisputeResolved {
-            escrow: escrow_key,
-            transaction_id,
-            quality_score,
-            refund_percentage,
-            refund_amount,
-            payment_amount,
-            verifier: ctx.accounts.verifier.key(),
-        });
+fn calculate_dispute_cost(reputation: &EntityReputation) -> u64 {
+    if reputation.total_transactions == 0 {
+        return BASE_DISPUTE_COST;
+    }
+    let dispute_rate = (reputation.disputes_filed * 100) / reputation.total_transactions;
+    let multiplier = match dispute_rate {
+        0..=20 => 1,
+        21..=40 => 2,
+        41..=60 => 5,
+        _ => 10,
+    };
+    BASE_DISPUTE_COST.saturating_mul(multiplier)
+}
 
-        Ok(())
+fn calculate_reputation_score(reputation: &EntityReputation) -> u16 {
+    if reputation.total_transactions == 0 {
+        return 500;
     }
+    let tx_score = reputation.total_transactions.min(100) as u16 * 5;
+    let dispute_score = if reputation.disputes_filed > 0 {
+        let win_rate = (reputation.disputes_won * 100) / reputation.disputes_filed;
+        (win_rate as u16 * 3).min(300)
+    } else {
+        150
+    };
+    let quality_score = (reputation.average_quality_received as u16 * 2).min(200);
+    (tx_score + dispute_score + quality_score).min(1000)
+}
 
-    // ========================================================================
-    // Oracle Registry Instructions
-    // ========================================================================
+fn get_rate_limits(verification: VerificationLevel) -> (u16, u16, u16) {
+    match verification {
+        VerificationLevel::Basic => (1, 10, 3),
+        VerificationLevel::Staked => (10, 100, 10),
+        VerificationLevel::Social => (50, 500, 50),
+        VerificationLevel::KYC => (1000, 10000, 1000),
+    }
+}
 
-    /// Initialize the oracle registry
-    pub fn initialize_oracle_registry(
-        ctx: Context<InitializeOracleRegistry>,
-        min_consensus: u8,
-        max_score_deviation: u8,
-    ) -> Result<()> {
-        let registry = &mut ctx.accounts.oracle_registry;
+/// Fold a settled transaction into the agent's reputation. `refund_percentage`
+/// is `None` for undisputed releases, which leave the dispute counters alone.
+fn update_agent_reputation(
+    reputation: &mut EntityReputation,
+    quality_score: u8,
+    refund_percentage: Option<u8>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    reputation.total_transactions = reputation.total_transactions.saturating_add(1);
 
-        require!(min_consensus >= MIN_CONSENSUS_ORACLES, MitamaError::InsufficientOracleConsensus);
-        require!(max_score_deviation <= 50, MitamaError::InvalidQualityScore);
+    let total_quality = (reputation.average_quality_received as u64)
+        .saturating_mul(reputation.total_transactions.saturating_sub(1) as u64)
+        .saturating_add(quality_score as u64);
+    reputation.average_quality_received =
+        (total_quality / reputation.total_transactions as u64) as u8;
 
-        let clock = Clock::get()?;
+    match refund_percentage {
+        Some(refund) if refund >= 75 => {
+            reputation.disputes_won = reputation.disputes_won.saturating_add(1);
+        }
+        Some(refund) if refund >= 25 => {
+            reputation.disputes_partial = reputation.disputes_partial.saturating_add(1);
+        }
+        Some(_) => {
+            reputation.disputes_lost = reputation.disputes_lost.saturating_add(1);
+        }
+        None => {}
+    }
 
-        registry.admin = ctx.accounts.admin.key();
-        registry.oracles = Vec::new();
-        registry.min_consensus = min_consensus;
-        registry.max_score_deviation = max_score_deviation;
-        registry.created_at = clock.unix_timestamp;
-        registry.updated_at = clock.unix_timestamp;
-        registry.bump = ctx.bumps.oracle_registry;
+    reputation.reputation_score = calculate_reputation_score(reputation);
+    reputation.last_updated = clock.unix_timestamp;
+    Ok(())
+}
 
-        emit!(OracleRegistryInitialized {
-            registry: registry.key(),
-            admin: registry.admin,
-            min_consensus,
-            max_score_deviation,
-        });
+/// Fold a settled transaction into the API's reputation, scoring the quality
+/// it delivered as the share of the payment it kept
+fn update_api_reputation(
+    reputation: &mut EntityReputation,
+    refund_percentage: Option<u8>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    reputation.total_transactions = reputation.total_transactions.saturating_add(1);
 
-        Ok(())
-    }
+    let quality_delivered = 100u8.saturating_sub(refund_percentage.unwrap_or(0));
+    let total_quality = (reputation.average_quality_received as u64)
+        .saturating_mul(reputation.total_transactions.saturating_sub(1) as u64)
+        .saturating_add(quality_delivered as u64);
+    reputation.average_quality_received =
+        (total_quality / reputation.total_transactions as u64) as u8;
 
-    /// Add an oracle to the registry
-    pub fn add_oracle(
-        ctx: Context<ManageOracle>,
-        oracle_pubkey: Pubkey,
-        oracle_type: OracleType,
-        weight: u16,
-    ) -> Result<()> {
-        let registry = &mut ctx.accounts.oracle_registry;
+    match refund_percentage {
+        Some(refund) if refund <= 25 => {
+            reputation.disputes_won = reputation.disputes_won.saturating_add(1);
+        }
+        Some(refund) if refund <= 75 => {
+            reputation.disputes_partial = reputation.disputes_partial.saturating_add(1);
+        }
+        Some(_) => {
+            reputation.disputes_lost = reputation.disputes_lost.saturating_add(1);
+        }
+        None => {}
+    }
 
-        require!(ctx.accounts.admin.key() == registry.admin, MitamaError::Unauthorized);
-        require!(registry.oracles.len() < MAX_ORACLES, MitamaError::MaxOraclesReached);
-        require!(weight > 0, MitamaError::InvalidOracleWeight);
-        require!(
-            !registry.oracles.iter().any(|o| o.pubkey == oracle_pubkey),
-            MitamaError::DuplicateOracleSubmission
-        );
+    reputation.reputation_score = calculate_reputation_score(reputation);
+    reputation.last_updated = clock.unix_timestamp;
+    Ok(())
+}
 
-        registry.oracles.push(OracleConfig {
-            pubkey: oracle_pubkey,
-            oracle_type,
-            weight,
-        });
+/// Decode an account still in the layout of an earlier release. Accounts
+/// already `space` bytes long were created or migrated under this one.
+fn load_legacy_account<T: AnchorDeserialize>(
+    account: &AccountInfo,
+    discriminator: &[u8],
+    space: usize,
+) -> Result<T> {
+    require!(account.data_len() < space, MitamaError::AlreadyMigrated);
 
-        let clock = Clock::get()?;
-        registry.updated_at = clock.unix_timestamp;
+    let data = account.try_borrow_data()?;
+    require!(
+        data.starts_with(discriminator),
+        anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+    );
+    T::deserialize(&mut &data[discriminator.len()..])
+        .map_err(|_| anchor_lang::error::ErrorCode::AccountDidNotDeserialize.into())
+}
 
-        emit!(OracleAdded {
-            registry: registry.key(),
-            oracle: oracle_pubkey,
-            oracle_type_index: match oracle_type {
-                OracleType::Ed25519 => 0,
-                OracleType::Switchboard => 1,
-                OracleType::Custom => 2,
+/// Grow a legacy account to `space` bytes, with the payer topping up rent,
+/// and write its migrated contents over the old layout
+fn store_migrated<'info, T: AccountSerialize>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    space: usize,
+    migrated: &T,
+) -> Result<()> {
+    let shortfall = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account.lamports());
+    if shortfall > 0 {
+        let cpi_context = CpiContext::new(
+            system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: payer.to_account_info(),
+                to: account.clone(),
             },
-            weight,
-        });
-
-        Ok(())
+        );
+        anchor_lang::system_program::transfer(cpi_context, shortfall)?;
     }
 
-    /// Remove an oracle from the registry
-    pub fn remove_oracle(
-        ctx: Context<ManageOracle>,
-        oracle_pubkey: Pubkey,
-    ) -> Result<()> {
-        let registry = &mut ctx.accounts.oracle_registry;
-
-        require!(ctx.accounts.admin.key() == registry.admin, MitamaError::Unauthorized);
+    account.resize(space)?;
+    migrated.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])
+}
 
-        let initial_len = registry.oracles.len();
-        registry.oracles.retain(|o| o.pubkey != oracle_pubkey);
+/// Create and fund a single-amount escrow, optionally in streaming mode
+fn open_escrow(
+    ctx: Context<InitializeEscrow>,
+    amount: u64,
+    time_lock: i64,
+    transaction_id: String,
+    use_spl_token: bool,
+    streaming: bool,
+) -> Result<()> {
+    require!(amount > 0, MitamaError::InvalidAmount);
+    require!(
+        (MIN_TIME_LOCK..=MAX_TIME_LOCK).contains(&time_lock),
+        MitamaError::InvalidTimeLock
+    );
+    require!(
+        !transaction_id.is_empty() && transaction_id.len() <= 64,
+        MitamaError::InvalidTransactionId
+    );
 
-        require!(registry.oracles.len() < initial_len, MitamaError::OracleNotFound);
+    require!(ctx.accounts.protocol_config.is_active, MitamaError::ProtocolNotActive);
 
-        let clock = Clock::get()?;
-        registry.updated_at = clock.unix_timestamp;
+    let clock = Clock::get()?;
+    let escrow = &mut ctx.accounts.escrow;
+
+    escrow.agent = ctx.accounts.agent.key();
+    escrow.api = ctx.accounts.api.key();
+    escrow.amount = amount;
+    escrow.status = EscrowStatus::Active;
+    escrow.created_at = clock.unix_timestamp;
+    escrow.expires_at = clock.unix_timestamp + time_lock;
+    escrow.transaction_id = transaction_id.clone();
+    escrow.bump = ctx.bumps.escrow;
+    escrow.quality_score = None;
+    escrow.refund_percentage = None;
+    escrow.oracle_submissions = Vec::new();
+    escrow.milestones = Vec::new();
+    escrow.streaming = streaming;
+    escrow.withdrawn = 0;
+    escrow.disputed_at = 0;
+    escrow.appealed = false;
+    escrow.settles_at = 0;
+    escrow.agent_identity =
+        bind_agent_identity(ctx.accounts.agent_identity.as_mut(), clock.unix_timestamp)?;
+    escrow.switchboard_feed = match ctx.accounts.switchboard_feed.as_ref() {
+        Some(feed) => {
+            let registry = ctx.accounts.oracle_registry.as_ref()
+                .ok_or(MitamaError::UnregisteredSwitchboardFeed)?;
+            require!(
+                registry.is_registered_as(&feed.key(), OracleType::Switchboard),
+                MitamaError::UnregisteredSwitchboardFeed
+            );
+            validate_switchboard_feed(feed)?;
+            Some(feed.key())
+        }
+        None => None,
+    };
 
-        emit!(OracleRemoved {
-            registry: registry.key(),
-            oracle: oracle_pubkey,
-        });
+    if use_spl_token {
+        let token_mint = ctx.accounts.token_mint.as_ref()
+            .ok_or(MitamaError::MissingTokenMint)?;
+        let escrow_token_account = ctx.accounts.escrow_token_account.as_mut()
+            .ok_or(MitamaError::MissingTokenAccount)?;
+        let agent_token_account = ctx.accounts.agent_token_account.as_ref()
+            .ok_or(MitamaError::MissingTokenAccount)?;
+        let token_program = ctx.accounts.token_program.as_ref()
+            .ok_or(MitamaError::MissingTokenProgram)?;
+
+        escrow.token_mint = Some(token_mint.key());
+        escrow.escrow_token_account = Some(escrow_token_account.key());
+        escrow.token_decimals = token_mint.decimals;
+
+        // Transfer-fee mints deliver less than was sent; escrow what actually arrived
+        escrow.amount = deposit_escrow_tokens(
+            &ctx.accounts.agent,
+            token_mint,
+            agent_token_account,
+            escrow_token_account,
+            token_program,
+            amount,
+        )?;
+    } else {
+        escrow.token_mint = None;
+        escrow.escrow_token_account = None;
+        escrow.token_decimals = 9;
 
-        Ok(())
+        deposit_escrow_lamports(&ctx.accounts.agent, &escrow.to_account_info(), amount)?;
     }
 
+    collect_agreement_fee(
+        &mut ctx.accounts.protocol_config,
+        &ctx.accounts.agent,
+        &ctx.accounts.fee_vault,
+        amount,
+    )?;
+
+    emit!(EscrowInitialized {
+        escrow: escrow.key(),
+        agent: escrow.agent,
+        api: escrow.api,
+        amount: escrow.amount,
+        expires_at: escrow.expires_at,
+        transaction_id,
+        is_token: use_spl_token,
+        token_mint: escrow.token_mint,
+    });
+
+    Ok(())
+}
+
+// ============================================================================
+// Program
+// ============================================================================
+
+#[program]
+pub mod mitama {
+    use super::*;
+
     // ========================================================================
-    // Reputation Instructions
+    // Agent Identity Instructions
     // ========================================================================
 
-    /// Initialize entity reputation
-    pub fn init_reputation(ctx: Context<InitReputation>) -> Result<()> {
-        let reputation = &mut ctx.accounts.reputation;
+    /// Create a new agent identity with PDA
+    pub fn create_agent(
+        ctx: Context<CreateAgent>,
+        name: String,
+        agent_type: AgentType,
+        stake_amount: u64,
+    ) -> Result<()> {
+        require!(
+            !name.is_empty() && name.len() <= MAX_AGENT_NAME_LENGTH,
+            MitamaError::InvalidAgentName
+        );
+        require!(
+            stake_amount >= MIN_STAKE_AMOUNT,
+            MitamaError::InsufficientStake
+        );
+
+        let protocol_config = &mut ctx.accounts.protocol_config;
+        require!(protocol_config.is_active, MitamaError::ProtocolNotActive);
+
+        let identity_fee = protocol_config.identity_fee;
+
         let clock = Clock::get()?;
+        let agent = &mut ctx.accounts.agent;
 
-        reputation.entity = ctx.accounts.entity.key();
-        reputation.entity_type = EntityType::Agent;
-        reputation.total_transactions = 0;
-        reputation.disputes_filed = 0;
-        reputation.disputes_won = 0;
-        reputation.disputes_partial = 0;
-        reputation.disputes_lost = 0;
-        reputation.average_quality_received = 0;
-        reputation.reputation_score = 500;
-        reputation.created_at = clock.unix_timestamp;
-        reputation.last_updated = clock.unix_timestamp;
-        reputation.bump = ctx.bumps.reputation;
+        agent.owner = ctx.accounts.owner.key();
+        agent.name = name.clone();
+        agent.agent_type = agent_type;
+        agent.reputation = 500;
+        agent.stake_amount = stake_amount;
+        agent.is_active = true;
+        agent.created_at = clock.unix_timestamp;
+        agent.last_active = clock.unix_timestamp;
+        agent.total_escrows = 0;
+        agent.successful_escrows = 0;
+        agent.disputed_escrows = 0;
+        agent.bump = ctx.bumps.agent;
+        agent.version = ACCOUNT_VERSION;
+        agent.pending_unstake = 0;
+        agent.unstake_available_at = 0;
+        agent.open_disputes = 0;
+
+        // Transfer stake to agent PDA
+        let transfer_ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.owner.key(),
+            &agent.key(),
+            stake_amount,
+        );
+        anchor_lang::solana_program::program::invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.owner.to_account_info(),
+                agent.to_account_info(),
+            ],
+        )?;
+
+        // Collect identity creation fee
+        if identity_fee > 0 {
+            let fee_ix = anchor_lang::solana_program::system_instruction::transfer(
+                &ctx.accounts.owner.key(),
+                &ctx.accounts.fee_vault.key(),
+                identity_fee,
+            );
+            anchor_lang::solana_program::program::invoke(
+                &fee_ix,
+                &[
+                    ctx.accounts.owner.to_account_info(),
+                    ctx.accounts.fee_vault.to_account_info(),
+                ],
+            )?;
+
+            protocol_config.total_fees_collected = protocol_config
+                .total_fees_collected
+                .saturating_add(identity_fee);
+
+            emit!(ProtocolFeeCollected {
+                fee_type: "identity".to_string(),
+                amount: identity_fee,
+                payer: ctx.accounts.owner.key(),
+                treasury: protocol_config.treasury,
+            });
+        }
+
+        emit!(AgentCreated {
+            agent_pda: agent.key(),
+            owner: agent.owner,
+            name,
+            agent_type: agent_type as u8,
+            stake_amount,
+        });
 
         Ok(())
     }
 
-    // ========================================================================
-    // Protocol Config Instructions
-    // ========================================================================
+    /// Bring an agent identity from an earlier release up to the current
+    /// layout, with no stake cooling down and no open disputes. The owner
+    /// pays the extra rent.
+    pub fn migrate_agent_identity(ctx: Context<MigrateAgentIdentity>) -> Result<()> {
+        let info = ctx.accounts.agent.to_account_info();
+        let space = 8 + AgentIdentity::INIT_SPACE;
+        let legacy: LegacyAgentIdentity =
+            load_legacy_account(&info, AgentIdentity::DISCRIMINATOR, space)?;
+
+        let agent = AgentIdentity {
+            owner: legacy.owner,
+            name: legacy.name,
+            agent_type: legacy.agent_type,
+            reputation: legacy.reputation,
+            stake_amount: legacy.stake_amount,
+            is_active: legacy.is_active,
+            created_at: legacy.created_at,
+            last_active: legacy.last_active,
+            total_escrows: legacy.total_escrows,
+            successful_escrows: legacy.successful_escrows,
+            disputed_escrows: legacy.disputed_escrows,
+            bump: legacy.bump,
+            version: ACCOUNT_VERSION,
+            pending_unstake: 0,
+            unstake_available_at: 0,
+            open_disputes: 0,
+        };
+        store_migrated(
+            &info,
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            space,
+            &agent,
+        )?;
+
+        emit!(AccountMigrated {
+            account: info.key(),
+            version: ACCOUNT_VERSION,
+        });
+
+        Ok(())
+    }
+
+    /// Deactivate agent and return stake
+    pub fn deactivate_agent(ctx: Context<DeactivateAgent>) -> Result<()> {
+        let agent = &mut ctx.accounts.agent;
+
+        require!(
+            ctx.accounts.owner.key() == agent.owner,
+            MitamaError::Unauthorized
+        );
+        // A slashed-out agent keeps its residual stake until its disputes settle
+        require!(
+            agent.is_active || agent.stake_amount > 0,
+            MitamaError::AgentNotActive
+        );
+        require!(agent.open_disputes == 0, MitamaError::AgentHasOpenDisputes);
+
+        let stake_to_return = agent.stake_amount;
+
+        // Transfer stake back to owner
+        **agent.to_account_info().try_borrow_mut_lamports()? -= stake_to_return;
+        **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? += stake_to_return;
+
+        agent.is_active = false;
+        agent.stake_amount = 0;
+        agent.pending_unstake = 0;
+        agent.unstake_available_at = 0;
+
+        emit!(AgentDeactivated {
+            agent_pda: agent.key(),
+            owner: agent.owner,
+            refunded_stake: stake_to_return,
+        });
+
+        Ok(())
+    }
+
+    /// Add to an active agent's stake
+    pub fn increase_stake(ctx: Context<IncreaseStake>, amount: u64) -> Result<()> {
+        require!(amount > 0, MitamaError::InvalidAmount);
+        require!(ctx.accounts.agent.is_active, MitamaError::AgentNotActive);
+
+        deposit_escrow_lamports(
+            &ctx.accounts.owner,
+            &ctx.accounts.agent.to_account_info(),
+            amount,
+        )?;
+
+        let agent = &mut ctx.accounts.agent;
+        agent.stake_amount = agent.stake_amount
+            .checked_add(amount)
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+        agent.last_active = Clock::get()?.unix_timestamp;
+
+        emit!(AgentStakeIncreased {
+            agent_pda: agent.key(),
+            owner: agent.owner,
+            amount,
+            stake_amount: agent.stake_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Start the cooldown to withdraw part of an agent's stake. The amount
+    /// stays staked, and slashable, until `complete_unstake`. A new request
+    /// replaces any pending one and restarts the cooldown.
+    pub fn request_unstake(ctx: Context<ManageAgentStake>, amount: u64) -> Result<()> {
+        let agent = &mut ctx.accounts.agent;
+
+        require!(agent.is_active, MitamaError::AgentNotActive);
+        require!(amount > 0, MitamaError::InvalidAmount);
+        require!(
+            agent.stake_amount.saturating_sub(amount) >= MIN_STAKE_AMOUNT,
+            MitamaError::InsufficientStake
+        );
 
-    /// Initialize protocol configuration with fee parameters
-    pub fn initialize_protocol_config(
-        ctx: Context<InitializeProtocolConfig>,
-        treasury: Pubkey,
-    ) -> Result<()> {
-        let config = &mut ctx.accounts.protocol_config;
         let clock = Clock::get()?;
+        agent.pending_unstake = amount;
+        agent.unstake_available_at = clock.unix_timestamp + AGENT_UNSTAKE_COOLDOWN;
+        agent.last_active = clock.unix_timestamp;
 
-        config.admin = ctx.accounts.admin.key();
-        config.treasury = treasury;
-        config.agreement_fee_bps = DEFAULT_AGREEMENT_FEE_BPS;
-        config.dispute_fee_bps = DEFAULT_DISPUTE_FEE_BPS;
-        config.dispute_base_fee = DEFAULT_DISPUTE_BASE_FEE;
-        config.identity_fee = DEFAULT_IDENTITY_FEE;
-        config.total_fees_collected = 0;
-        config.is_active = true;
-        config.created_at = clock.unix_timestamp;
-        config.updated_at = clock.unix_timestamp;
-        config.bump = ctx.bumps.protocol_config;
+        emit!(AgentUnstakeRequested {
+            agent_pda: agent.key(),
+            owner: agent.owner,
+            amount,
+            available_at: agent.unstake_available_at,
+        });
 
-        emit!(ProtocolConfigInitialized {
-            config: config.key(),
-            admin: config.admin,
-            treasury: config.treasury,
+        Ok(())
+    }
+
+    /// Withdraw a requested unstake once its cooldown has passed and the agent
+    /// has no open disputes. Slashes taken during the cooldown come out of
+    /// the withdrawal first, so the agent keeps the minimum stake.
+    pub fn complete_unstake(ctx: Context<ManageAgentStake>) -> Result<()> {
+        let agent = &mut ctx.accounts.agent;
+
+        require!(agent.is_active, MitamaError::AgentNotActive);
+        require!(agent.pending_unstake > 0, MitamaError::NoPendingUnstake);
+        require!(agent.open_disputes == 0, MitamaError::AgentHasOpenDisputes);
+
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp >= agent.unstake_available_at,
+            MitamaError::UnstakeCooldownActive
+        );
+
+        let amount = agent.pending_unstake
+            .min(agent.stake_amount.saturating_sub(MIN_STAKE_AMOUNT));
+
+        **agent.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? += amount;
+
+        agent.stake_amount -= amount;
+        agent.pending_unstake = 0;
+        agent.unstake_available_at = 0;
+        agent.last_active = clock.unix_timestamp;
+
+        emit!(AgentUnstaked {
+            agent_pda: agent.key(),
+            owner: agent.owner,
+            amount,
+            stake_amount: agent.stake_amount,
         });
 
         Ok(())
     }
 
-    /// Update protocol fee parameters (admin only)
-    pub fn update_protocol_config(
-        ctx: Context<UpdateProtocolConfig>,
-        new_treasury: Option<Pubkey>,
-        new_agreement_fee_bps: Option<u16>,
-        new_dispute_fee_bps: Option<u16>,
-        new_dispute_base_fee: Option<u64>,
-        new_identity_fee: Option<u64>,
+    /// Adjust an agent's reputation (reputation authority only)
+    pub fn update_agent_rep(
+        ctx: Context<UpdateAgentRep>,
+        delta: i64,
+        reason: ReputationUpdateReason,
     ) -> Result<()> {
-        let config = &mut ctx.accounts.protocol_config;
-
         require!(
-            ctx.accounts.admin.key() == config.admin,
+            ctx.accounts.authority.key() == ctx.accounts.protocol_config.reputation_authority,
             MitamaError::Unauthorized
         );
 
-        if let Some(treasury) = new_treasury {
-            config.treasury = treasury;
-        }
+        let agent = &mut ctx.accounts.agent;
+        let old_rep = agent.reputation;
 
-        if let Some(fee_bps) = new_agreement_fee_bps {
-            require!(fee_bps <= MAX_FEE_BPS, MitamaError::FeeTooHigh);
-            config.agreement_fee_bps = fee_bps;
+        if delta >= 0 {
+            agent.reputation = agent.reputation.saturating_add(delta.unsigned_abs());
+        } else {
+            agent.reputation = agent.reputation.saturating_sub(delta.unsigned_abs());
         }
 
-        if let Some(fee_bps) = new_dispute_fee_bps {
-            require!(fee_bps <= MAX_FEE_BPS, MitamaError::FeeTooHigh);
-            config.dispute_fee_bps = fee_bps;
-        }
+        // Clamp to 0-1000
+        agent.reputation = agent.reputation.min(1000);
 
-        if let Some(base_fee) = new_dispute_base_fee {
-            config.dispute_base_fee = base_fee;
-        }
+        let clock = Clock::get()?;
+        agent.last_active = clock.unix_timestamp;
+
+        emit!(AgentReputationUpdated {
+            agent_pda: agent.key(),
+            authority: ctx.accounts.authority.key(),
+            old_reputation: old_rep,
+            new_reputation: agent.reputation,
+            delta,
+            reason,
+        });
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // Escrow Instructions
+    // ========================================================================
+
+    /// Initialize a new escrow for agent-to-API payment
+    pub fn initialize_escrow(
+        ctx: Context<InitializeEscrow>,
+        amount: u64,
+        time_lock: i64,
+        transaction_id: String,
+        use_spl_token: bool,
+    ) -> Result<()> {
+        open_escrow(ctx, amount, time_lock, transaction_id, use_spl_token, false)
+    }
+
+    /// Initialize a streaming escrow whose amount vests to the API linearly
+    /// between creation and expiry, withdrawable as it vests
+    pub fn initialize_streaming_escrow(
+        ctx: Context<InitializeEscrow>,
+        amount: u64,
+        time_lock: i64,
+        transaction_id: String,
+        use_spl_token: bool,
+    ) -> Result<()> {
+        open_escrow(ctx, amount, time_lock, transaction_id, use_spl_token, true)
+    }
+
+    /// Release funds to API (happy path)
+    pub fn release_funds(ctx: Context<ReleaseFunds>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        let (status, agent_key, expires_at, transfer_amount) = {
+            let escrow = &ctx.accounts.escrow;
+            require!(escrow.milestones.is_empty(), MitamaError::MilestoneEscrow);
+            (
+                escrow.status,
+                escrow.agent,
+                escrow.expires_at,
+                escrow.amount
+                    .checked_sub(escrow.withdrawn)
+                    .ok_or(MitamaError::ArithmeticOverflow)?,
+            )
+        };
+
+        require!(status == EscrowStatus::Active, MitamaError::InvalidStatus);
+
+        let is_agent = ctx.accounts.agent.key() == agent_key;
+        let time_lock_expired = clock.unix_timestamp >= expires_at;
+
+        if !is_agent {
+            require!(time_lock_expired, MitamaError::TimeLockNotExpired);
+        }
+
+        require!(is_agent || time_lock_expired, MitamaError::Unauthorized);
+
+        let rent_recipient = if is_agent {
+            Some(ctx.accounts.agent.to_account_info())
+        } else {
+            ctx.accounts.agent_wallet.as_ref().map(|wallet| wallet.to_account_info())
+        };
+
+        pay_out_escrow(
+            &ctx.accounts.escrow,
+            &ctx.accounts.agent.to_account_info(),
+            &ctx.accounts.api.to_account_info(),
+            EscrowTokenAccounts {
+                token_mint: ctx.accounts.token_mint.as_ref(),
+                escrow_token_account: ctx.accounts.escrow_token_account.as_ref(),
+                agent_token_account: None,
+                api_token_account: ctx.accounts.api_token_account.as_ref(),
+                token_program: ctx.accounts.token_program.as_ref(),
+            },
+            rent_recipient,
+            0,
+            transfer_amount,
+        )?;
+
+        ctx.accounts.escrow.status = EscrowStatus::Released;
+        record_agent_activity(
+            &ctx.accounts.escrow,
+            ctx.accounts.agent_identity.as_mut(),
+            AgentActivity::Released,
+        )?;
+        record_release(
+            ctx.accounts.agent_reputation.as_mut(),
+            ctx.accounts.api_reputation.as_mut(),
+        )?;
+
+        let escrow = &ctx.accounts.escrow;
+        emit!(FundsReleased {
+            escrow: escrow.key(),
+            transaction_id: escrow.transaction_id.clone(),
+            amount: escrow.amount,
+            api: escrow.api,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw the vested, not yet withdrawn portion of a streaming escrow to
+    /// the API. Vesting stops when the escrow is disputed.
+    pub fn withdraw_vested(ctx: Context<WithdrawVested>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        let (status, withdrawable) = {
+            let escrow = &ctx.accounts.escrow;
+            require!(escrow.streaming, MitamaError::NotStreamingEscrow);
+            require!(
+                escrow.status == EscrowStatus::Active || escrow.status == EscrowStatus::Disputed,
+                MitamaError::InvalidStatus
+            );
+            (
+                escrow.status,
+                escrow.vested_amount(clock.unix_timestamp)?
+                    .checked_sub(escrow.withdrawn)
+                    .ok_or(MitamaError::ArithmeticOverflow)?,
+            )
+        };
+
+        require!(withdrawable > 0, MitamaError::NothingVested);
+
+        let tokens = EscrowTokenAccounts {
+            token_mint: ctx.accounts.token_mint.as_ref(),
+            escrow_token_account: ctx.accounts.escrow_token_account.as_ref(),
+            agent_token_account: None,
+            api_token_account: ctx.accounts.api_token_account.as_ref(),
+            token_program: ctx.accounts.token_program.as_ref(),
+        };
+
+        transfer_escrow_payout(
+            &ctx.accounts.escrow,
+            &ctx.accounts.agent.to_account_info(),
+            &ctx.accounts.api.to_account_info(),
+            tokens,
+            0,
+            withdrawable,
+        )?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.withdrawn = escrow.withdrawn
+            .checked_add(withdrawable)
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+
+        // A fully vested and withdrawn stream is settled
+        if status == EscrowStatus::Active && escrow.withdrawn == escrow.amount {
+            escrow.status = EscrowStatus::Released;
+            close_settled_vault(
+                &ctx.accounts.escrow,
+                tokens,
+                Some(ctx.accounts.agent.to_account_info()),
+            )?;
+            record_agent_activity(
+                &ctx.accounts.escrow,
+                ctx.accounts.agent_identity.as_mut(),
+                AgentActivity::Released,
+            )?;
+            record_release(
+                ctx.accounts.agent_reputation.as_mut(),
+                ctx.accounts.api_reputation.as_mut(),
+            )?;
+        }
+
+        let escrow = &ctx.accounts.escrow;
+        emit!(VestedFundsWithdrawn {
+            escrow: escrow.key(),
+            transaction_id: escrow.transaction_id.clone(),
+            amount: withdrawable,
+            total_withdrawn: escrow.withdrawn,
+            api: escrow.api,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Mark escrow as disputed
+    pub fn mark_disputed(ctx: Context<MarkDisputed>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let reputation = &mut ctx.accounts.reputation;
+
+        require!(ctx.accounts.protocol_config.is_active, MitamaError::ProtocolNotActive);
+        require!(escrow.status == EscrowStatus::Active, MitamaError::InvalidStatus);
+        require!(escrow.milestones.is_empty(), MitamaError::MilestoneEscrow);
+        require!(ctx.accounts.agent.key() == escrow.agent, MitamaError::Unauthorized);
+
+        let clock = Clock::get()?;
+        require!(clock.unix_timestamp < escrow.expires_at, MitamaError::DisputeWindowExpired);
+
+        // Streaming escrows only dispute what has not vested yet
+        let disputed_amount = escrow.amount
+            .checked_sub(escrow.vested_amount(clock.unix_timestamp)?)
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+
+        let oracle_reward_pool = collect_dispute_fee(
+            &mut ctx.accounts.protocol_config,
+            reputation,
+            &ctx.accounts.agent,
+            &ctx.accounts.fee_vault,
+            &ctx.accounts.dispute_record.to_account_info(),
+            disputed_amount,
+        )?;
+
+        reputation.disputes_filed = reputation.disputes_filed.saturating_add(1);
+        escrow.status = EscrowStatus::Disputed;
+        escrow.disputed_at = clock.unix_timestamp;
+        record_agent_activity(
+            escrow,
+            ctx.accounts.agent_identity.as_mut(),
+            AgentActivity::Disputed,
+        )?;
+
+        open_dispute_record(
+            &mut ctx.accounts.dispute_record,
+            escrow,
+            escrow.key(),
+            None,
+            &mut ctx.accounts.protocol_config,
+            clock.unix_timestamp,
+            ctx.bumps.dispute_record,
+        );
+        ctx.accounts.dispute_record.oracle_reward_pool = oracle_reward_pool;
+
+        emit!(DisputeMarked {
+            escrow: escrow.key(),
+            agent: escrow.agent,
+            transaction_id: escrow.transaction_id.clone(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Resolve dispute with a staked verifier oracle's signature. Only
+    /// available until oracles commit scores; see `check_single_verifier`.
+    pub fn resolve_dispute(
+        ctx: Context<ResolveDispute>,
+        quality_score: u8,
+        refund_percentage: u8,
+        expires_at: i64,
+        signature: [u8; 64],
+    ) -> Result<()> {
+        // Extract values we need before mutating
+        let (status, transaction_id, escrow_key) = {
+            let escrow = &ctx.accounts.escrow;
+            require!(escrow.milestones.is_empty(), MitamaError::MilestoneEscrow);
+            require!(!escrow.appealed, MitamaError::AppealRequiresQuorum);
+            (
+                escrow.status,
+                escrow.transaction_id.clone(),
+                escrow.key(),
+            )
+        };
+
+        require!(status == EscrowStatus::Disputed, MitamaError::InvalidStatus);
+        require!(quality_score <= 100, MitamaError::InvalidQualityScore);
+        require!(refund_percentage <= 100, MitamaError::InvalidRefundPercentage);
+
+        let dispute_record = &ctx.accounts.dispute_record;
+        require!(
+            dispute_record.milestone_index.is_none(),
+            MitamaError::DisputeRecordMismatch
+        );
+        require!(
+            dispute_record.hearing_closed(Clock::get()?.unix_timestamp),
+            MitamaError::EvidenceWindowOpen
+        );
+        check_single_verifier(
+            &ctx.accounts.oracle_registry,
+            dispute_record,
+            ctx.accounts.verifier.key,
+            &ctx.accounts.verifier_stake,
+            ctx.accounts.protocol_config.min_oracle_stake,
+        )?;
+
+        let attestation = OracleAttestation::for_dispute(
+            &ctx.accounts.oracle_registry,
+            dispute_record,
+            quality_score,
+            refund_percentage,
+            expires_at,
+        );
+        verify_oracle_attestation(
+            &ctx.accounts.instructions_sysvar,
+            &signature,
+            ctx.accounts.verifier.key,
+            &attestation,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        // The refund always comes from the protocol curve; strict mode also
+        // rejects rulings whose signed refund disagrees with it
+        let config = &ctx.accounts.protocol_config;
+        let curve_refund = config.refund_for_quality(quality_score);
+        require!(
+            !config.strict_refunds || refund_percentage == curve_refund,
+            MitamaError::RefundMismatch
+        );
+        let refund_percentage = curve_refund;
+
+        if hold_for_appeal(
+            &mut ctx.accounts.escrow,
+            &ctx.accounts.oracle_registry,
+            quality_score,
+            refund_percentage,
+        )? {
+            return Ok(());
+        }
+
+        let (refund_amount, payment_amount) = settle_dispute_outcome(
+            DisputeSettlement {
+                escrow: &mut ctx.accounts.escrow,
+                agent: ctx.accounts.agent.to_account_info(),
+                api: ctx.accounts.api.to_account_info(),
+                tokens: EscrowTokenAccounts {
+                    token_mint: ctx.accounts.token_mint.as_ref(),
+                    escrow_token_account: ctx.accounts.escrow_token_account.as_ref(),
+                    agent_token_account: ctx.accounts.agent_token_account.as_ref(),
+                    api_token_account: ctx.accounts.api_token_account.as_ref(),
+                    token_program: ctx.accounts.token_program.as_ref(),
+                },
+                agent_reputation: &mut ctx.accounts.agent_reputation,
+                api_reputation: &mut ctx.accounts.api_reputation,
+                agent_identity: ctx.accounts.agent_identity.as_mut(),
+                protocol_config: &ctx.accounts.protocol_config,
+                fee_vault: Some(&ctx.accounts.fee_vault),
+            },
+            None,
+            Some(quality_score),
+            refund_percentage,
+        )?;
+
+        emit!(DisputeResolved {
+            escrow: escrow_key,
+            transaction_id,
+            quality_score,
+            refund_percentage,
+            refund_amount,
+            payment_amount,
+            verifier: ctx.accounts.verifier.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Submit an evidence commitment (content hash plus URI) to an open
+    /// dispute. Both the agent and the API may respond until the response
+    /// window closes or oracles start scoring.
+    pub fn submit_evidence(
+        ctx: Context<SubmitEvidence>,
+        content_hash: [u8; 32],
+        uri: String,
+    ) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        let dispute_record = &mut ctx.accounts.dispute_record;
+        let submitter = ctx.accounts.submitter.key();
+
+        require!(
+            submitter == dispute_record.agent || submitter == dispute_record.api,
+            MitamaError::Unauthorized
+        );
+        require!(uri.len() <= MAX_EVIDENCE_URI_LENGTH, MitamaError::InvalidEvidence);
+
+        let still_disputed = match dispute_record.milestone_index {
+            Some(index) => escrow.milestones
+                .get(index as usize)
+                .is_some_and(|m| m.status == EscrowStatus::Disputed),
+            None => escrow.status == EscrowStatus::Disputed,
+        };
+        require!(still_disputed, MitamaError::InvalidStatus);
+
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp < dispute_record.response_deadline
+                && escrow.oracle_submissions.is_empty(),
+            MitamaError::EvidenceWindowClosed
+        );
+        // Oracles score the evidence set as it stands when the hearing closes
+        require!(
+            !dispute_record.hearing_closed(clock.unix_timestamp)
+                && clock.unix_timestamp < dispute_record.scoring_opens_at
+                && dispute_record.commitments.is_empty(),
+            MitamaError::HearingClosed
+        );
+        require!(
+            dispute_record.evidence.iter().filter(|e| e.submitter == submitter).count()
+                < MAX_EVIDENCE_PER_PARTY,
+            MitamaError::TooMuchEvidence
+        );
+
+        dispute_record.evidence.push(Evidence {
+            submitter,
+            content_hash,
+            uri: uri.clone(),
+            submitted_at: clock.unix_timestamp,
+        });
+
+        emit!(EvidenceSubmitted {
+            escrow: escrow.key(),
+            dispute_record: dispute_record.key(),
+            submitter,
+            content_hash,
+            uri,
+            evidence_hash: dispute_record.evidence_hash().to_bytes(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Close the evidence record of a settled dispute and return its rent to the agent
+    pub fn close_dispute_record(ctx: Context<CloseDisputeRecord>) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        let settled = match ctx.accounts.dispute_record.milestone_index {
+            Some(index) => escrow.milestones
+                .get(index as usize)
+                .is_some_and(|m| m.status == EscrowStatus::Resolved),
+            None => escrow.status == EscrowStatus::Resolved,
+        };
+        require!(settled, MitamaError::InvalidStatus);
+
+        // Oracle rewards nobody earned go to the protocol, not back to the agent
+        let record = &mut ctx.accounts.dispute_record;
+        let unclaimed = record.oracle_reward_pool;
+        if unclaimed > 0 {
+            **record.to_account_info().try_borrow_mut_lamports()? -= unclaimed;
+            **ctx.accounts.fee_vault.try_borrow_mut_lamports()? += unclaimed;
+            record.oracle_reward_pool = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Close a settled escrow and return its rent to the agent, closing its
+    /// dispute records too if any are still open. Remaining accounts are the
+    /// dispute records of every milestone that went through a dispute, in
+    /// milestone order; the escrow cannot close without them.
+    pub fn close_escrow<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseEscrow<'info>>,
+    ) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+
+        require!(
+            escrow.status == EscrowStatus::Released || escrow.status == EscrowStatus::Resolved,
+            MitamaError::InvalidStatus
+        );
+
+        let agent = ctx.accounts.agent.to_account_info();
+        close_open_dispute_record(
+            &ctx.accounts.dispute_record.to_account_info(),
+            &ctx.accounts.fee_vault,
+            &agent,
+        )?;
+
+        let disputed_milestones: Vec<u8> = escrow.milestones
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.status == EscrowStatus::Resolved)
+            .map(|(index, _)| index as u8)
+            .collect();
+        require!(
+            ctx.remaining_accounts.len() == disputed_milestones.len(),
+            MitamaError::DisputeRecordMismatch
+        );
+        for (record_info, index) in ctx.remaining_accounts.iter().zip(disputed_milestones) {
+            let (expected, _) = Pubkey::find_program_address(
+                &[b"dispute", escrow.key().as_ref(), &[index]],
+                &crate::ID,
+            );
+            require_keys_eq!(record_info.key(), expected, MitamaError::DisputeRecordMismatch);
+            close_open_dispute_record(record_info, &ctx.accounts.fee_vault, &agent)?;
+        }
+
+        let clock = Clock::get()?;
+
+        emit!(EscrowClosed {
+            escrow: escrow.key(),
+            agent: escrow.agent,
+            api: escrow.api,
+            transaction_id: escrow.transaction_id.clone(),
+            status: escrow.status,
+            amount: escrow.amount,
+            token_mint: escrow.token_mint,
+            quality_score: escrow.quality_score,
+            refund_percentage: escrow.refund_percentage,
+            created_at: escrow.created_at,
+            closed_at: clock.unix_timestamp,
+            rent_returned: escrow.to_account_info().lamports(),
+        });
+
+        Ok(())
+    }
+
+    /// Move an escrow created under the legacy `[b"escrow", transaction_id]`
+    /// seeds to its agent-namespaced address, carrying over state and funds.
+    /// Fields added since the legacy layout start empty.
+    pub fn migrate_legacy_escrow(
+        ctx: Context<MigrateLegacyEscrow>,
+        _transaction_id: String,
+    ) -> Result<()> {
+        let legacy_info = ctx.accounts.legacy_escrow.to_account_info();
+
+        // Escrows were only ever written at the legacy seeds in the legacy
+        // layout; later layouts live at the agent-namespaced seeds
+        require!(
+            legacy_info.data_len() == 8 + LegacyEscrow::INIT_SPACE,
+            anchor_lang::error::ErrorCode::AccountDidNotDeserialize
+        );
+        let legacy: LegacyEscrow = load_legacy_account(
+            &legacy_info,
+            Escrow::DISCRIMINATOR,
+            8 + Escrow::INIT_SPACE,
+        )?;
+        require!(legacy.agent == ctx.accounts.agent.key(), MitamaError::Unauthorized);
+
+        let is_open =
+            legacy.status == EscrowStatus::Active || legacy.status == EscrowStatus::Disputed;
+
+        let mut amount = legacy.amount;
+        let mut escrow_token_account = legacy.escrow_token_account;
+
+        if is_open && legacy.token_mint.is_some() {
+            let token_mint = ctx.accounts.token_mint.as_ref()
+                .ok_or(MitamaError::MissingTokenMint)?;
+            let legacy_token_account = ctx.accounts.legacy_token_account.as_ref()
+                .ok_or(MitamaError::MissingTokenAccount)?;
+            let vault = ctx.accounts.escrow_token_account.as_mut()
+                .ok_or(MitamaError::MissingTokenAccount)?;
+            let token_program = ctx.accounts.token_program.as_ref()
+                .ok_or(MitamaError::MissingTokenProgram)?;
+
+            require!(
+                legacy.token_mint == Some(token_mint.key()),
+                MitamaError::TokenMintMismatch
+            );
+            require!(
+                legacy.escrow_token_account == Some(legacy_token_account.key()),
+                MitamaError::InvalidTokenAccount
+            );
+
+            let legacy_seeds = &[
+                b"escrow".as_ref(),
+                legacy.transaction_id.as_bytes(),
+                &[legacy.bump],
+            ];
+            let signer = &[&legacy_seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                TransferChecked {
+                    from: legacy_token_account.to_account_info(),
+                    mint: token_mint.to_account_info(),
+                    to: vault.to_account_info(),
+                    authority: legacy_info.clone(),
+                },
+                signer,
+            );
+            token_interface::transfer_checked(
+                cpi_ctx,
+                legacy_token_account.amount,
+                legacy.token_decimals,
+            )?;
+
+            // Transfer fees may apply to the move itself
+            vault.reload()?;
+            amount = vault.amount;
+            escrow_token_account = Some(vault.key());
+
+            harvest_and_close_vault(
+                legacy_token_account.to_account_info(),
+                token_mint,
+                legacy_info.clone(),
+                signer,
+                ctx.accounts.agent.to_account_info(),
+                token_program,
+            )?;
+        } else if is_open {
+            **legacy_info.try_borrow_mut_lamports()? -= amount;
+            **ctx.accounts.escrow.to_account_info().try_borrow_mut_lamports()? += amount;
+        }
+
+        // The legacy account no longer deserializes as an Escrow, so it is
+        // closed by hand
+        let rent = legacy_info.lamports();
+        **legacy_info.try_borrow_mut_lamports()? = 0;
+        **ctx.accounts.agent.to_account_info().try_borrow_mut_lamports()? += rent;
+        legacy_info.assign(&System::id());
+        legacy_info.resize(0)?;
+
+        ctx.accounts.escrow.set_inner(Escrow {
+            agent: legacy.agent,
+            api: legacy.api,
+            amount,
+            status: legacy.status,
+            created_at: legacy.created_at,
+            expires_at: legacy.expires_at,
+            transaction_id: legacy.transaction_id,
+            bump: ctx.bumps.escrow,
+            quality_score: legacy.quality_score,
+            refund_percentage: legacy.refund_percentage,
+            oracle_submissions: legacy.oracle_submissions,
+            token_mint: legacy.token_mint,
+            escrow_token_account,
+            token_decimals: legacy.token_decimals,
+            switchboard_feed: None,
+            milestones: Vec::new(),
+            streaming: false,
+            withdrawn: 0,
+            disputed_at: 0,
+            appealed: false,
+            settles_at: 0,
+            agent_identity: None,
+        });
+        let escrow = &mut ctx.accounts.escrow;
+
+        // Legacy disputes predate dispute records and dispute timestamps;
+        // open a record dated to the migration so the resolve and timeout
+        // paths can settle it
+        if legacy.status == EscrowStatus::Disputed {
+            let record = ctx.accounts.dispute_record.as_mut()
+                .ok_or(MitamaError::MissingDisputeRecord)?;
+            let protocol_config = ctx.accounts.protocol_config.as_mut()
+                .ok_or(MitamaError::MissingDisputeRecord)?;
+            let opened_at = Clock::get()?.unix_timestamp;
+            escrow.disputed_at = opened_at;
+            open_dispute_record(
+                record,
+                escrow,
+                escrow.key(),
+                None,
+                protocol_config,
+                opened_at,
+                ctx.bumps.dispute_record.ok_or(MitamaError::MissingDisputeRecord)?,
+            );
+        }
+
+        emit!(EscrowMigrated {
+            legacy_escrow: legacy_info.key(),
+            escrow: escrow.key(),
+            agent: escrow.agent,
+            transaction_id: escrow.transaction_id.clone(),
+            status: escrow.status,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Commit to a quality score for a disputed escrow without revealing it.
+    /// `commitment` is `ScoreCommitment::hash` of the escrow, the oracle, the
+    /// score and a secret salt; it must be revealed once the commit window closes.
+    pub fn commit_oracle_score(
+        ctx: Context<CommitOracleScore>,
+        commitment: [u8; 32],
+    ) -> Result<()> {
+        let escrow = &ctx.accounts.escrow;
+        let registry = &ctx.accounts.oracle_registry;
+        let dispute_record = &mut ctx.accounts.dispute_record;
+        let oracle_key = ctx.accounts.oracle.key();
+
+        require!(escrow.status == EscrowStatus::Disputed, MitamaError::InvalidStatus);
+        require!(registry.is_registered(&oracle_key), MitamaError::UnregisteredOracle);
+        require!(
+            ctx.accounts.oracle_stake.amount >= ctx.accounts.protocol_config.min_oracle_stake,
+            MitamaError::InsufficientOracleStake
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(dispute_record.hearing_closed(now), MitamaError::EvidenceWindowOpen);
+        require!(
+            now < dispute_record.commit_deadline(registry),
+            MitamaError::CommitWindowClosed
+        );
+        require!(
+            !dispute_record.commitments.iter().any(|c| c.oracle == oracle_key),
+            MitamaError::DuplicateOracleSubmission
+        );
+        require!(
+            dispute_record.commitments.len() < MAX_ORACLES,
+            MitamaError::MaxOraclesReached
+        );
+
+        // The stake must stay bonded until this dispute can no longer be ruled
+        // on, and through the appeal window that holds the ruling's settlement
+        let stake = &mut ctx.accounts.oracle_stake;
+        let unlocks_at = dispute_record.resolution_deadline.saturating_add(registry.appeal_window);
+        stake.locked_until = stake.locked_until.max(unlocks_at);
+
+        dispute_record.commitments.push(ScoreCommitment {
+            oracle: oracle_key,
+            commitment,
+            committed_at: now,
+            revealed: false,
+        });
+
+        emit!(OracleScoreCommitted {
+            escrow: escrow.key(),
+            oracle: oracle_key,
+            commitment_count: dispute_record.commitments.len() as u8,
+        });
+
+        Ok(())
+    }
+
+    /// Reveal a committed quality score. The score must match the oracle's
+    /// commitment and carry its signed attestation; only revealed scores count
+    /// toward consensus.
+    pub fn reveal_oracle_score(
+        ctx: Context<RevealOracleScore>,
+        quality_score: u8,
+        salt: [u8; 32],
+        expires_at: i64,
+        signature: [u8; 64],
+    ) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let registry = &ctx.accounts.oracle_registry;
+        let oracle_key = ctx.accounts.oracle.key();
+
+        require!(escrow.status == EscrowStatus::Disputed, MitamaError::InvalidStatus);
+        require!(quality_score <= 100, MitamaError::InvalidQualityScore);
+        require!(registry.is_registered(&oracle_key), MitamaError::UnregisteredOracle);
+
+        let clock = Clock::get()?;
+        let dispute_record = &mut ctx.accounts.dispute_record;
+        require!(
+            clock.unix_timestamp >= dispute_record.commit_deadline(registry),
+            MitamaError::CommitWindowOpen
+        );
+        require!(
+            clock.unix_timestamp < dispute_record.reveal_deadline(registry),
+            MitamaError::RevealWindowClosed
+        );
+
+        let expected = ScoreCommitment::hash(&escrow.key(), &oracle_key, quality_score, &salt);
+        let commitment = dispute_record.commitments
+            .iter_mut()
+            .find(|c| c.oracle == oracle_key)
+            .ok_or(MitamaError::MissingCommitment)?;
+        require!(!commitment.revealed, MitamaError::DuplicateOracleSubmission);
+        require!(commitment.commitment == expected, MitamaError::CommitmentMismatch);
+        commitment.revealed = true;
+
+        let dispute_record = &ctx.accounts.dispute_record;
+        let attestation = OracleAttestation::for_dispute(
+            registry,
+            dispute_record,
+            quality_score,
+            ctx.accounts.protocol_config.refund_for_quality(quality_score),
+            expires_at,
+        );
+        verify_oracle_attestation(
+            &ctx.accounts.instructions_sysvar,
+            &signature,
+            &oracle_key,
+            &attestation,
+            clock.unix_timestamp,
+        )?;
+
+        escrow.oracle_submissions.push(OracleSubmission {
+            oracle: oracle_key,
+            quality_score,
+            submitted_at: clock.unix_timestamp,
+        });
+
+        emit!(OracleScoreSubmitted {
+            escrow: escrow.key(),
+            transaction_id: escrow.transaction_id.clone(),
+            oracle: oracle_key,
+            quality_score,
+            submission_count: escrow.oracle_submissions.len() as u8,
+        });
+
+        Ok(())
+    }
+
+    /// Settle a disputed escrow from the consensus of registered oracle reveals.
+    /// Once the ruling is final (appeals are disabled or this is the appeal
+    /// round), oracles near consensus share the dispute's reward pool, while
+    /// outliers and non-revealers are slashed. Remaining accounts are then the
+    /// counted oracles' stake accounts, in submission order, followed by the
+    /// first round's on an appeal, then those of oracles that committed but
+    /// never revealed, this round's before the first round's. A first-round
+    /// ruling held for appeal takes no stake accounts; its stakes settle in
+    /// `finalize_settlement` or in the appeal round.
+    pub fn finalize_multi_oracle_resolution<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FinalizeMultiOracleResolution<'info>>,
+    ) -> Result<()> {
+        let registry = &ctx.accounts.oracle_registry;
+
+        let (status, transaction_id, escrow_key) = {
+            let escrow = &ctx.accounts.escrow;
+            require!(escrow.milestones.is_empty(), MitamaError::MilestoneEscrow);
+            (
+                escrow.status,
+                escrow.transaction_id.clone(),
+                escrow.key(),
+            )
+        };
+
+        require!(status == EscrowStatus::Disputed, MitamaError::InvalidStatus);
+
+        // An appeal round needs the registry's larger appeal quorum
+        let appealed = ctx.accounts.escrow.appealed;
+        let required_consensus = if appealed {
+            registry.appeal_min_consensus
+        } else {
+            registry.min_consensus
+        };
+        require!(
+            !appealed || ctx.accounts.appeal.is_some(),
+            MitamaError::MissingAppeal
+        );
+
+        // Scoring closes once every committed oracle has revealed or the
+        // reveal window has passed
+        let dispute_record = &ctx.accounts.dispute_record;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= dispute_record.commit_deadline(registry),
+            MitamaError::CommitWindowOpen
+        );
+        let absent: Vec<Pubkey> = dispute_record.commitments
+            .iter()
+            .filter(|c| !c.revealed)
+            .map(|c| c.oracle)
+            .collect();
+        require!(
+            absent.is_empty() || now >= dispute_record.reveal_deadline(registry),
+            MitamaError::RevealWindowOpen
+        );
+
+        // Only count oracles that are still registered at finalization time
+        let submissions: Vec<OracleSubmission> = ctx.accounts.escrow.oracle_submissions
+            .iter()
+            .filter(|s| registry.is_registered(&s.oracle))
+            .cloned()
+            .collect();
+
+        require!(
+            submissions.len() >= required_consensus as usize,
+            MitamaError::InsufficientOracleConsensus
+        );
+
+        let individual_scores: Vec<u8> = submissions.iter().map(|s| s.quality_score).collect();
+        let oracles: Vec<Pubkey> = submissions.iter().map(|s| s.oracle).collect();
+        let registry_weights: Vec<u16> = submissions.iter()
+            .map(|s| registry.oracle_weight(&s.oracle))
+            .collect();
+
+        let (consensus_score, included) = match registry.consensus_mode {
+            ConsensusMode::Median => {
+                calculate_consensus_score(&individual_scores, registry.max_score_deviation)?
+            }
+            ConsensusMode::WeightedMedian => calculate_weighted_consensus_score(
+                &individual_scores,
+                &registry_weights,
+                registry.max_score_deviation,
+                false,
+            )?,
+            ConsensusMode::WeightedTrimmedMean => calculate_weighted_consensus_score(
+                &individual_scores,
+                &registry_weights,
+                registry.max_score_deviation,
+                true,
+            )?,
+        };
+
+        let refund_percentage = ctx.accounts.protocol_config.refund_for_quality(consensus_score);
+
+        // A ruling held for appeal is not final, so stakes settle later against
+        // whichever ruling stands. Keep the counted submissions for that.
+        if hold_for_appeal(
+            &mut ctx.accounts.escrow,
+            registry,
+            consensus_score,
+            refund_percentage,
+        )? {
+            ctx.accounts.escrow.oracle_submissions = submissions;
+            return Ok(());
+        }
+
+        // The appeal ruling also scores the first round it overrode
+        let mut counted = submissions.clone();
+        let mut missed = absent;
+        if let (true, Some(appeal)) = (appealed, ctx.accounts.appeal.as_ref()) {
+            counted.extend(appeal.first_round.iter().cloned());
+            missed.extend(appeal.first_round_absent.iter().copied());
+        }
+        require!(
+            ctx.remaining_accounts.len() == counted.len() + missed.len(),
+            MitamaError::OracleStakeMismatch
+        );
+        let (counted_stakes, missed_stakes) = ctx.remaining_accounts.split_at(counted.len());
+        slash_missing_reveals(
+            missed_stakes,
+            &missed,
+            escrow_key,
+            &ctx.accounts.fee_vault,
+            ctx.accounts.protocol_config.oracle_slash_bps,
+        )?;
+        settle_oracle_stakes(
+            counted_stakes,
+            &counted,
+            &mut ctx.accounts.dispute_record,
+            &ctx.accounts.fee_vault,
+            ctx.accounts.protocol_config.oracle_slash_bps,
+            consensus_score,
+            registry.max_score_deviation,
+        )?;
+
+        let (refund_amount, payment_amount) = settle_dispute_outcome(
+            DisputeSettlement {
+                escrow: &mut ctx.accounts.escrow,
+                agent: ctx.accounts.agent.to_account_info(),
+                api: ctx.accounts.api.to_account_info(),
+                tokens: EscrowTokenAccounts {
+                    token_mint: ctx.accounts.token_mint.as_ref(),
+                    escrow_token_account: ctx.accounts.escrow_token_account.as_ref(),
+                    agent_token_account: ctx.accounts.agent_token_account.as_ref(),
+                    api_token_account: ctx.accounts.api_token_account.as_ref(),
+                    token_program: ctx.accounts.token_program.as_ref(),
+                },
+                agent_reputation: &mut ctx.accounts.agent_reputation,
+                api_reputation: &mut ctx.accounts.api_reputation,
+                agent_identity: ctx.accounts.agent_identity.as_mut(),
+                protocol_config: &ctx.accounts.protocol_config,
+                fee_vault: Some(&ctx.accounts.fee_vault),
+            },
+            None,
+            Some(consensus_score),
+            refund_percentage,
+        )?;
+
+        emit!(MultiOracleDisputeResolved {
+            escrow: escrow_key,
+            transaction_id,
+            oracle_count: submissions.len() as u8,
+            individual_scores,
+            oracles,
+            weights: registry_weights,
+            included,
+            consensus_mode: registry.consensus_mode,
+            consensus_score,
+            refund_percentage,
+            refund_amount,
+            payment_amount,
+        });
+
+        // The appeal ruling overrides the first round; settle the bond against it
+        if let (true, Some(appeal)) = (appealed, ctx.accounts.appeal.as_ref()) {
+            let bond_refunded = settle_appeal_bond(
+                appeal,
+                &ctx.accounts.agent.to_account_info(),
+                &ctx.accounts.api.to_account_info(),
+                refund_percentage,
+            )?;
+
+            emit!(AppealResolved {
+                escrow: escrow_key,
+                appellant: appeal.appellant,
+                original_refund_percentage: appeal.original_refund_percentage,
+                refund_percentage,
+                bond: appeal.bond,
+                bond_refunded,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Resolve dispute from the Switchboard pull feed bound to the escrow
+    pub fn resolve_dispute_switchboard(ctx: Context<ResolveDisputeSwitchboard>) -> Result<()> {
+        let (status, transaction_id, escrow_key) = {
+            let escrow = &ctx.accounts.escrow;
+            require!(escrow.milestones.is_empty(), MitamaError::MilestoneEscrow);
+            require!(!escrow.appealed, MitamaError::AppealRequiresQuorum);
+            (
+                escrow.status,
+                escrow.transaction_id.clone(),
+                escrow.key(),
+            )
+        };
+
+        require!(status == EscrowStatus::Disputed, MitamaError::InvalidStatus);
+
+        let clock = Clock::get()?;
+        require!(
+            ctx.accounts.dispute_record.hearing_closed(clock.unix_timestamp),
+            MitamaError::EvidenceWindowOpen
+        );
+
+        let quality_score =
+            read_switchboard_quality_score(&ctx.accounts.switchboard_feed, clock.slot)?;
+        let refund_percentage = ctx.accounts.protocol_config.refund_for_quality(quality_score);
+
+        if hold_for_appeal(
+            &mut ctx.accounts.escrow,
+            &ctx.accounts.oracle_registry,
+            quality_score,
+            refund_percentage,
+        )? {
+            return Ok(());
+        }
+
+        let (refund_amount, payment_amount) = settle_dispute_outcome(
+            DisputeSettlement {
+                escrow: &mut ctx.accounts.escrow,
+                agent: ctx.accounts.agent.to_account_info(),
+                api: ctx.accounts.api.to_account_info(),
+                tokens: EscrowTokenAccounts {
+                    token_mint: ctx.accounts.token_mint.as_ref(),
+                    escrow_token_account: ctx.accounts.escrow_token_account.as_ref(),
+                    agent_token_account: ctx.accounts.agent_token_account.as_ref(),
+                    api_token_account: ctx.accounts.api_token_account.as_ref(),
+                    token_program: ctx.accounts.token_program.as_ref(),
+                },
+                agent_reputation: &mut ctx.accounts.agent_reputation,
+                api_reputation: &mut ctx.accounts.api_reputation,
+                agent_identity: ctx.accounts.agent_identity.as_mut(),
+                protocol_config: &ctx.accounts.protocol_config,
+                fee_vault: Some(&ctx.accounts.fee_vault),
+            },
+            None,
+            Some(quality_score),
+            refund_percentage,
+        )?;
+
+        emit!(SwitchboardDisputeResolved {
+            escrow: escrow_key,
+            transaction_id,
+            feed: ctx.accounts.switchboard_feed.key(),
+            quality_score,
+            refund_percentage,
+            refund_amount,
+            payment_amount,
+            slot: clock.slot,
+        });
+
+        Ok(())
+    }
+
+    /// Appeal a ruling held in `PendingSettlement`. The appellant posts the
+    /// registry's appeal bond and the dispute reopens for a second oracle
+    /// round that needs the larger appeal quorum; its ruling replaces the first.
+    pub fn file_appeal(ctx: Context<FileAppeal>) -> Result<()> {
+        let (status, settles_at, original_quality_score, original_refund_percentage) = {
+            let escrow = &ctx.accounts.escrow;
+            (
+                escrow.status,
+                escrow.settles_at,
+                escrow.quality_score,
+                escrow.refund_percentage,
+            )
+        };
+
+        require!(status == EscrowStatus::PendingSettlement, MitamaError::InvalidStatus);
+        let original_quality_score = original_quality_score.ok_or(MitamaError::InvalidStatus)?;
+        let original_refund_percentage = original_refund_percentage.ok_or(MitamaError::InvalidStatus)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(now < settles_at, MitamaError::AppealWindowClosed);
+
+        // Part of the bond pays the appeal round's oracles, whatever the outcome
+        let bond = ctx.accounts.oracle_registry.appeal_bond;
+        let oracle_reward = (bond as u128)
+            .checked_mul(ctx.accounts.protocol_config.oracle_reward_bps as u128)
+            .ok_or(MitamaError::ArithmeticOverflow)?
+            .checked_div(10_000)
+            .ok_or(MitamaError::ArithmeticOverflow)? as u64;
+        let held_bond = bond - oracle_reward;
+
+        deposit_escrow_lamports(
+            &ctx.accounts.appellant,
+            &ctx.accounts.appeal.to_account_info(),
+            held_bond,
+        )?;
+        deposit_escrow_lamports(
+            &ctx.accounts.appellant,
+            &ctx.accounts.dispute_record.to_account_info(),
+            oracle_reward,
+        )?;
+
+        let appeal = &mut ctx.accounts.appeal;
+        appeal.escrow = ctx.accounts.escrow.key();
+        appeal.appellant = ctx.accounts.appellant.key();
+        appeal.bond = held_bond;
+        appeal.oracle_reward = oracle_reward;
+        appeal.first_round = ctx.accounts.escrow.oracle_submissions.clone();
+        appeal.first_round_absent = ctx.accounts.dispute_record.commitments
+            .iter()
+            .filter(|c| !c.revealed)
+            .map(|c| c.oracle)
+            .collect();
+        appeal.original_quality_score = original_quality_score;
+        appeal.original_refund_percentage = original_refund_percentage;
+        appeal.filed_at = now;
+        appeal.bump = ctx.bumps.appeal;
+
+        // Reopen the dispute for a fresh oracle round
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.status = EscrowStatus::Disputed;
+        escrow.appealed = true;
+        escrow.settles_at = 0;
+        escrow.quality_score = None;
+        escrow.refund_percentage = None;
+        escrow.oracle_submissions.clear();
+
+        // The appeal round gets as long to resolve as the first round had
+        let dispute_record = &mut ctx.accounts.dispute_record;
+        let resolution_timeout = dispute_record.resolution_deadline - dispute_record.opened_at;
+        dispute_record.scoring_opens_at = now;
+        dispute_record.commitments.clear();
+        dispute_record.resolution_deadline = now.saturating_add(resolution_timeout);
+        dispute_record.oracle_reward_pool = dispute_record.oracle_reward_pool
+            .checked_add(oracle_reward)
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+
+        emit!(AppealFiled {
+            escrow: escrow.key(),
+            appeal: appeal.key(),
+            appellant: appeal.appellant,
+            bond: held_bond,
+            oracle_reward,
+            original_quality_score,
+            original_refund_percentage,
+        });
+
+        Ok(())
+    }
+
+    /// Pay out a ruling held in `PendingSettlement` once its appeal window has
+    /// closed without an appeal, settling the oracle stakes behind it.
+    /// Remaining accounts are the counted oracles' stake accounts, in
+    /// submission order, followed by those of oracles that committed but never
+    /// revealed. Anyone can crank this.
+    pub fn finalize_settlement<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FinalizeSettlement<'info>>,
+    ) -> Result<()> {
+        let (status, settles_at, quality_score, refund_percentage, transaction_id, escrow_key) = {
+            let escrow = &ctx.accounts.escrow;
+            (
+                escrow.status,
+                escrow.settles_at,
+                escrow.quality_score,
+                escrow.refund_percentage,
+                escrow.transaction_id.clone(),
+                escrow.key(),
+            )
+        };
+
+        require!(status == EscrowStatus::PendingSettlement, MitamaError::InvalidStatus);
+        require!(
+            Clock::get()?.unix_timestamp >= settles_at,
+            MitamaError::AppealWindowOpen
+        );
+        let quality_score = quality_score.ok_or(MitamaError::InvalidStatus)?;
+        let refund_percentage = refund_percentage.ok_or(MitamaError::InvalidStatus)?;
+
+        // The unappealed ruling is final, so settle the oracle stakes held for it
+        let submissions = ctx.accounts.escrow.oracle_submissions.clone();
+        let absent: Vec<Pubkey> = ctx.accounts.dispute_record.commitments
+            .iter()
+            .filter(|c| !c.revealed)
+            .map(|c| c.oracle)
+            .collect();
+        require!(
+            ctx.remaining_accounts.len() == submissions.len() + absent.len(),
+            MitamaError::OracleStakeMismatch
+        );
+        let (counted_stakes, absent_stakes) = ctx.remaining_accounts.split_at(submissions.len());
+        slash_missing_reveals(
+            absent_stakes,
+            &absent,
+            escrow_key,
+            &ctx.accounts.fee_vault,
+            ctx.accounts.protocol_config.oracle_slash_bps,
+        )?;
+        settle_oracle_stakes(
+            counted_stakes,
+            &submissions,
+            &mut ctx.accounts.dispute_record,
+            &ctx.accounts.fee_vault,
+            ctx.accounts.protocol_config.oracle_slash_bps,
+            quality_score,
+            ctx.accounts.oracle_registry.max_score_deviation,
+        )?;
+
+        let (refund_amount, payment_amount) = settle_dispute_outcome(
+            DisputeSettlement {
+                escrow: &mut ctx.accounts.escrow,
+                agent: ctx.accounts.agent.to_account_info(),
+                api: ctx.accounts.api.to_account_info(),
+                tokens: EscrowTokenAccounts {
+                    token_mint: ctx.accounts.token_mint.as_ref(),
+                    escrow_token_account: ctx.accounts.escrow_token_account.as_ref(),
+                    agent_token_account: ctx.accounts.agent_token_account.as_ref(),
+                    api_token_account: ctx.accounts.api_token_account.as_ref(),
+                    token_program: ctx.accounts.token_program.as_ref(),
+                },
+                agent_reputation: &mut ctx.accounts.agent_reputation,
+                api_reputation: &mut ctx.accounts.api_reputation,
+                agent_identity: ctx.accounts.agent_identity.as_mut(),
+                protocol_config: &ctx.accounts.protocol_config,
+                fee_vault: Some(&ctx.accounts.fee_vault),
+            },
+            None,
+            Some(quality_score),
+            refund_percentage,
+        )?;
+
+        emit!(SettlementFinalized {
+            escrow: escrow_key,
+            transaction_id,
+            quality_score,
+            refund_percentage,
+            refund_amount,
+            payment_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Settle a dispute nobody ruled on before its resolution deadline by the
+    /// protocol's timeout policy. A milestone dispute settles that milestone
+    /// alone. The oracles' unearned share of the dispute fee is refunded to
+    /// the agent, and any appeal bond, with its oracle share, to the appellant.
+    /// Anyone can crank this.
+    pub fn resolve_dispute_timeout(ctx: Context<ResolveDisputeTimeout>) -> Result<()> {
+        let (status, transaction_id, escrow_key, appealed) = {
+            let escrow = &ctx.accounts.escrow;
+            (
+                escrow.status,
+                escrow.transaction_id.clone(),
+                escrow.key(),
+                escrow.appealed,
+            )
+        };
+        let milestone_index = ctx.accounts.dispute_record.milestone_index;
+
+        require!(
+            ctx.accounts.dispute_record.timed_out(Clock::get()?.unix_timestamp),
+            MitamaError::ResolutionDeadlineNotReached
+        );
+        require!(
+            !appealed || ctx.accounts.appeal.is_some(),
+            MitamaError::MissingAppeal
+        );
+
+        // A milestone dispute leaves the escrow itself active
+        let expected_status = if milestone_index.is_some() {
+            EscrowStatus::Active
+        } else {
+            EscrowStatus::Disputed
+        };
+        require!(status == expected_status, MitamaError::InvalidStatus);
+
+        let policy = ctx.accounts.protocol_config.timeout_policy;
+        let refund_percentage = policy.refund_percentage();
+        let (refund_amount, payment_amount) = settle_dispute_outcome(
+            DisputeSettlement {
+                escrow: &mut ctx.accounts.escrow,
+                agent: ctx.accounts.agent.to_account_info(),
+                api: ctx.accounts.api.to_account_info(),
+                tokens: EscrowTokenAccounts {
+                    token_mint: ctx.accounts.token_mint.as_ref(),
+                    escrow_token_account: ctx.accounts.escrow_token_account.as_ref(),
+                    agent_token_account: ctx.accounts.agent_token_account.as_ref(),
+                    api_token_account: ctx.accounts.api_token_account.as_ref(),
+                    token_program: ctx.accounts.token_program.as_ref(),
+                },
+                agent_reputation: &mut ctx.accounts.agent_reputation,
+                api_reputation: &mut ctx.accounts.api_reputation,
+                agent_identity: ctx.accounts.agent_identity.as_mut(),
+                protocol_config: &ctx.accounts.protocol_config,
+                fee_vault: None,
+            },
+            milestone_index,
+            None,
+            refund_percentage,
+        )?;
+
+        // No oracle earned the pool, so each part goes back to whoever paid it:
+        // the appeal's share of the bond to the appellant, the rest of the
+        // dispute fee share to the agent
+        let appeal = ctx.accounts.appeal.as_ref().filter(|_| appealed);
+        let record = &mut ctx.accounts.dispute_record;
+        let appeal_reward = appeal.map_or(0, |a| a.oracle_reward.min(record.oracle_reward_pool));
+        let fee_refund = record.oracle_reward_pool - appeal_reward;
+        record.oracle_reward_pool = 0;
+
+        let mut appeal_refund = 0;
+        if let Some(appeal) = appeal {
+            let appellant = if appeal.appellant == ctx.accounts.agent.key() {
+                ctx.accounts.agent.to_account_info()
+            } else {
+                ctx.accounts.api.to_account_info()
+            };
+            **record.to_account_info().try_borrow_mut_lamports()? -= appeal_reward;
+            **appellant.try_borrow_mut_lamports()? += appeal_reward;
+            appeal_refund = appeal.bond + appeal_reward;
+            appeal.close(appellant)?;
+        }
+        if fee_refund > 0 {
+            **record.to_account_info().try_borrow_mut_lamports()? -= fee_refund;
+            **ctx.accounts.agent.to_account_info().try_borrow_mut_lamports()? += fee_refund;
+        }
+
+        emit!(DisputeTimedOut {
+            escrow: escrow_key,
+            transaction_id,
+            milestone_index,
+            policy,
+            refund_percentage,
+            refund_amount,
+            payment_amount,
+            fee_refund,
+            appeal_refund,
+        });
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // Milestone Escrow Instructions
+    // ========================================================================
+
+    /// Initialize an escrow paid out over ordered milestones, each with its
+    /// own amount and deadline, released or disputed independently
+    pub fn initialize_milestone_escrow(
+        ctx: Context<InitializeMilestoneEscrow>,
+        transaction_id: String,
+        milestones: Vec<MilestoneParams>,
+        use_spl_token: bool,
+    ) -> Result<()> {
+        require!(
+            !milestones.is_empty() && milestones.len() <= MAX_MILESTONES,
+            MitamaError::InvalidMilestone
+        );
+        require!(
+            !transaction_id.is_empty() && transaction_id.len() <= 64,
+            MitamaError::InvalidTransactionId
+        );
+        require!(ctx.accounts.protocol_config.is_active, MitamaError::ProtocolNotActive);
+
+        // Deadlines must not run backwards through the list
+        let mut amount: u64 = 0;
+        let mut last_time_lock = 0;
+        for params in milestones.iter() {
+            require!(params.amount > 0, MitamaError::InvalidAmount);
+            require!(
+                (MIN_TIME_LOCK..=MAX_TIME_LOCK).contains(&params.time_lock),
+                MitamaError::InvalidTimeLock
+            );
+            require!(params.time_lock >= last_time_lock, MitamaError::InvalidMilestone);
+            last_time_lock = params.time_lock;
+            amount = amount
+                .checked_add(params.amount)
+                .ok_or(MitamaError::ArithmeticOverflow)?;
+        }
+
+        let clock = Clock::get()?;
+        let escrow = &mut ctx.accounts.escrow;
+
+        escrow.agent = ctx.accounts.agent.key();
+        escrow.api = ctx.accounts.api.key();
+        escrow.amount = amount;
+        escrow.status = EscrowStatus::Active;
+        escrow.created_at = clock.unix_timestamp;
+        escrow.expires_at = clock.unix_timestamp + last_time_lock;
+        escrow.transaction_id = transaction_id.clone();
+        escrow.bump = ctx.bumps.escrow;
+        escrow.quality_score = None;
+        escrow.refund_percentage = None;
+        escrow.oracle_submissions = Vec::new();
+        escrow.switchboard_feed = None;
+        escrow.streaming = false;
+        escrow.withdrawn = 0;
+        escrow.disputed_at = 0;
+        escrow.appealed = false;
+        escrow.settles_at = 0;
+        escrow.agent_identity =
+            bind_agent_identity(ctx.accounts.agent_identity.as_mut(), clock.unix_timestamp)?;
+        escrow.milestones = milestones
+            .iter()
+            .map(|params| Milestone {
+                amount: params.amount,
+                deadline: clock.unix_timestamp + params.time_lock,
+                status: EscrowStatus::Active,
+                quality_score: None,
+                refund_percentage: None,
+            })
+            .collect();
+
+        if use_spl_token {
+            let token_mint = ctx.accounts.token_mint.as_ref()
+                .ok_or(MitamaError::MissingTokenMint)?;
+            let escrow_token_account = ctx.accounts.escrow_token_account.as_mut()
+                .ok_or(MitamaError::MissingTokenAccount)?;
+            let agent_token_account = ctx.accounts.agent_token_account.as_ref()
+                .ok_or(MitamaError::MissingTokenAccount)?;
+            let token_program = ctx.accounts.token_program.as_ref()
+                .ok_or(MitamaError::MissingTokenProgram)?;
+
+            escrow.token_mint = Some(token_mint.key());
+            escrow.escrow_token_account = Some(escrow_token_account.key());
+            escrow.token_decimals = token_mint.decimals;
+
+            let received = deposit_escrow_tokens(
+                &ctx.accounts.agent,
+                token_mint,
+                agent_token_account,
+                escrow_token_account,
+                token_program,
+                amount,
+            )?;
+            scale_milestone_amounts(&mut escrow.milestones, amount, received)?;
+            escrow.amount = received;
+        } else {
+            escrow.token_mint = None;
+            escrow.escrow_token_account = None;
+            escrow.token_decimals = 9;
+
+            deposit_escrow_lamports(&ctx.accounts.agent, &escrow.to_account_info(), amount)?;
+        }
+
+        collect_agreement_fee(
+            &mut ctx.accounts.protocol_config,
+            &ctx.accounts.agent,
+            &ctx.accounts.fee_vault,
+            amount,
+        )?;
+
+        emit!(EscrowInitialized {
+            escrow: escrow.key(),
+            agent: escrow.agent,
+            api: escrow.api,
+            amount: escrow.amount,
+            expires_at: escrow.expires_at,
+            transaction_id,
+            is_token: use_spl_token,
+            token_mint: escrow.token_mint,
+        });
+
+        Ok(())
+    }
+
+    /// Release one milestone's funds to the API. The agent may release at any
+    /// time; anyone may release once the milestone deadline has passed.
+    pub fn release_milestone(ctx: Context<ReleaseFunds>, milestone_index: u8) -> Result<()> {
+        let clock = Clock::get()?;
+        let index = milestone_index as usize;
+
+        let (status, agent_key, milestone) = {
+            let escrow = &ctx.accounts.escrow;
+            let milestone = escrow.milestones.get(index)
+                .ok_or(MitamaError::InvalidMilestone)?;
+            (escrow.status, escrow.agent, milestone.clone())
+        };
+
+        require!(status == EscrowStatus::Active, MitamaError::InvalidStatus);
+        require!(milestone.status == EscrowStatus::Active, MitamaError::InvalidStatus);
+
+        let is_agent = ctx.accounts.agent.key() == agent_key;
+        if !is_agent {
+            require!(
+                clock.unix_timestamp >= milestone.deadline,
+                MitamaError::TimeLockNotExpired
+            );
+        }
+
+        let tokens = EscrowTokenAccounts {
+            token_mint: ctx.accounts.token_mint.as_ref(),
+            escrow_token_account: ctx.accounts.escrow_token_account.as_ref(),
+            agent_token_account: None,
+            api_token_account: ctx.accounts.api_token_account.as_ref(),
+            token_program: ctx.accounts.token_program.as_ref(),
+        };
+
+        transfer_escrow_payout(
+            &ctx.accounts.escrow,
+            &ctx.accounts.agent.to_account_info(),
+            &ctx.accounts.api.to_account_info(),
+            tokens,
+            0,
+            milestone.amount,
+        )?;
+
+        ctx.accounts.escrow.milestones[index].status = EscrowStatus::Released;
+        record_release(
+            ctx.accounts.agent_reputation.as_mut(),
+            ctx.accounts.api_reputation.as_mut(),
+        )?;
+
+        if let Some(final_status) = ctx.accounts.escrow.final_milestone_status() {
+            ctx.accounts.escrow.status = final_status;
+
+            let rent_recipient = if is_agent {
+                Some(ctx.accounts.agent.to_account_info())
+            } else {
+                ctx.accounts.agent_wallet.as_ref().map(|wallet| wallet.to_account_info())
+            };
+            close_settled_vault(&ctx.accounts.escrow, tokens, rent_recipient)?;
+
+            record_agent_activity(
+                &ctx.accounts.escrow,
+                ctx.accounts.agent_identity.as_mut(),
+                AgentActivity::Released,
+            )?;
+        }
+
+        let escrow = &ctx.accounts.escrow;
+        emit!(MilestoneReleased {
+            escrow: escrow.key(),
+            transaction_id: escrow.transaction_id.clone(),
+            milestone_index,
+            amount: milestone.amount,
+            api: escrow.api,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Dispute a single milestone before its deadline
+    pub fn dispute_milestone(ctx: Context<DisputeMilestone>, milestone_index: u8) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        let reputation = &mut ctx.accounts.reputation;
+        let index = milestone_index as usize;
+
+        require!(ctx.accounts.protocol_config.is_active, MitamaError::ProtocolNotActive);
+        require!(escrow.status == EscrowStatus::Active, MitamaError::InvalidStatus);
+        require!(ctx.accounts.agent.key() == escrow.agent, MitamaError::Unauthorized);
+
+        let milestone = escrow.milestones.get(index)
+            .ok_or(MitamaError::InvalidMilestone)?;
+        require!(milestone.status == EscrowStatus::Active, MitamaError::InvalidStatus);
+
+        let clock = Clock::get()?;
+        require!(clock.unix_timestamp < milestone.deadline, MitamaError::DisputeWindowExpired);
+
+        let oracle_reward_pool = collect_dispute_fee(
+            &mut ctx.accounts.protocol_config,
+            reputation,
+            &ctx.accounts.agent,
+            &ctx.accounts.fee_vault,
+            &ctx.accounts.dispute_record.to_account_info(),
+            milestone.amount,
+        )?;
+
+        reputation.disputes_filed = reputation.disputes_filed.saturating_add(1);
+        escrow.milestones[index].status = EscrowStatus::Disputed;
+
+        open_dispute_record(
+            &mut ctx.accounts.dispute_record,
+            escrow,
+            escrow.key(),
+            Some(milestone_index),
+            &mut ctx.accounts.protocol_config,
+            clock.unix_timestamp,
+            ctx.bumps.dispute_record,
+        );
+        ctx.accounts.dispute_record.oracle_reward_pool = oracle_reward_pool;
+
+        record_agent_activity(
+            escrow,
+            ctx.accounts.agent_identity.as_mut(),
+            AgentActivity::Disputed,
+        )?;
+
+        emit!(MilestoneDisputed {
+            escrow: escrow.key(),
+            agent: escrow.agent,
+            transaction_id: escrow.transaction_id.clone(),
+            milestone_index,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Resolve a disputed milestone with a verifier-signed quality score.
+    /// The refund follows the same quality tiers as oracle consensus, and the
+    /// same single-verifier gate as `resolve_dispute` applies.
+    pub fn resolve_milestone_dispute(
+        ctx: Context<ResolveDispute>,
+        milestone_index: u8,
+        quality_score: u8,
+        expires_at: i64,
+        signature: [u8; 64],
+    ) -> Result<()> {
+        let index = milestone_index as usize;
+
+        let (status, transaction_id, escrow_key, milestone) = {
+            let escrow = &ctx.accounts.escrow;
+            let milestone = escrow.milestones.get(index)
+                .ok_or(MitamaError::InvalidMilestone)?;
+            (
+                escrow.status,
+                escrow.transaction_id.clone(),
+                escrow.key(),
+                milestone.clone(),
+            )
+        };
+
+        require!(status == EscrowStatus::Active, MitamaError::InvalidStatus);
+        require!(milestone.status == EscrowStatus::Disputed, MitamaError::InvalidStatus);
+        require!(quality_score <= 100, MitamaError::InvalidQualityScore);
+
+        let dispute_record = &ctx.accounts.dispute_record;
+        require!(
+            dispute_record.milestone_index == Some(milestone_index),
+            MitamaError::DisputeRecordMismatch
+        );
+        require!(
+            dispute_record.hearing_closed(Clock::get()?.unix_timestamp),
+            MitamaError::EvidenceWindowOpen
+        );
+        check_single_verifier(
+            &ctx.accounts.oracle_registry,
+            dispute_record,
+            ctx.accounts.verifier.key,
+            &ctx.accounts.verifier_stake,
+            ctx.accounts.protocol_config.min_oracle_stake,
+        )?;
+
+        let refund_percentage = ctx.accounts.protocol_config.refund_for_quality(quality_score);
+        let attestation = OracleAttestation::for_dispute(
+            &ctx.accounts.oracle_registry,
+            dispute_record,
+            quality_score,
+            refund_percentage,
+            expires_at,
+        );
+        verify_oracle_attestation(
+            &ctx.accounts.instructions_sysvar,
+            &signature,
+            ctx.accounts.verifier.key,
+            &attestation,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        let (refund_amount, payment_amount) = settle_dispute_outcome(
+            DisputeSettlement {
+                escrow: &mut ctx.accounts.escrow,
+                agent: ctx.accounts.agent.to_account_info(),
+                api: ctx.accounts.api.to_account_info(),
+                tokens: EscrowTokenAccounts {
+                    token_mint: ctx.accounts.token_mint.as_ref(),
+                    escrow_token_account: ctx.accounts.escrow_token_account.as_ref(),
+                    agent_token_account: ctx.accounts.agent_token_account.as_ref(),
+                    api_token_account: ctx.accounts.api_token_account.as_ref(),
+                    token_program: ctx.accounts.token_program.as_ref(),
+                },
+                agent_reputation: &mut ctx.accounts.agent_reputation,
+                api_reputation: &mut ctx.accounts.api_reputation,
+                agent_identity: ctx.accounts.agent_identity.as_mut(),
+                protocol_config: &ctx.accounts.protocol_config,
+                fee_vault: Some(&ctx.accounts.fee_vault),
+            },
+            Some(milestone_index),
+            Some(quality_score),
+            refund_percentage,
+        )?;
+
+        emit!(MilestoneDisputeResolved {
+            escrow: escrow_key,
+            transaction_id,
+            milestone_index,
+            quality_score,
+            refund_percentage,
+            refund_amount,
+            payment_amount,
+            verifier: ctx.accounts.verifier.key(),
+        });
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // Subscription Instructions
+    // ========================================================================
+
+    /// Create a subscription that pre-funds `total_periods` escrows of
+    /// `amount_per_period` to the API, opened one period at a time by a crank.
+    /// A SOL reserve covering each period's agreement fee (at the maximum rate)
+    /// and account rent is deposited alongside; what is unspent is returned on
+    /// cancellation.
+    pub fn create_subscription(
+        ctx: Context<CreateSubscription>,
+        subscription_id: String,
+        amount_per_period: u64,
+        period_length: i64,
+        total_periods: u16,
+        use_spl_token: bool,
+    ) -> Result<()> {
+        require!(amount_per_period > 0, MitamaError::InvalidAmount);
+        require!(
+            (MIN_TIME_LOCK..=MAX_TIME_LOCK).contains(&period_length),
+            MitamaError::InvalidTimeLock
+        );
+        require!(
+            total_periods > 0 && total_periods <= MAX_SUBSCRIPTION_PERIODS,
+            MitamaError::InvalidPeriodCount
+        );
+        require!(
+            !subscription_id.is_empty() && subscription_id.len() <= MAX_SUBSCRIPTION_ID_LENGTH,
+            MitamaError::InvalidTransactionId
+        );
+        require!(ctx.accounts.protocol_config.is_active, MitamaError::ProtocolNotActive);
+
+        let total_amount = amount_per_period
+            .checked_mul(total_periods as u64)
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+
+        let clock = Clock::get()?;
+        let rent = Rent::get()?;
+        let subscription = &mut ctx.accounts.subscription;
+
+        subscription.agent = ctx.accounts.agent.key();
+        subscription.api = ctx.accounts.api.key();
+        subscription.subscription_id = subscription_id.clone();
+        subscription.amount_per_period = amount_per_period;
+        subscription.period_length = period_length;
+        subscription.total_periods = total_periods;
+        subscription.periods_opened = 0;
+        subscription.next_period_at = clock.unix_timestamp;
+        subscription.created_at = clock.unix_timestamp;
+        subscription.bump = ctx.bumps.subscription;
+        subscription.agent_identity = match ctx.accounts.agent_identity.as_ref() {
+            Some(identity) => {
+                require!(identity.is_active, MitamaError::AgentNotActive);
+                Some(identity.key())
+            }
+            None => None,
+        };
+
+        let mut vault_rent = 0;
+        if use_spl_token {
+            let token_mint = ctx.accounts.token_mint.as_ref()
+                .ok_or(MitamaError::MissingTokenMint)?;
+            let subscription_token_account = ctx.accounts.subscription_token_account.as_mut()
+                .ok_or(MitamaError::MissingTokenAccount)?;
+            let agent_token_account = ctx.accounts.agent_token_account.as_ref()
+                .ok_or(MitamaError::MissingTokenAccount)?;
+            let token_program = ctx.accounts.token_program.as_ref()
+                .ok_or(MitamaError::MissingTokenProgram)?;
+
+            subscription.token_mint = Some(token_mint.key());
+            subscription.subscription_token_account = Some(subscription_token_account.key());
+            subscription.token_decimals = token_mint.decimals;
+
+            // Each period's escrow vault matches this one in size
+            vault_rent = rent.minimum_balance(subscription_token_account.to_account_info().data_len());
+
+            let received = deposit_escrow_tokens(
+                &ctx.accounts.agent,
+                token_mint,
+                agent_token_account,
+                subscription_token_account,
+                token_program,
+                total_amount,
+            )?;
+            subscription.amount_per_period = received / total_periods as u64;
+            require!(subscription.amount_per_period > 0, MitamaError::InvalidAmount);
+        } else {
+            subscription.token_mint = None;
+            subscription.subscription_token_account = None;
+            subscription.token_decimals = 9;
+
+            deposit_escrow_lamports(&ctx.accounts.agent, &subscription.to_account_info(), total_amount)?;
+        }
+
+        let reserve_per_period = rent
+            .minimum_balance(8 + Escrow::INIT_SPACE)
+            .checked_add(vault_rent)
+            .and_then(|cost| {
+                cost.checked_add(calculate_agreement_fee(MAX_FEE_BPS, amount_per_period).ok()?)
+            })
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+        let reserve = reserve_per_period
+            .checked_mul(total_periods as u64)
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+        deposit_escrow_lamports(&ctx.accounts.agent, &subscription.to_account_info(), reserve)?;
+
+        emit!(SubscriptionCreated {
+            subscription: subscription.key(),
+            agent: subscription.agent,
+            api: subscription.api,
+            subscription_id,
+            amount_per_period: subscription.amount_per_period,
+            period_length,
+            total_periods,
+            token_mint: subscription.token_mint,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless crank that opens the escrow for a subscription's next due
+    /// period under the usual fee and time-lock rules. The cranker pays the new
+    /// accounts' rent and is reimbursed from the subscription's reserve.
+    pub fn open_subscription_period(
+        ctx: Context<OpenSubscriptionPeriod>,
+        transaction_id: String,
+    ) -> Result<()> {
+        require!(ctx.accounts.protocol_config.is_active, MitamaError::ProtocolNotActive);
+
+        let clock = Clock::get()?;
+        let subscription = &ctx.accounts.subscription;
+
+        require!(
+            subscription.periods_opened < subscription.total_periods,
+            MitamaError::SubscriptionComplete
+        );
+        require!(
+            clock.unix_timestamp >= subscription.next_period_at,
+            MitamaError::PeriodNotDue
+        );
+        require!(
+            subscription.token_mint.is_some() == ctx.accounts.escrow_token_account.is_some(),
+            MitamaError::MissingTokenAccount
+        );
+
+        let period = subscription.periods_opened + 1;
+        require!(
+            transaction_id == subscription.period_transaction_id(period),
+            MitamaError::InvalidTransactionId
+        );
+
+        let amount = subscription.amount_per_period;
+        let agreement_fee =
+            calculate_agreement_fee(ctx.accounts.protocol_config.agreement_fee_bps, amount)?;
+
+        // Rent the cranker just paid for the new escrow and its vault
+        let mut rent_paid = ctx.accounts.escrow.to_account_info().lamports();
+        if let Some(vault) = ctx.accounts.escrow_token_account.as_ref() {
+            rent_paid = rent_paid
+                .checked_add(vault.to_account_info().lamports())
+                .ok_or(MitamaError::ArithmeticOverflow)?;
+        }
+
+        // Principal for this and later SOL periods must stay in the subscription
+        let subscription_info = subscription.to_account_info();
+        let principal_held = if subscription.token_mint.is_none() {
+            amount
+                .checked_mul((subscription.total_periods - subscription.periods_opened) as u64)
+                .ok_or(MitamaError::ArithmeticOverflow)?
+        } else {
+            0
+        };
+        let required = Rent::get()?
+            .minimum_balance(subscription_info.data_len())
+            .checked_add(principal_held)
+            .and_then(|held| held.checked_add(rent_paid))
+            .and_then(|held| held.checked_add(agreement_fee))
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+        require!(subscription_info.lamports() >= required, MitamaError::InsufficientFunds);
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.agent = subscription.agent;
+        escrow.api = subscription.api;
+        escrow.amount = amount;
+        escrow.status = EscrowStatus::Active;
+        escrow.created_at = clock.unix_timestamp;
+        escrow.expires_at = clock.unix_timestamp + subscription.period_length;
+        escrow.transaction_id = transaction_id.clone();
+        escrow.bump = ctx.bumps.escrow;
+        escrow.quality_score = None;
+        escrow.refund_percentage = None;
+        escrow.oracle_submissions = Vec::new();
+        escrow.switchboard_feed = None;
+        escrow.milestones = Vec::new();
+        escrow.streaming = false;
+        escrow.withdrawn = 0;
+        escrow.disputed_at = 0;
+        escrow.appealed = false;
+        escrow.settles_at = 0;
+        escrow.token_decimals = subscription.token_decimals;
+
+        // Periods of a bound subscription bind to the identity while it is active
+        escrow.agent_identity = match subscription.agent_identity {
+            Some(_) => {
+                let identity = ctx.accounts.agent_identity.as_mut()
+                    .ok_or(MitamaError::MissingAgentIdentity)?;
+                if identity.is_active {
+                    bind_agent_identity(Some(identity), clock.unix_timestamp)?
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
+
+        if let Some(mint) = subscription.token_mint {
+            let token_mint = ctx.accounts.token_mint.as_ref()
+                .ok_or(MitamaError::MissingTokenMint)?;
+            let subscription_token_account = ctx.accounts.subscription_token_account.as_ref()
+                .ok_or(MitamaError::MissingTokenAccount)?;
+            let escrow_token_account = ctx.accounts.escrow_token_account.as_mut()
+                .ok_or(MitamaError::MissingTokenAccount)?;
+            let token_program = ctx.accounts.token_program.as_ref()
+                .ok_or(MitamaError::MissingTokenProgram)?;
+
+            require!(token_mint.key() == mint, MitamaError::TokenMintMismatch);
+            require!(
+                subscription.subscription_token_account == Some(subscription_token_account.key()),
+                MitamaError::InvalidTokenAccount
+            );
+
+            escrow.token_mint = Some(mint);
+            escrow.escrow_token_account = Some(escrow_token_account.key());
+
+            transfer_subscription_tokens(
+                subscription,
+                token_mint,
+                subscription_token_account,
+                escrow_token_account,
+                token_program,
+                amount,
+            )?;
+
+            // Transfer-fee mints deliver less than was sent; escrow what actually arrived
+            escrow_token_account.reload()?;
+            escrow.amount = escrow_token_account.amount;
+            require!(escrow.amount > 0, MitamaError::InvalidAmount);
+        } else {
+            escrow.token_mint = None;
+            escrow.escrow_token_account = None;
+
+            **subscription_info.try_borrow_mut_lamports()? -= amount;
+            **escrow.to_account_info().try_borrow_mut_lamports()? += amount;
+        }
+
+        **subscription_info.try_borrow_mut_lamports()? -= rent_paid;
+        **ctx.accounts.payer.to_account_info().try_borrow_mut_lamports()? += rent_paid;
+
+        if agreement_fee > 0 {
+            **subscription_info.try_borrow_mut_lamports()? -= agreement_fee;
+            **ctx.accounts.fee_vault.try_borrow_mut_lamports()? += agreement_fee;
+
+            let protocol_config = &mut ctx.accounts.protocol_config;
+            protocol_config.total_fees_collected = protocol_config
+                .total_fees_collected
+                .saturating_add(agreement_fee);
+
+            emit!(ProtocolFeeCollected {
+                fee_type: "agreement".to_string(),
+                amount: agreement_fee,
+                payer: escrow.agent,
+                treasury: protocol_config.treasury,
+            });
+        }
+
+        emit!(EscrowInitialized {
+            escrow: escrow.key(),
+            agent: escrow.agent,
+            api: escrow.api,
+            amount: escrow.amount,
+            expires_at: escrow.expires_at,
+            transaction_id: transaction_id.clone(),
+            is_token: escrow.token_mint.is_some(),
+            token_mint: escrow.token_mint,
+        });
+
+        emit!(SubscriptionPeriodOpened {
+            subscription: subscription.key(),
+            escrow: escrow.key(),
+            period,
+            transaction_id,
+            amount: escrow.amount,
+            cranker: ctx.accounts.payer.key(),
+            rent_reimbursed: rent_paid,
+        });
+
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.periods_opened = period;
+        subscription.next_period_at = subscription.next_period_at
+            .checked_add(subscription.period_length)
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    /// Cancel a subscription's remaining periods and return everything it
+    /// still holds to the agent. Either party may cancel; escrows already
+    /// opened are unaffected and can still be released or disputed.
+    pub fn cancel_subscription(ctx: Context<CancelSubscription>) -> Result<()> {
+        let subscription = &ctx.accounts.subscription;
+        let authority = ctx.accounts.authority.key();
+
+        require!(
+            authority == subscription.agent || authority == subscription.api,
+            MitamaError::Unauthorized
+        );
+
+        let refunded = if let Some(mint) = subscription.token_mint {
+            let token_mint = ctx.accounts.token_mint.as_ref()
+                .ok_or(MitamaError::MissingTokenMint)?;
+            let subscription_token_account = ctx.accounts.subscription_token_account.as_ref()
+                .ok_or(MitamaError::MissingTokenAccount)?;
+            let agent_token_account = ctx.accounts.agent_token_account.as_ref()
+                .ok_or(MitamaError::MissingTokenAccount)?;
+            let token_program = ctx.accounts.token_program.as_ref()
+                .ok_or(MitamaError::MissingTokenProgram)?;
+
+            require!(token_mint.key() == mint, MitamaError::TokenMintMismatch);
+            require!(
+                subscription.subscription_token_account == Some(subscription_token_account.key()),
+                MitamaError::InvalidTokenAccount
+            );
+            require!(
+                agent_token_account.mint == mint && agent_token_account.owner == subscription.agent,
+                MitamaError::InvalidTokenAccount
+            );
+
+            let balance = subscription_token_account.amount;
+            if balance > 0 {
+                transfer_subscription_tokens(
+                    subscription,
+                    token_mint,
+                    subscription_token_account,
+                    agent_token_account,
+                    token_program,
+                    balance,
+                )?;
+            }
+
+            let seeds = &[
+                b"subscription".as_ref(),
+                subscription.agent.as_ref(),
+                subscription.subscription_id.as_bytes(),
+                &[subscription.bump],
+            ];
+            harvest_and_close_vault(
+                subscription_token_account.to_account_info(),
+                token_mint,
+                subscription.to_account_info(),
+                &[&seeds[..]],
+                ctx.accounts.agent.to_account_info(),
+                token_program,
+            )?;
+            balance
+        } else {
+            let periods_left = subscription.total_periods - subscription.periods_opened;
+            subscription.amount_per_period.saturating_mul(periods_left as u64)
+        };
+
+        emit!(SubscriptionCancelled {
+            subscription: subscription.key(),
+            cancelled_by: authority,
+            periods_opened: subscription.periods_opened,
+            total_periods: subscription.total_periods,
+            refunded,
+        });
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // Oracle Registry Instructions
+    // ========================================================================
+
+    /// Initialize the oracle registry
+    pub fn initialize_oracle_registry(
+        ctx: Context<InitializeOracleRegistry>,
+        min_consensus: u8,
+        max_score_deviation: u8,
+        genesis_hash: [u8; 32],
+    ) -> Result<()> {
+        let registry = &mut ctx.accounts.oracle_registry;
+
+        require!(min_consensus >= MIN_CONSENSUS_ORACLES, MitamaError::InsufficientOracleConsensus);
+        require!(max_score_deviation <= 50, MitamaError::InvalidQualityScore);
+
+        let clock = Clock::get()?;
+
+        registry.admin = ctx.accounts.admin.key();
+        registry.oracles = Vec::new();
+        registry.min_consensus = min_consensus;
+        registry.max_score_deviation = max_score_deviation;
+        registry.created_at = clock.unix_timestamp;
+        registry.updated_at = clock.unix_timestamp;
+        registry.bump = ctx.bumps.oracle_registry;
+        registry.version = ACCOUNT_VERSION;
+        registry.consensus_mode = ConsensusMode::Median;
+        registry.appeal_window = 0;
+        registry.appeal_min_consensus = 0;
+        registry.appeal_bond = 0;
+        registry.genesis_hash = genesis_hash;
+        registry.commit_window = DEFAULT_COMMIT_WINDOW;
+        registry.reveal_window = DEFAULT_REVEAL_WINDOW;
+
+        emit!(OracleRegistryInitialized {
+            registry: registry.key(),
+            admin: registry.admin,
+            min_consensus,
+            max_score_deviation,
+            genesis_hash,
+        });
+
+        Ok(())
+    }
+
+    /// Bring an oracle registry from an earlier release up to the current
+    /// layout, starting in median mode with appeals off and default scoring
+    /// windows. Earlier registries carry no genesis hash, so the admin
+    /// supplies it and pays the extra rent.
+    pub fn migrate_oracle_registry(
+        ctx: Context<MigrateOracleRegistry>,
+        genesis_hash: [u8; 32],
+    ) -> Result<()> {
+        let info = ctx.accounts.oracle_registry.to_account_info();
+        let space = 8 + OracleRegistry::INIT_SPACE;
+        let legacy: LegacyOracleRegistry =
+            load_legacy_account(&info, OracleRegistry::DISCRIMINATOR, space)?;
+
+        require!(legacy.admin == ctx.accounts.admin.key(), MitamaError::Unauthorized);
+
+        let registry = OracleRegistry {
+            admin: legacy.admin,
+            oracles: legacy.oracles,
+            min_consensus: legacy.min_consensus,
+            max_score_deviation: legacy.max_score_deviation,
+            created_at: legacy.created_at,
+            updated_at: Clock::get()?.unix_timestamp,
+            bump: legacy.bump,
+            version: ACCOUNT_VERSION,
+            consensus_mode: ConsensusMode::Median,
+            appeal_window: 0,
+            appeal_min_consensus: 0,
+            appeal_bond: 0,
+            genesis_hash,
+            commit_window: DEFAULT_COMMIT_WINDOW,
+            reveal_window: DEFAULT_REVEAL_WINDOW,
+        };
+        store_migrated(
+            &info,
+            &ctx.accounts.admin,
+            &ctx.accounts.system_program,
+            space,
+            &registry,
+        )?;
+
+        emit!(AccountMigrated {
+            account: info.key(),
+            version: ACCOUNT_VERSION,
+        });
+
+        Ok(())
+    }
+
+    /// Add an oracle to the registry
+    pub fn add_oracle(
+        ctx: Context<ManageOracle>,
+        oracle_pubkey: Pubkey,
+        oracle_type: OracleType,
+        weight: u16,
+    ) -> Result<()> {
+        let registry = &mut ctx.accounts.oracle_registry;
+
+        require!(ctx.accounts.admin.key() == registry.admin, MitamaError::Unauthorized);
+        require!(registry.oracles.len() < MAX_ORACLES, MitamaError::MaxOraclesReached);
+        require!(weight > 0, MitamaError::InvalidOracleWeight);
+        require!(
+            !registry.oracles.iter().any(|o| o.pubkey == oracle_pubkey),
+            MitamaError::DuplicateOracleSubmission
+        );
+
+        registry.oracles.push(OracleConfig {
+            pubkey: oracle_pubkey,
+            oracle_type,
+            weight,
+        });
+
+        let clock = Clock::get()?;
+        registry.updated_at = clock.unix_timestamp;
+
+        emit!(OracleAdded {
+            registry: registry.key(),
+            oracle: oracle_pubkey,
+            oracle_type_index: match oracle_type {
+                OracleType::Ed25519 => 0,
+                OracleType::Switchboard => 1,
+                OracleType::Custom => 2,
+            },
+            weight,
+        });
+
+        Ok(())
+    }
+
+    /// Remove an oracle from the registry
+    pub fn remove_oracle(
+        ctx: Context<ManageOracle>,
+        oracle_pubkey: Pubkey,
+    ) -> Result<()> {
+        let registry = &mut ctx.accounts.oracle_registry;
+
+        require!(ctx.accounts.admin.key() == registry.admin, MitamaError::Unauthorized);
+
+        let initial_len = registry.oracles.len();
+        registry.oracles.retain(|o| o.pubkey != oracle_pubkey);
+
+        require!(registry.oracles.len() < initial_len, MitamaError::OracleNotFound);
+
+        let clock = Clock::get()?;
+        registry.updated_at = clock.unix_timestamp;
+
+        emit!(OracleRemoved {
+            registry: registry.key(),
+            oracle: oracle_pubkey,
+        });
+
+        Ok(())
+    }
+
+    /// Select the algorithm used to reduce oracle submissions to a consensus score
+    pub fn set_consensus_mode(
+        ctx: Context<ManageOracle>,
+        consensus_mode: ConsensusMode,
+    ) -> Result<()> {
+        let registry = &mut ctx.accounts.oracle_registry;
+
+        require!(ctx.accounts.admin.key() == registry.admin, MitamaError::Unauthorized);
+
+        let old_mode = registry.consensus_mode;
+        registry.consensus_mode = consensus_mode;
+
+        let clock = Clock::get()?;
+        registry.updated_at = clock.unix_timestamp;
+
+        emit!(ConsensusModeUpdated {
+            registry: registry.key(),
+            old_mode,
+            new_mode: consensus_mode,
+        });
+
+        Ok(())
+    }
+
+    /// Set how long oracles have to commit scores once a hearing closes and
+    /// how long they then have to reveal them
+    pub fn set_scoring_windows(
+        ctx: Context<SetScoringWindows>,
+        commit_window: i64,
+        reveal_window: i64,
+    ) -> Result<()> {
+        let registry = &mut ctx.accounts.oracle_registry;
+
+        require!(ctx.accounts.admin.key() == registry.admin, MitamaError::Unauthorized);
+        require!(
+            (1..=MAX_SCORING_WINDOW).contains(&commit_window)
+                && (1..=MAX_SCORING_WINDOW).contains(&reveal_window),
+            MitamaError::InvalidScoringWindows
+        );
+        // Scoring must finish before a dispute can time out
+        require!(
+            DISPUTE_RESPONSE_WINDOW + commit_window + reveal_window
+                <= ctx.accounts.protocol_config.resolution_timeout,
+            MitamaError::InvalidScoringWindows
+        );
+
+        registry.commit_window = commit_window;
+        registry.reveal_window = reveal_window;
+
+        let clock = Clock::get()?;
+        registry.updated_at = clock.unix_timestamp;
+
+        emit!(ScoringWindowsUpdated {
+            registry: registry.key(),
+            commit_window,
+            reveal_window,
+        });
+
+        Ok(())
+    }
+
+    /// Configure the appeal round. A non-zero window holds every first-round
+    /// ruling in `PendingSettlement` for that long; an appeal costs
+    /// `appeal_bond` lamports and needs `appeal_min_consensus` oracles, which
+    /// must exceed the first-round quorum. A zero window disables appeals.
+    pub fn set_appeal_params(
+        ctx: Context<ManageOracle>,
+        appeal_window: i64,
+        appeal_min_consensus: u8,
+        appeal_bond: u64,
+    ) -> Result<()> {
+        let registry = &mut ctx.accounts.oracle_registry;
+
+        require!(ctx.accounts.admin.key() == registry.admin, MitamaError::Unauthorized);
+        require!(
+            (0..=MAX_APPEAL_WINDOW).contains(&appeal_window),
+            MitamaError::InvalidAppealParams
+        );
+        require!(
+            appeal_min_consensus > registry.min_consensus
+                && appeal_min_consensus as usize <= MAX_ORACLES,
+            MitamaError::InvalidAppealParams
+        );
+        require!(appeal_bond > 0, MitamaError::InvalidAppealParams);
+
+        registry.appeal_window = appeal_window;
+        registry.appeal_min_consensus = appeal_min_consensus;
+        registry.appeal_bond = appeal_bond;
+
+        let clock = Clock::get()?;
+        registry.updated_at = clock.unix_timestamp;
+
+        emit!(AppealParamsUpdated {
+            registry: registry.key(),
+            appeal_window,
+            appeal_min_consensus,
+            appeal_bond,
+        });
+
+        Ok(())
+    }
+
+    /// Open an oracle's stake account and bond lamports into it
+    pub fn stake_oracle(ctx: Context<StakeOracle>, amount: u64) -> Result<()> {
+        require!(amount > 0, MitamaError::InvalidAmount);
+
+        deposit_escrow_lamports(
+            &ctx.accounts.oracle,
+            &ctx.accounts.oracle_stake.to_account_info(),
+            amount,
+        )?;
+
+        let stake = &mut ctx.accounts.oracle_stake;
+        stake.oracle = ctx.accounts.oracle.key();
+        stake.amount = amount;
+        stake.rewards = 0;
+        stake.total_slashed = 0;
+        stake.created_at = Clock::get()?.unix_timestamp;
+        stake.bump = ctx.bumps.oracle_stake;
+        stake.locked_until = 0;
+
+        emit!(OracleStaked {
+            oracle: stake.oracle,
+            amount,
+            total_stake: stake.amount,
+        });
+
+        Ok(())
+    }
+
+    /// Bond more lamports into an existing oracle stake
+    pub fn add_oracle_stake(ctx: Context<AddOracleStake>, amount: u64) -> Result<()> {
+        require!(amount > 0, MitamaError::InvalidAmount);
+
+        deposit_escrow_lamports(
+            &ctx.accounts.oracle,
+            &ctx.accounts.oracle_stake.to_account_info(),
+            amount,
+        )?;
+
+        let stake = &mut ctx.accounts.oracle_stake;
+        stake.amount = stake.amount
+            .checked_add(amount)
+            .ok_or(MitamaError::ArithmeticOverflow)?;
+
+        emit!(OracleStaked {
+            oracle: stake.oracle,
+            amount,
+            total_stake: stake.amount,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw bonded stake. A registered oracle must keep the protocol
+    /// minimum bonded; it can withdraw everything once removed from the registry.
+    /// Stake is locked while a dispute the oracle committed to can be ruled on.
+    pub fn unstake_oracle(ctx: Context<UnstakeOracle>, amount: u64) -> Result<()> {
+        let stake = &mut ctx.accounts.oracle_stake;
+
+        require!(amount > 0 && amount <= stake.amount, MitamaError::InvalidAmount);
+        require!(
+            Clock::get()?.unix_timestamp >= stake.locked_until,
+            MitamaError::OracleStakeLocked
+        );
+
+        let remaining = stake.amount - amount;
+        if ctx.accounts.oracle_registry.is_registered(&stake.oracle) {
+            require!(
+                remaining >= ctx.accounts.protocol_config.min_oracle_stake,
+                MitamaError::InsufficientOracleStake
+            );
+        }
+
+        **stake.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.oracle.to_account_info().try_borrow_mut_lamports()? += amount;
+        stake.amount = remaining;
+
+        emit!(OracleUnstaked {
+            oracle: stake.oracle,
+            amount,
+            remaining_stake: remaining,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw the dispute fee share an oracle has earned
+    pub fn claim_oracle_rewards(ctx: Context<ClaimOracleRewards>) -> Result<()> {
+        let stake = &mut ctx.accounts.oracle_stake;
+        let amount = stake.rewards;
+
+        require!(amount > 0, MitamaError::InsufficientFunds);
+
+        **stake.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.oracle.to_account_info().try_borrow_mut_lamports()? += amount;
+        stake.rewards = 0;
+
+        emit!(OracleRewardsClaimed {
+            oracle: stake.oracle,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // Reputation Instructions
+    // ========================================================================
+
+    /// Initialize entity reputation
+    pub fn init_reputation(ctx: Context<InitReputation>) -> Result<()> {
+        let reputation = &mut ctx.accounts.reputation;
+        let clock = Clock::get()?;
+
+        reputation.entity = ctx.accounts.entity.key();
+        reputation.entity_type = EntityType::Agent;
+        reputation.total_transactions = 0;
+        reputation.disputes_filed = 0;
+        reputation.disputes_won = 0;
+        reputation.disputes_partial = 0;
+        reputation.disputes_lost = 0;
+        reputation.average_quality_received = 0;
+        reputation.reputation_score = 500;
+        reputation.created_at = clock.unix_timestamp;
+        reputation.last_updated = clock.unix_timestamp;
+        reputation.bump = ctx.bumps.reputation;
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // Protocol Config Instructions
+    // ========================================================================
+
+    /// Initialize protocol configuration with fee parameters
+    pub fn initialize_protocol_config(
+        ctx: Context<InitializeProtocolConfig>,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        let clock = Clock::get()?;
+
+        config.admin = ctx.accounts.admin.key();
+        config.treasury = treasury;
+        config.agreement_fee_bps = DEFAULT_AGREEMENT_FEE_BPS;
+        config.dispute_fee_bps = DEFAULT_DISPUTE_FEE_BPS;
+        config.dispute_base_fee = DEFAULT_DISPUTE_BASE_FEE;
+        config.identity_fee = DEFAULT_IDENTITY_FEE;
+        config.total_fees_collected = 0;
+        config.disputes_opened = 0;
+        config.refund_tiers = DEFAULT_REFUND_TIERS.to_vec();
+        config.strict_refunds = false;
+        config.oracle_reward_bps = DEFAULT_ORACLE_REWARD_BPS;
+        config.oracle_slash_bps = DEFAULT_ORACLE_SLASH_BPS;
+        config.min_oracle_stake = DEFAULT_MIN_ORACLE_STAKE;
+        config.resolution_timeout = DEFAULT_RESOLUTION_TIMEOUT;
+        config.timeout_policy = DisputeTimeoutPolicy::FullRefund;
+        config.lost_dispute_slash_bps = DEFAULT_LOST_DISPUTE_SLASH_BPS;
+        config.frivolous_dispute_slash_bps = DEFAULT_FRIVOLOUS_DISPUTE_SLASH_BPS;
+        config.slash_counterparty_bps = DEFAULT_SLASH_COUNTERPARTY_BPS;
+        config.reputation_authority = ctx.accounts.admin.key();
+        config.is_active = true;
+        config.created_at = clock.unix_timestamp;
+        config.updated_at = clock.unix_timestamp;
+        config.bump = ctx.bumps.protocol_config;
+        config.version = ACCOUNT_VERSION;
+
+        emit!(ProtocolConfigInitialized {
+            config: config.key(),
+            admin: config.admin,
+            treasury: config.treasury,
+        });
+
+        Ok(())
+    }
+
+    /// Bring the protocol config from an earlier release up to the current
+    /// layout, with dispute nonces starting from zero, the default refund
+    /// curve, oracle economics, dispute timeout and agent slashing policy.
+    /// The admin becomes reputation authority and pays the extra rent.
+    pub fn migrate_protocol_config(ctx: Context<MigrateProtocolConfig>) -> Result<()> {
+        let info = ctx.accounts.protocol_config.to_account_info();
+        let space = 8 + ProtocolConfig::INIT_SPACE;
+        let legacy: LegacyProtocolConfig =
+            load_legacy_account(&info, ProtocolConfig::DISCRIMINATOR, space)?;
+
+        require!(legacy.admin == ctx.accounts.admin.key(), MitamaError::Unauthorized);
+
+        let config = ProtocolConfig {
+            admin: legacy.admin,
+            treasury: legacy.treasury,
+            agreement_fee_bps: legacy.agreement_fee_bps,
+            dispute_fee_bps: legacy.dispute_fee_bps,
+            dispute_base_fee: legacy.dispute_base_fee,
+            identity_fee: legacy.identity_fee,
+            total_fees_collected: legacy.total_fees_collected,
+            is_active: legacy.is_active,
+            created_at: legacy.created_at,
+            updated_at: Clock::get()?.unix_timestamp,
+            bump: legacy.bump,
+            version: ACCOUNT_VERSION,
+            disputes_opened: 0,
+            refund_tiers: DEFAULT_REFUND_TIERS.to_vec(),
+            strict_refunds: false,
+            oracle_reward_bps: DEFAULT_ORACLE_REWARD_BPS,
+            oracle_slash_bps: DEFAULT_ORACLE_SLASH_BPS,
+            min_oracle_stake: DEFAULT_MIN_ORACLE_STAKE,
+            resolution_timeout: DEFAULT_RESOLUTION_TIMEOUT,
+            timeout_policy: DisputeTimeoutPolicy::FullRefund,
+            lost_dispute_slash_bps: DEFAULT_LOST_DISPUTE_SLASH_BPS,
+            frivolous_dispute_slash_bps: DEFAULT_FRIVOLOUS_DISPUTE_SLASH_BPS,
+            slash_counterparty_bps: DEFAULT_SLASH_COUNTERPARTY_BPS,
+            reputation_authority: legacy.admin,
+        };
+        store_migrated(
+            &info,
+            &ctx.accounts.admin,
+            &ctx.accounts.system_program,
+            space,
+            &config,
+        )?;
+
+        emit!(AccountMigrated {
+            account: info.key(),
+            version: ACCOUNT_VERSION,
+        });
+
+        Ok(())
+    }
+
+    /// Update protocol fee parameters (admin only)
+    pub fn update_protocol_config(
+        ctx: Context<UpdateProtocolConfig>,
+        new_treasury: Option<Pubkey>,
+        new_agreement_fee_bps: Option<u16>,
+        new_dispute_fee_bps: Option<u16>,
+        new_dispute_base_fee: Option<u64>,
+        new_identity_fee: Option<u64>,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            MitamaError::Unauthorized
+        );
+
+        if let Some(treasury) = new_treasury {
+            config.treasury = treasury;
+        }
+
+        if let Some(fee_bps) = new_agreement_fee_bps {
+            require!(fee_bps <= MAX_FEE_BPS, MitamaError::FeeTooHigh);
+            config.agreement_fee_bps = fee_bps;
+        }
+
+        if let Some(fee_bps) = new_dispute_fee_bps {
+            require!(fee_bps <= MAX_FEE_BPS, MitamaError::FeeTooHigh);
+            config.dispute_fee_bps = fee_bps;
+        }
+
+        if let Some(base_fee) = new_dispute_base_fee {
+            config.dispute_base_fee = base_fee;
+        }
+
+        if let Some(identity_fee) = new_identity_fee {
+            config.identity_fee = identity_fee;
+        }
+
+        let clock = Clock::get()?;
+        config.updated_at = clock.unix_timestamp;
+
+        emit!(ProtocolConfigUpdated {
+            config: config.key(),
+            admin: config.admin,
+        });
+
+        Ok(())
+    }
+
+    /// Replace the quality-to-refund tier table and choose whether rulings
+    /// that sign a different refund are rejected (admin only)
+    pub fn set_refund_tiers(
+        ctx: Context<UpdateProtocolConfig>,
+        tiers: Vec<RefundTier>,
+        strict_refunds: bool,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            MitamaError::Unauthorized
+        );
+        validate_refund_tiers(&tiers)?;
+
+        config.refund_tiers = tiers.clone();
+        config.strict_refunds = strict_refunds;
+
+        let clock = Clock::get()?;
+        config.updated_at = clock.unix_timestamp;
+
+        emit!(RefundTiersUpdated {
+            config: config.key(),
+            tiers,
+            strict_refunds,
+        });
+
+        Ok(())
+    }
+
+    /// Set how long a dispute may go without a ruling and how it is settled
+    /// once that deadline passes (admin only)
+    pub fn set_dispute_timeout(
+        ctx: Context<SetDisputeTimeout>,
+        resolution_timeout: i64,
+        timeout_policy: DisputeTimeoutPolicy,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        let registry = &ctx.accounts.oracle_registry;
+
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            MitamaError::Unauthorized
+        );
+        // A dispute must not time out while oracles can still commit or reveal
+        require!(
+            resolution_timeout
+                >= DISPUTE_RESPONSE_WINDOW + registry.commit_window + registry.reveal_window
+                && resolution_timeout <= MAX_RESOLUTION_TIMEOUT,
+            MitamaError::InvalidDisputeTimeout
+        );
+
+        config.resolution_timeout = resolution_timeout;
+        config.timeout_policy = timeout_policy;
+
+        let clock = Clock::get()?;
+        config.updated_at = clock.unix_timestamp;
+
+        emit!(DisputeTimeoutUpdated {
+            config: config.key(),
+            resolution_timeout,
+            timeout_policy,
+        });
+
+        Ok(())
+    }
+
+    /// Set how much of an agent's stake is slashed when it loses a dispute it
+    /// filed (refund below half) or files a frivolous one (no refund), and the
+    /// share of each slash paid to the API (admin only)
+    pub fn set_agent_slashing(
+        ctx: Context<UpdateProtocolConfig>,
+        lost_dispute_slash_bps: u16,
+        frivolous_dispute_slash_bps: u16,
+        slash_counterparty_bps: u16,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            MitamaError::Unauthorized
+        );
+        require!(
+            lost_dispute_slash_bps <= 10_000
+                && frivolous_dispute_slash_bps <= 10_000
+                && slash_counterparty_bps <= 10_000,
+            MitamaError::InvalidSlashingPolicy
+        );
+
+        config.lost_dispute_slash_bps = lost_dispute_slash_bps;
+        config.frivolous_dispute_slash_bps = frivolous_dispute_slash_bps;
+        config.slash_counterparty_bps = slash_counterparty_bps;
+
+        let clock = Clock::get()?;
+        config.updated_at = clock.unix_timestamp;
+
+        emit!(AgentSlashingPolicyUpdated {
+            config: config.key(),
+            lost_dispute_slash_bps,
+            frivolous_dispute_slash_bps,
+            slash_counterparty_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Set the share of each dispute fee paid to oracles, the share of an
+    /// outlier's stake slashed per ruling and the stake needed to submit
+    /// scores (admin only)
+    pub fn set_oracle_stake_params(
+        ctx: Context<UpdateProtocolConfig>,
+        oracle_reward_bps: u16,
+        oracle_slash_bps: u16,
+        min_oracle_stake: u64,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            MitamaError::Unauthorized
+        );
+        require!(
+            oracle_reward_bps <= 10_000 && oracle_slash_bps <= 10_000,
+            MitamaError::InvalidOracleStakeParams
+        );
+
+        config.oracle_reward_bps = oracle_reward_bps;
+        config.oracle_slash_bps = oracle_slash_bps;
+        config.min_oracle_stake = min_oracle_stake;
+
+        let clock = Clock::get()?;
+        config.updated_at = clock.unix_timestamp;
+
+        emit!(OracleStakeParamsUpdated {
+            config: config.key(),
+            oracle_reward_bps,
+            oracle_slash_bps,
+            min_oracle_stake,
+        });
+
+        Ok(())
+    }
+
+    /// Set the signer allowed to adjust agent reputation (admin only)
+    pub fn set_reputation_authority(
+        ctx: Context<UpdateProtocolConfig>,
+        reputation_authority: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            MitamaError::Unauthorized
+        );
+
+        config.reputation_authority = reputation_authority;
+
+        let clock = Clock::get()?;
+        config.updated_at = clock.unix_timestamp;
+
+        emit!(ReputationAuthorityUpdated {
+            config: config.key(),
+            reputation_authority,
+        });
+
+        Ok(())
+    }
+
+    /// Transfer protocol admin to new address
+    pub fn transfer_protocol_admin(
+        ctx: Context<UpdateProtocolConfig>,
+        new_admin: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            MitamaError::Unauthorized
+        );
+
+        config.admin = new_admin;
+
+        let clock = Clock::get()?;
+        config.updated_at = clock.unix_timestamp;
+
+        Ok(())
+    }
+
+    /// Withdraw accumulated fees to treasury
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
+        let config = &ctx.accounts.protocol_config;
+
+        require!(
+            ctx.accounts.admin.key() == config.admin,
+            MitamaError::Unauthorized
+        );
+
+        let fee_vault_balance = ctx.accounts.fee_vault.lamports();
+        let rent = Rent::get()?;
+        let min_balance = rent.minimum_balance(0);
+
+        let withdrawable = fee_vault_balance.saturating_sub(min_balance);
+        require!(withdrawable > 0, MitamaError::InsufficientFunds);
+
+        **ctx.accounts.fee_vault.try_borrow_mut_lamports()? -= withdrawable;
+        **ctx.accounts.treasury.try_borrow_mut_lamports()? += withdrawable;
+
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Account Structs
+// ============================================================================
+
+#[derive(Accounts)]
+#[instruction(name: String)]
+pub struct CreateAgent<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + AgentIdentity::INIT_SPACE,
+        seeds = [b"agent", owner.key().as_ref()],
+        bump
+    )]
+    pub agent: Account<'info, AgentIdentity>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Fee vault PDA
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateAgentIdentity<'info> {
+    /// CHECK: Agent identity in an earlier layout, decoded by the handler
+    #[account(
+        mut,
+        seeds = [b"agent", owner.key().as_ref()],
+        bump,
+        owner = crate::ID
+    )]
+    pub agent: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DeactivateAgent<'info> {
+    #[account(
+        mut,
+        seeds = [b"agent", owner.key().as_ref()],
+        bump = agent.bump
+    )]
+    pub agent: Account<'info, AgentIdentity>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct IncreaseStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"agent", owner.key().as_ref()],
+        bump = agent.bump
+    )]
+    pub agent: Account<'info, AgentIdentity>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageAgentStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"agent", owner.key().as_ref()],
+        bump = agent.bump
+    )]
+    pub agent: Account<'info, AgentIdentity>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAgentRep<'info> {
+    #[account(
+        mut,
+        seeds = [b"agent", agent.owner.as_ref()],
+        bump = agent.bump
+    )]
+    pub agent: Account<'info, AgentIdentity>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, time_lock: i64, transaction_id: String)]
+pub struct InitializeEscrow<'info> {
+    #[account(
+        init,
+        payer = agent,
+        space = 8 + Escrow::INIT_SPACE,
+        seeds = [b"escrow", agent.key().as_ref(), transaction_id.as_bytes()],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(mut)]
+    pub agent: Signer<'info>,
+
+    /// CHECK: API wallet address
+    pub api: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Fee vault PDA
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// Token vault owned by the escrow PDA, created here and closed on settlement
+    #[account(
+        init,
+        payer = agent,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub agent_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// CHECK: Switchboard pull feed that may settle a dispute, validated in handler
+    pub switchboard_feed: Option<AccountInfo<'info>>,
+
+    /// Agent identity to bind the escrow to; it must be active
+    #[account(
+        mut,
+        seeds = [b"agent", agent.key().as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
+
+    /// Registry the Switchboard feed must be registered in, required with a feed
+    #[account(
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
+    )]
+    pub oracle_registry: Option<Account<'info, OracleRegistry>>,
+}
+
+#[derive(Accounts)]
+#[instruction(transaction_id: String)]
+pub struct InitializeMilestoneEscrow<'info> {
+    #[account(
+        init,
+        payer = agent,
+        space = 8 + Escrow::INIT_SPACE,
+        seeds = [b"escrow", agent.key().as_ref(), transaction_id.as_bytes()],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(mut)]
+    pub agent: Signer<'info>,
+
+    /// CHECK: API wallet address
+    pub api: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Fee vault PDA
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// Token vault owned by the escrow PDA, closed once every milestone settles
+    #[account(
+        init,
+        payer = agent,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub agent_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// Agent identity to bind the escrow to; it must be active
+    #[account(
+        mut,
+        seeds = [b"agent", agent.key().as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
+}
+
+#[derive(Accounts)]
+#[instruction(subscription_id: String)]
+pub struct CreateSubscription<'info> {
+    #[account(
+        init,
+        payer = agent,
+        space = 8 + Subscription::INIT_SPACE,
+        seeds = [b"subscription", agent.key().as_ref(), subscription_id.as_bytes()],
+        bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    #[account(mut)]
+    pub agent: Signer<'info>,
+
+    /// CHECK: API wallet address
+    pub api: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// Token vault owned by the subscription PDA, funding every period
+    #[account(
+        init,
+        payer = agent,
+        seeds = [b"vault", subscription.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = subscription,
+        token::token_program = token_program,
+    )]
+    pub subscription_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub agent_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// Agent identity each period's escrow binds to; it must be active
+    #[account(
+        seeds = [b"agent", agent.key().as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
+}
+
+#[derive(Accounts)]
+#[instruction(transaction_id: String)]
+pub struct OpenSubscriptionPeriod<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription", subscription.agent.as_ref(), subscription.subscription_id.as_bytes()],
+        bump = subscription.bump
+    )]
+    pub subscription: Box<Account<'info, Subscription>>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Escrow::INIT_SPACE,
+        seeds = [b"escrow", subscription.agent.as_ref(), transaction_id.as_bytes()],
+        bump
+    )]
+    pub escrow: Box<Account<'info, Escrow>>,
+
+    /// Cranker, reimbursed for the rent of the new accounts
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    /// CHECK: Fee vault PDA
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub subscription_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token vault for the period's escrow
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// Agent identity of a bound subscription, required when it is bound
+    #[account(
+        mut,
+        seeds = [b"agent", subscription.agent.as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Box<Account<'info, AgentIdentity>>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSubscription<'info> {
+    #[account(
+        mut,
+        seeds = [b"subscription", subscription.agent.as_ref(), subscription.subscription_id.as_bytes()],
+        bump = subscription.bump,
+        close = agent
+    )]
+    pub subscription: Account<'info, Subscription>,
+
+    /// Agent or API of the subscription
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = agent.key() == subscription.agent @ MitamaError::Unauthorized
+    )]
+    pub agent: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub subscription_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub agent_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseFunds<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(mut)]
+    pub agent: Signer<'info>,
+
+    /// CHECK: API wallet address, checked against the escrow
+    #[account(
+        mut,
+        constraint = api.key() == escrow.api @ MitamaError::Unauthorized
+    )]
+    pub api: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Agent reputation, updated when the agent has one
+    #[account(
+        mut,
+        seeds = [b"reputation", escrow.agent.as_ref()],
+        bump = agent_reputation.bump
+    )]
+    pub agent_reputation: Option<Account<'info, EntityReputation>>,
+
+    /// API reputation, updated when the API has one
+    #[account(
+        mut,
+        seeds = [b"reputation", escrow.api.as_ref()],
+        bump = api_reputation.bump
+    )]
+    pub api_reputation: Option<Account<'info, EntityReputation>>,
+
+    #[account(mut)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub api_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// CHECK: Escrow agent wallet, receives vault rent when a non-agent releases
+    #[account(
+        mut,
+        constraint = agent_wallet.key() == escrow.agent @ MitamaError::Unauthorized
+    )]
+    pub agent_wallet: Option<AccountInfo<'info>>,
+
+    /// Agent identity the escrow is bound to, required when it is bound
+    #[account(
+        mut,
+        seeds = [b"agent", escrow.agent.as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawVested<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        constraint = api.key() == escrow.api @ MitamaError::Unauthorized
+    )]
+    pub api: Signer<'info>,
+
+    /// Escrow agent, receives vault rent once the stream is fully withdrawn
+    #[account(
+        mut,
+        constraint = agent.key() == escrow.agent @ MitamaError::Unauthorized
+    )]
+    pub agent: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Agent reputation, updated when the agent has one
+    #[account(
+        mut,
+        seeds = [b"reputation", escrow.agent.as_ref()],
+        bump = agent_reputation.bump
+    )]
+    pub agent_reputation: Option<Account<'info, EntityReputation>>,
+
+    /// API reputation, updated when the API has one
+    #[account(
+        mut,
+        seeds = [b"reputation", escrow.api.as_ref()],
+        bump = api_reputation.bump
+    )]
+    pub api_reputation: Option<Account<'info, EntityReputation>>,
+
+    #[account(mut)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub api_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// Agent identity the escrow is bound to, required when it is bound
+    #[account(
+        mut,
+        seeds = [b"agent", escrow.agent.as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
+}
+
+#[derive(Accounts)]
+pub struct MarkDisputed<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        init,
+        payer = agent,
+        space = 8 + DisputeRecord::INIT_SPACE,
+        seeds = [b"dispute", escrow.key().as_ref()],
+        bump
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
+
+    #[account(
+        mut,
+        seeds = [b"reputation", agent.key().as_ref()],
+        bump = reputation.bump
+    )]
+    pub reputation: Account<'info, EntityReputation>,
+
+    #[account(mut)]
+    pub agent: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Fee vault PDA
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Agent identity the escrow is bound to, required when it is bound
+    #[account(
+        mut,
+        seeds = [b"agent", escrow.agent.as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
+}
+
+#[derive(Accounts)]
+#[instruction(milestone_index: u8)]
+pub struct DisputeMilestone<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        init,
+        payer = agent,
+        space = 8 + DisputeRecord::INIT_SPACE,
+        seeds = [b"dispute", escrow.key().as_ref(), &[milestone_index]],
+        bump
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
+
+    #[account(
+        mut,
+        seeds = [b"reputation", agent.key().as_ref()],
+        bump = reputation.bump
+    )]
+    pub reputation: Account<'info, EntityReputation>,
+
+    #[account(mut)]
+    pub agent: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Fee vault PDA
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Agent identity the escrow is bound to, required when it is bound
+    #[account(
+        mut,
+        seeds = [b"agent", escrow.agent.as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitEvidence<'info> {
+    #[account(
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        constraint = dispute_record.escrow == escrow.key() @ MitamaError::DisputeRecordMismatch
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
+
+    /// Agent or API of the escrow
+    pub submitter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseDisputeRecord<'info> {
+    #[account(
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        constraint = dispute_record.escrow == escrow.key() @ MitamaError::DisputeRecordMismatch,
+        close = agent
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
+
+    #[account(
+        mut,
+        constraint = agent.key() == escrow.agent @ MitamaError::Unauthorized
+    )]
+    pub agent: SystemAccount<'info>,
+
+    /// CHECK: Fee vault PDA
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    /// Evidence record of the dispute being resolved
+    #[account(
+        constraint = dispute_record.escrow == escrow.key() @ MitamaError::DisputeRecordMismatch
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
+
+    #[account(
+        mut,
+        constraint = agent.key() == escrow.agent @ MitamaError::Unauthorized
+    )]
+    pub agent: SystemAccount<'info>,
+
+    /// CHECK: API wallet address, checked against the escrow
+    #[account(
+        mut,
+        constraint = api.key() == escrow.api @ MitamaError::Unauthorized
+    )]
+    pub api: AccountInfo<'info>,
+
+    /// CHECK: Verifier oracle public key, checked against the oracle registry
+    pub verifier: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"oracle_stake", verifier.key().as_ref()],
+        bump = verifier_stake.bump
+    )]
+    pub verifier_stake: Account<'info, OracleStake>,
+
+    /// CHECK: Instructions sysvar
+    #[account(address = INSTRUCTIONS_ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// CHECK: Fee vault PDA, receives its share of agent stake slashes
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"reputation", agent.key().as_ref()],
+        bump = agent_reputation.bump
+    )]
+    pub agent_reputation: Account<'info, EntityReputation>,
+
+    #[account(
+        mut,
+        seeds = [b"reputation", api.key().as_ref()],
+        bump = api_reputation.bump
+    )]
+    pub api_reputation: Account<'info, EntityReputation>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(mut)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub agent_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub api_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// Agent identity the escrow is bound to, required when it is bound
+    #[account(
+        mut,
+        seeds = [b"agent", escrow.agent.as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump,
+        close = agent
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        constraint = agent.key() == escrow.agent @ MitamaError::Unauthorized
+    )]
+    pub agent: Signer<'info>,
+
+    /// CHECK: Dispute record address of the escrow, closed in handler if it exists
+    #[account(
+        mut,
+        seeds = [b"dispute", escrow.key().as_ref()],
+        bump
+    )]
+    pub dispute_record: UncheckedAccount<'info>,
+
+    /// CHECK: Fee vault PDA, receives any unclaimed oracle rewards
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(transaction_id: String)]
+pub struct MigrateLegacyEscrow<'info> {
+    /// CHECK: Escrow in the legacy layout, decoded and closed by the handler
+    #[account(
+        mut,
+        seeds = [b"escrow", transaction_id.as_bytes()],
+        bump,
+        owner = crate::ID
+    )]
+    pub legacy_escrow: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = agent,
+        space = 8 + Escrow::INIT_SPACE,
+        seeds = [b"escrow", agent.key().as_ref(), transaction_id.as_bytes()],
+        bump
+    )]
+    pub escrow: Box<Account<'info, Escrow>>,
+
+    #[account(mut)]
+    pub agent: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(mut)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub legacy_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Token vault for the migrated escrow
+    #[account(
+        init,
+        payer = agent,
+        seeds = [b"vault", escrow.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = escrow,
+        token::token_program = token_program,
+    )]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// Dispute record opened for a legacy escrow that is still disputed
+    #[account(
+        init,
+        payer = agent,
+        space = 8 + DisputeRecord::INIT_SPACE,
+        seeds = [b"dispute", escrow.key().as_ref()],
+        bump
+    )]
+    pub dispute_record: Option<Box<Account<'info, DisputeRecord>>>,
+
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Option<Box<Account<'info, ProtocolConfig>>>,
+}
+
+#[derive(Accounts)]
+pub struct CommitOracleScore<'info> {
+    #[account(
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        seeds = [b"dispute", escrow.key().as_ref()],
+        bump = dispute_record.bump
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
+
+    #[account(
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"oracle_stake", oracle.key().as_ref()],
+        bump = oracle_stake.bump
+    )]
+    pub oracle_stake: Account<'info, OracleStake>,
+
+    pub oracle: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevealOracleScore<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        seeds = [b"dispute", escrow.key().as_ref()],
+        bump = dispute_record.bump
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
+
+    #[account(
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    pub oracle: Signer<'info>,
+
+    /// CHECK: Instructions sysvar
+    #[account(address = INSTRUCTIONS_ID)]
+    pub instructions_sysvar: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeMultiOracleResolution<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        constraint = agent.key() == escrow.agent @ MitamaError::Unauthorized
+    )]
+    pub agent: SystemAccount<'info>,
+
+    /// CHECK: API wallet address, checked against the escrow
+    #[account(
+        mut,
+        constraint = api.key() == escrow.api @ MitamaError::Unauthorized
+    )]
+    pub api: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"dispute", escrow.key().as_ref()],
+        bump = dispute_record.bump
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
+
+    /// CHECK: Fee vault PDA, receives slashed oracle stake
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+
+    /// Appeal against the first-round ruling, required once the escrow is appealed
+    #[account(
+        mut,
+        seeds = [b"appeal", escrow.key().as_ref()],
+        bump = appeal.bump
+    )]
+    pub appeal: Option<Account<'info, Appeal>>,
+
+    #[account(
+        mut,
+        seeds = [b"reputation", agent.key().as_ref()],
+        bump = agent_reputation.bump
+    )]
+    pub agent_reputation: Account<'info, EntityReputation>,
 
-        if let Some(identity_fee) = new_identity_fee {
-            config.identity_fee = identity_fee;
-        }
+    #[account(
+        mut,
+        seeds = [b"reputation", api.key().as_ref()],
+        bump = api_reputation.bump
+    )]
+    pub api_reputation: Account<'info, EntityReputation>,
 
-        let clock = Clock::get()?;
-        config.updated_at = clock.unix_timestamp;
+    pub system_program: Program<'info, System>,
 
-        emit!(ProtocolConfigUpdated {
-            config: config.key(),
-            admin: config.admin,
-        });
+    #[account(mut)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
 
-        Ok(())
-    }
+    #[account(mut)]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
 
-    /// Transfer protocol admin to new address
-    pub fn transfer_protocol_admin(
-        ctx: Context<UpdateProtocolConfig>,
-        new_admin: Pubkey,
-    ) -> Result<()> {
-        let config = &mut ctx.accounts.protocol_config;
+    #[account(mut)]
+    pub agent_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
 
-        require!(
-            ctx.accounts.admin.key() == config.admin,
-            MitamaError::Unauthorized
-        );
+    #[account(mut)]
+    pub api_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
 
-        config.admin = new_admin;
+    pub token_program: Option<Interface<'info, TokenInterface>>,
 
-        let clock = Clock::get()?;
-        config.updated_at = clock.unix_timestamp;
+    /// Agent identity the escrow is bound to, required when it is bound
+    #[account(
+        mut,
+        seeds = [b"agent", escrow.agent.as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
+}
 
-        Ok(())
-    }
+#[derive(Accounts)]
+pub struct FileAppeal<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
 
-    /// Withdraw accumulated fees to treasury
-    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
-        let config = &ctx.accounts.protocol_config;
+    #[account(
+        init,
+        payer = appellant,
+        space = 8 + Appeal::INIT_SPACE,
+        seeds = [b"appeal", escrow.key().as_ref()],
+        bump
+    )]
+    pub appeal: Account<'info, Appeal>,
 
-        require!(
-            ctx.accounts.admin.key() == config.admin,
-            MitamaError::Unauthorized
-        );
+    #[account(
+        mut,
+        seeds = [b"dispute", escrow.key().as_ref()],
+        bump = dispute_record.bump
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
 
-        let fee_vault_balance = ctx.accounts.fee_vault.lamports();
-        let rent = Rent::get()?;
-        let min_balance = rent.minimum_balance(0);
+    #[account(
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
 
-        let withdrawable = fee_vault_balance.saturating_sub(min_balance);
-        require!(withdrawable > 0, MitamaError::InsufficientFunds);
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
 
-        **ctx.accounts.fee_vault.try_borrow_mut_lamports()? -= withdrawable;
-        **ctx.accounts.treasury.try_borrow_mut_lamports()? += withdrawable;
+    #[account(
+        mut,
+        constraint = appellant.key() == escrow.agent || appellant.key() == escrow.api @ MitamaError::Unauthorized
+    )]
+    pub appellant: Signer<'info>,
 
-        Ok(())
-    }
+    pub system_program: Program<'info, System>,
 }
 
-// ============================================================================
-// Account Structs
-// ============================================================================
-
 #[derive(Accounts)]
-#[instruction(name: String)]
-pub struct CreateAgent<'info> {
+pub struct FinalizeSettlement<'info> {
     #[account(
-        init,
-        payer = owner,
-        space = 8 + AgentIdentity::INIT_SPACE,
-        seeds = [b"agent", owner.key().as_ref()],
-        bump
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
     )]
-    pub agent: Account<'info, AgentIdentity>,
+    pub escrow: Account<'info, Escrow>,
 
-    #[account(mut)]
-    pub owner: Signer<'info>,
+    /// Record holding the reward pool and commitments of the held ruling
+    #[account(
+        mut,
+        seeds = [b"dispute", escrow.key().as_ref()],
+        bump = dispute_record.bump
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
+
+    #[account(
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+
+    #[account(
+        mut,
+        constraint = agent.key() == escrow.agent @ MitamaError::Unauthorized
+    )]
+    pub agent: SystemAccount<'info>,
 
+    /// CHECK: API wallet address, checked against the escrow
     #[account(
         mut,
+        constraint = api.key() == escrow.api @ MitamaError::Unauthorized
+    )]
+    pub api: AccountInfo<'info>,
+
+    #[account(
         seeds = [b"protocol_config"],
         bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
 
-    /// CHECK: Fee vault PDA
+    /// CHECK: Fee vault PDA, receives oracle and agent stake slashes
     #[account(
         mut,
         seeds = [b"fee_vault"],
@@ -1171,212 +6484,354 @@ pub struct CreateAgent<'info> {
     )]
     pub fee_vault: AccountInfo<'info>,
 
-    pub system_program: Program<'info, System>,
-}
+    #[account(
+        mut,
+        seeds = [b"reputation", agent.key().as_ref()],
+        bump = agent_reputation.bump
+    )]
+    pub agent_reputation: Account<'info, EntityReputation>,
 
-#[derive(Accounts)]
-pub struct DeactivateAgent<'info> {
     #[account(
         mut,
-        seeds = [b"agent", owner.key().as_ref()],
-        bump = agent.bump
+        seeds = [b"reputation", api.key().as_ref()],
+        bump = api_reputation.bump
     )]
-    pub agent: Account<'info, AgentIdentity>,
+    pub api_reputation: Account<'info, EntityReputation>,
+
+    pub system_program: Program<'info, System>,
 
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub agent_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub api_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// Agent identity the escrow is bound to, required when it is bound
+    #[account(
+        mut,
+        seeds = [b"agent", escrow.agent.as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateAgentRep<'info> {
+pub struct ResolveDisputeTimeout<'info> {
     #[account(
         mut,
-        seeds = [b"agent", agent.owner.as_ref()],
-        bump = agent.bump
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
     )]
-    pub agent: Account<'info, AgentIdentity>,
+    pub escrow: Account<'info, Escrow>,
 
-    pub authority: Signer<'info>,
+    /// Record of the escrow's dispute or of one of its milestone disputes
+    #[account(
+        mut,
+        constraint = dispute_record.escrow == escrow.key() @ MitamaError::DisputeRecordMismatch
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// Appeal whose bond is returned, required once the escrow is appealed
+    #[account(
+        mut,
+        seeds = [b"appeal", escrow.key().as_ref()],
+        bump = appeal.bump
+    )]
+    pub appeal: Option<Account<'info, Appeal>>,
+
+    #[account(
+        mut,
+        constraint = agent.key() == escrow.agent @ MitamaError::Unauthorized
+    )]
+    pub agent: SystemAccount<'info>,
+
+    /// CHECK: API wallet address, checked against the escrow
+    #[account(
+        mut,
+        constraint = api.key() == escrow.api @ MitamaError::Unauthorized
+    )]
+    pub api: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"reputation", agent.key().as_ref()],
+        bump = agent_reputation.bump
+    )]
+    pub agent_reputation: Account<'info, EntityReputation>,
+
+    #[account(
+        mut,
+        seeds = [b"reputation", api.key().as_ref()],
+        bump = api_reputation.bump
+    )]
+    pub api_reputation: Account<'info, EntityReputation>,
+
+    pub system_program: Program<'info, System>,
+
+    #[account(mut)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub agent_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub api_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// Agent identity the escrow is bound to, required when it is bound
+    #[account(
+        mut,
+        seeds = [b"agent", escrow.agent.as_ref()],
+        bump = agent_identity.bump
+    )]
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
 }
 
 #[derive(Accounts)]
-#[instruction(amount: u64, time_lock: i64, transaction_id: String)]
-pub struct InitializeEscrow<'info> {
+pub struct ResolveDisputeSwitchboard<'info> {
     #[account(
-        init,
-        payer = agent,
-        space = 8 + Escrow::INIT_SPACE,
-        seeds = [b"escrow", transaction_id.as_bytes()],
-        bump
+        mut,
+        seeds = [b"escrow", escrow.agent.as_ref(), escrow.transaction_id.as_bytes()],
+        bump = escrow.bump
     )]
     pub escrow: Account<'info, Escrow>,
 
-    #[account(mut)]
-    pub agent: Signer<'info>,
+    #[account(
+        seeds = [b"dispute", escrow.key().as_ref()],
+        bump = dispute_record.bump
+    )]
+    pub dispute_record: Account<'info, DisputeRecord>,
 
-    /// CHECK: API wallet address
-    pub api: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = agent.key() == escrow.agent @ MitamaError::Unauthorized
+    )]
+    pub agent: SystemAccount<'info>,
 
+    /// CHECK: API wallet address, checked against the escrow
     #[account(
         mut,
+        constraint = api.key() == escrow.api @ MitamaError::Unauthorized
+    )]
+    pub api: AccountInfo<'info>,
+
+    /// CHECK: Switchboard pull feed bound to the escrow, parsed in handler
+    #[account(
+        constraint = escrow.switchboard_feed == Some(switchboard_feed.key()) @ MitamaError::InvalidSwitchboardFeed
+    )]
+    pub switchboard_feed: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump,
+        constraint = oracle_registry.is_registered_as(&switchboard_feed.key(), OracleType::Switchboard)
+            @ MitamaError::UnregisteredSwitchboardFeed
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+
+    #[account(
         seeds = [b"protocol_config"],
         bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
 
-    /// CHECK: Fee vault PDA
+    /// CHECK: Fee vault PDA, receives its share of agent stake slashes
+    #[account(
+        mut,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"reputation", agent.key().as_ref()],
+        bump = agent_reputation.bump
+    )]
+    pub agent_reputation: Account<'info, EntityReputation>,
+
     #[account(
         mut,
-        seeds = [b"fee_vault"],
-        bump
+        seeds = [b"reputation", api.key().as_ref()],
+        bump = api_reputation.bump
     )]
-    pub fee_vault: AccountInfo<'info>,
+    pub api_reputation: Account<'info, EntityReputation>,
 
     pub system_program: Program<'info, System>,
 
-    pub token_mint: Option<Account<'info, Mint>>,
+    #[account(mut)]
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
 
     #[account(mut)]
-    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
+    pub escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(mut)]
-    pub agent_token_account: Option<Account<'info, TokenAccount>>,
+    pub agent_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Option<Program<'info, Token>>,
-    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
-}
+    #[account(mut)]
+    pub api_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
 
-#[derive(Accounts)]
-pub struct ReleaseFunds<'info> {
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    /// Agent identity the escrow is bound to, required when it is bound
     #[account(
         mut,
-        seeds = [b"escrow", escrow.transaction_id.as_bytes()],
-        bump = escrow.bump
+        seeds = [b"agent", escrow.agent.as_ref()],
+        bump = agent_identity.bump
     )]
-    pub escrow: Account<'info, Escrow>,
+    pub agent_identity: Option<Account<'info, AgentIdentity>>,
+}
 
-    #[account(mut)]
-    pub agent: Signer<'info>,
+#[derive(Accounts)]
+pub struct InitializeOracleRegistry<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + OracleRegistry::INIT_SPACE,
+        seeds = [b"oracle_registry"],
+        bump
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
 
-    /// CHECK: API wallet address
     #[account(mut)]
-    pub api: AccountInfo<'info>,
+    pub admin: Signer<'info>,
 
     pub system_program: Program<'info, System>,
+}
 
-    #[account(mut)]
-    pub escrow_token_account: Option<Account<'info, TokenAccount>>,
+#[derive(Accounts)]
+pub struct MigrateOracleRegistry<'info> {
+    /// CHECK: Oracle registry in an earlier layout, decoded by the handler
+    #[account(
+        mut,
+        seeds = [b"oracle_registry"],
+        bump,
+        owner = crate::ID
+    )]
+    pub oracle_registry: UncheckedAccount<'info>,
 
     #[account(mut)]
-    pub api_token_account: Option<Account<'info, TokenAccount>>,
+    pub admin: Signer<'info>,
 
-    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct MarkDisputed<'info> {
+pub struct ManageOracle<'info> {
     #[account(
         mut,
-        seeds = [b"escrow", escrow.transaction_id.as_bytes()],
-        bump = escrow.bump
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
     )]
-    pub escrow: Account<'info, Escrow>,
+    pub oracle_registry: Account<'info, OracleRegistry>,
 
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetScoringWindows<'info> {
     #[account(
         mut,
-        seeds = [b"reputation", agent.key().as_ref()],
-        bump = reputation.bump
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
     )]
-    pub reputation: Account<'info, EntityReputation>,
-
-    #[account(mut)]
-    pub agent: Signer<'info>,
+    pub oracle_registry: Account<'info, OracleRegistry>,
 
     #[account(
-        mut,
         seeds = [b"protocol_config"],
         bump = protocol_config.bump
     )]
     pub protocol_config: Account<'info, ProtocolConfig>,
 
-    /// CHECK: Fee vault PDA
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct StakeOracle<'info> {
     #[account(
-        mut,
-        seeds = [b"fee_vault"],
+        init,
+        payer = oracle,
+        space = 8 + OracleStake::INIT_SPACE,
+        seeds = [b"oracle_stake", oracle.key().as_ref()],
         bump
     )]
-    pub fee_vault: AccountInfo<'info>,
+    pub oracle_stake: Account<'info, OracleStake>,
+
+    #[account(mut)]
+    pub oracle: Signer<'info>,
 
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ResolveDispute<'info> {
+pub struct AddOracleStake<'info> {
     #[account(
         mut,
-        seeds = [b"escrow", escrow.transaction_id.as_bytes()],
-        bump = escrow.bump
+        seeds = [b"oracle_stake", oracle.key().as_ref()],
+        bump = oracle_stake.bump
     )]
-    pub escrow: Account<'info, Escrow>,
+    pub oracle_stake: Account<'info, OracleStake>,
 
     #[account(mut)]
-    pub agent: SystemAccount<'info>,
-
-    /// CHECK: API wallet address
-    #[account(mut)]
-    pub api: AccountInfo<'info>,
-
-    /// CHECK: Verifier oracle public key
-    pub verifier: AccountInfo<'info>,
+    pub oracle: Signer<'info>,
 
-    /// CHECK: Instructions sysvar
-    #[account(address = INSTRUCTIONS_ID)]
-    pub instructions_sysvar: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+pub struct UnstakeOracle<'info> {
     #[account(
         mut,
-        seeds = [b"reputation", agent.key().as_ref()],
-        bump = agent_reputation.bump
+        seeds = [b"oracle_stake", oracle.key().as_ref()],
+        bump = oracle_stake.bump
     )]
-    pub agent_reputation: Account<'info, EntityReputation>,
+    pub oracle_stake: Account<'info, OracleStake>,
 
     #[account(
-        mut,
-        seeds = [b"reputation", api.key().as_ref()],
-        bump = api_reputation.bump
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
     )]
-    pub api_reputation: Account<'info, EntityReputation>,
-
-    pub system_program: Program<'info, System>,
-}
+    pub oracle_registry: Account<'info, OracleRegistry>,
 
-#[derive(Accounts)]
-pub struct InitializeOracleRegistry<'info> {
     #[account(
-        init,
-        payer = admin,
-        space = 8 + OracleRegistry::INIT_SPACE,
-        seeds = [b"oracle_registry"],
-        bump
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
     )]
-    pub oracle_registry: Account<'info, OracleRegistry>,
+    pub protocol_config: Account<'info, ProtocolConfig>,
 
     #[account(mut)]
-    pub admin: Signer<'info>,
-
-    pub system_program: Program<'info, System>,
+    pub oracle: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct ManageOracle<'info> {
+pub struct ClaimOracleRewards<'info> {
     #[account(
         mut,
-        seeds = [b"oracle_registry"],
-        bump = oracle_registry.bump
+        seeds = [b"oracle_stake", oracle.key().as_ref()],
+        bump = oracle_stake.bump
     )]
-    pub oracle_registry: Account<'info, OracleRegistry>,
+    pub oracle_stake: Account<'info, OracleStake>,
 
-    pub admin: Signer<'info>,
+    #[account(mut)]
+    pub oracle: Signer<'info>,
 }
 
 #[derive(Accounts)]
@@ -1427,6 +6882,23 @@ pub struct InitializeProtocolConfig<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct MigrateProtocolConfig<'info> {
+    /// CHECK: Protocol config in an earlier layout, decoded by the handler
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump,
+        owner = crate::ID
+    )]
+    pub protocol_config: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct UpdateProtocolConfig<'info> {
     #[account(
@@ -1439,6 +6911,24 @@ pub struct UpdateProtocolConfig<'info> {
     pub admin: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct SetDisputeTimeout<'info> {
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump
+    )]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    #[account(
+        seeds = [b"oracle_registry"],
+        bump = oracle_registry.bump
+    )]
+    pub oracle_registry: Account<'info, OracleRegistry>,
+
+    pub admin: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct WithdrawFees<'info> {
     #[account(
@@ -1488,6 +6978,10 @@ pub struct AgentIdentity {
     pub successful_escrows: u64,          // 8
     pub disputed_escrows: u64,            // 8
     pub bump: u8,                         // 1
+    pub version: u8,                      // 1
+    pub pending_unstake: u64,             // 8
+    pub unstake_available_at: i64,        // 8
+    pub open_disputes: u32,               // 4
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
@@ -1510,49 +7004,396 @@ pub struct OracleRegistry {
     pub created_at: i64,
     pub updated_at: i64,
     pub bump: u8,
+    pub version: u8,               // Layout version, see ACCOUNT_VERSION
+    pub consensus_mode: ConsensusMode,
+    pub appeal_window: i64,
+    pub appeal_min_consensus: u8,
+    pub appeal_bond: u64,
+    pub genesis_hash: [u8; 32],
+    pub commit_window: i64,        // Seconds oracles have to commit once a hearing closes
+    pub reveal_window: i64,        // Seconds oracles then have to reveal
+}
+
+impl OracleRegistry {
+    /// Whether an oracle is currently registered
+    pub fn is_registered(&self, oracle: &Pubkey) -> bool {
+        self.oracles.iter().any(|o| o.pubkey == *oracle)
+    }
+
+    /// Whether an oracle is registered with the given type
+    pub fn is_registered_as(&self, oracle: &Pubkey, oracle_type: OracleType) -> bool {
+        self.oracles
+            .iter()
+            .any(|o| o.pubkey == *oracle && o.oracle_type == oracle_type)
+    }
+
+    /// Registry weight for an oracle, or 0 if it is not registered
+    pub fn oracle_weight(&self, oracle: &Pubkey) -> u16 {
+        self.oracles
+            .iter()
+            .find(|o| o.pubkey == *oracle)
+            .map(|o| o.weight)
+            .unwrap_or(0)
+    }
+}
+
+/// Lamports an oracle has bonded against its rulings, plus dispute fee
+/// rewards it has earned but not claimed. Both are held in this account.
+#[account]
+#[derive(InitSpace)]
+pub struct OracleStake {
+    pub oracle: Pubkey,
+    pub amount: u64,           // Bonded stake, slashable
+    pub rewards: u64,          // Unclaimed dispute fee share
+    pub total_slashed: u64,
+    pub created_at: i64,
+    pub bump: u8,
+    pub locked_until: i64,     // End of the latest dispute the oracle committed to
+}
+
+/// How oracle submissions are reduced to a single consensus score
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum ConsensusMode {
+    Median,
+    WeightedMedian,
+    WeightedTrimmedMean,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct OracleConfig {
+    pub pubkey: Pubkey,
+    pub oracle_type: OracleType,
+    pub weight: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum OracleType {
+    Ed25519,
+    Switchboard,
+    Custom,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct OracleSubmission {
+    pub oracle: Pubkey,
+    pub quality_score: u8,
+    pub submitted_at: i64,
+}
+
+/// Ruling an oracle signs on a dispute. Oracles sign `DOMAIN` followed by the
+/// Borsh encoding of this struct, which ties the ruling to one program, one
+/// cluster and one dispute, and stops it being used after `expires_at`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct OracleAttestation {
+    pub version: u8,
+    pub program_id: Pubkey,
+    pub genesis_hash: [u8; 32],
+    pub escrow: Pubkey,
+    pub milestone_index: Option<u8>,
+    pub nonce: u64,
+    pub quality_score: u8,
+    pub refund_percentage: u8,
+    pub evidence_hash: [u8; 32],
+    pub expires_at: i64,
+}
+
+impl OracleAttestation {
+    pub const VERSION: u8 = 1;
+    pub const DOMAIN: &'static [u8] = b"mitama-oracle-attestation";
+
+    /// Attestation for a ruling on the dispute behind `dispute_record`
+    pub fn for_dispute(
+        registry: &OracleRegistry,
+        dispute_record: &DisputeRecord,
+        quality_score: u8,
+        refund_percentage: u8,
+        expires_at: i64,
+    ) -> Self {
+        Self {
+            version: Self::VERSION,
+            program_id: ID,
+            genesis_hash: registry.genesis_hash,
+            escrow: dispute_record.escrow,
+            milestone_index: dispute_record.milestone_index,
+            nonce: dispute_record.nonce,
+            quality_score,
+            refund_percentage,
+            evidence_hash: dispute_record.evidence_hash().to_bytes(),
+            expires_at,
+        }
+    }
+
+    /// Bytes the oracle signs
+    pub fn message(&self) -> Result<Vec<u8>> {
+        let mut message = Self::DOMAIN.to_vec();
+        self.serialize(&mut message)
+            .map_err(|_| error!(MitamaError::InvalidAttestation))?;
+        Ok(message)
+    }
+
+    /// Decode a signed message, rejecting other domains, versions and programs
+    pub fn try_from_message(message: &[u8]) -> Result<Self> {
+        let payload = message
+            .strip_prefix(Self::DOMAIN)
+            .ok_or(MitamaError::InvalidAttestation)?;
+        let attestation = Self::try_from_slice(payload)
+            .map_err(|_| error!(MitamaError::InvalidAttestation))?;
+        require!(
+            attestation.version == Self::VERSION && attestation.program_id == ID,
+            MitamaError::InvalidAttestation
+        );
+        Ok(attestation)
+    }
+
+    /// Reject the attestation once `now` is past its expiry
+    pub fn check_expiry(&self, now: i64) -> Result<()> {
+        require!(now <= self.expires_at, MitamaError::AttestationExpired);
+        Ok(())
+    }
+}
+
+/// Escrow Account
+#[account]
+#[derive(InitSpace)]
+pub struct Escrow {
+    pub agent: Pubkey,
+    pub api: Pubkey,
+    pub amount: u64,
+    pub status: EscrowStatus,
+    pub created_at: i64,
+    pub expires_at: i64,
+    #[max_len(64)]
+    pub transaction_id: String,
+    pub bump: u8,
+    pub quality_score: Option<u8>,
+    pub refund_percentage: Option<u8>,
+    #[max_len(5)]
+    pub oracle_submissions: Vec<OracleSubmission>,
+    pub token_mint: Option<Pubkey>,
+    pub escrow_token_account: Option<Pubkey>,
+    pub token_decimals: u8,
+    pub switchboard_feed: Option<Pubkey>,
+    #[max_len(10)]
+    pub milestones: Vec<Milestone>,
+    pub streaming: bool,
+    pub withdrawn: u64,
+    pub disputed_at: i64,
+    pub appealed: bool,
+    pub settles_at: i64,
+    pub agent_identity: Option<Pubkey>, // Agent PDA whose escrow counters this escrow updates
+}
+
+impl Escrow {
+    /// Amount of a streaming escrow vested to the API at `timestamp`, linear
+    /// from `created_at` to `expires_at` and frozen at `disputed_at`. Always 0
+    /// for other escrows.
+    pub fn vested_amount(&self, timestamp: i64) -> Result<u64> {
+        if !self.streaming {
+            return Ok(0);
+        }
+
+        let until = if self.disputed_at > 0 {
+            timestamp.min(self.disputed_at)
+        } else {
+            timestamp
+        };
+        let elapsed = until.clamp(self.created_at, self.expires_at) - self.created_at;
+        let duration = self.expires_at - self.created_at;
+        if duration <= 0 {
+            return Ok(self.amount);
+        }
+
+        let vested = (self.amount as u128)
+            .checked_mul(elapsed as u128)
+            .ok_or(MitamaError::ArithmeticOverflow)?
+            / duration as u128;
+        Ok(vested as u64)
+    }
+
+    /// Escrow status once every milestone has settled: Resolved if any went
+    /// through a dispute, otherwise Released. None while any are still open.
+    pub fn final_milestone_status(&self) -> Option<EscrowStatus> {
+        let open = self.milestones.iter().any(|m| {
+            m.status == EscrowStatus::Active || m.status == EscrowStatus::Disputed
+        });
+        if open {
+            return None;
+        }
+
+        if self.milestones.iter().any(|m| m.status == EscrowStatus::Resolved) {
+            Some(EscrowStatus::Resolved)
+        } else {
+            Some(EscrowStatus::Released)
+        }
+    }
+
+    /// Settle a disputed milestone with a ruling, or with none on a timeout,
+    /// returning its (refund, payment) split. The escrow's own status is left
+    /// to `final_milestone_status`.
+    pub fn resolve_milestone(
+        &mut self,
+        milestone_index: u8,
+        quality_score: Option<u8>,
+        refund_percentage: u8,
+    ) -> Result<(u64, u64)> {
+        let milestone = self.milestones.get_mut(milestone_index as usize)
+            .ok_or(MitamaError::InvalidMilestone)?;
+        require!(milestone.status == EscrowStatus::Disputed, MitamaError::InvalidStatus);
+
+        let split = split_escrow_amount(milestone.amount, refund_percentage)?;
+        milestone.status = EscrowStatus::Resolved;
+        milestone.quality_score = quality_score;
+        milestone.refund_percentage = Some(refund_percentage);
+        Ok(split)
+    }
+}
+
+/// Evidence both parties submit to a dispute before it is ruled on
+#[account]
+#[derive(InitSpace)]
+pub struct DisputeRecord {
+    pub escrow: Pubkey,
+    pub milestone_index: Option<u8>,
+    pub agent: Pubkey,
+    pub api: Pubkey,
+    pub opened_at: i64,
+    pub response_deadline: i64,
+    #[max_len(8)]
+    pub evidence: Vec<Evidence>,
+    pub bump: u8,
+    pub nonce: u64,
+    pub oracle_reward_pool: u64,   // Oracles' share of the dispute fee, held in this account
+    pub scoring_opens_at: i64,     // Start of the current commit window
+    #[max_len(5)]
+    pub commitments: Vec<ScoreCommitment>,
+    pub resolution_deadline: i64,  // After this, anyone can settle by the timeout policy
+}
+
+impl DisputeRecord {
+    /// Hash committing to the evidence set in submission order, which oracles
+    /// include in the message they sign
+    pub fn evidence_hash(&self) -> Hash {
+        let entries: Vec<Hash> = self.evidence
+            .iter()
+            .map(|e| hashv(&[e.submitter.as_ref(), &e.content_hash, e.uri.as_bytes()]))
+            .collect();
+        let entry_bytes: Vec<&[u8]> = entries.iter().map(|h| h.as_ref()).collect();
+        hashv(&entry_bytes)
+    }
+
+    /// Whether the hearing is over: the response window has passed or both
+    /// parties have had their say
+    pub fn hearing_closed(&self, now: i64) -> bool {
+        let agent_responded = self.evidence.iter().any(|e| e.submitter == self.agent);
+        let api_responded = self.evidence.iter().any(|e| e.submitter == self.api);
+        now >= self.response_deadline || (agent_responded && api_responded)
+    }
+
+    /// End of the commit window, after which oracles reveal
+    pub fn commit_deadline(&self, registry: &OracleRegistry) -> i64 {
+        self.scoring_opens_at.saturating_add(registry.commit_window)
+    }
+
+    /// End of the reveal window, after which unrevealed commitments are void
+    pub fn reveal_deadline(&self, registry: &OracleRegistry) -> i64 {
+        self.commit_deadline(registry).saturating_add(registry.reveal_window)
+    }
+
+    /// Whether the resolution deadline has passed without a ruling, opening
+    /// the timeout settlement
+    pub fn timed_out(&self, now: i64) -> bool {
+        now >= self.resolution_deadline
+    }
 }
 
+/// An oracle's sealed score, opened with `reveal_oracle_score`
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
-pub struct OracleConfig {
-    pub pubkey: Pubkey,
-    pub oracle_type: OracleType,
-    pub weight: u16,
+pub struct ScoreCommitment {
+    pub oracle: Pubkey,
+    pub commitment: [u8; 32],
+    pub committed_at: i64,
+    pub revealed: bool,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
-pub enum OracleType {
-    Ed25519,
-    Switchboard,
-    Custom,
+impl ScoreCommitment {
+    /// Commitment to a score. Binding the escrow and oracle stops one oracle
+    /// from reusing another's commitment and copying its reveal.
+    pub fn hash(escrow: &Pubkey, oracle: &Pubkey, quality_score: u8, salt: &[u8; 32]) -> [u8; 32] {
+        hashv(&[escrow.as_ref(), oracle.as_ref(), &[quality_score], salt]).to_bytes()
+    }
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
-pub struct OracleSubmission {
-    pub oracle: Pubkey,
-    pub quality_score: u8,
+pub struct Evidence {
+    pub submitter: Pubkey,
+    pub content_hash: [u8; 32],
+    #[max_len(128)]
+    pub uri: String,
     pub submitted_at: i64,
 }
 
-/// Escrow Account
+/// Appeal against a first-round ruling, holding the appellant's bond until
+/// the appeal round settles
 #[account]
 #[derive(InitSpace)]
-pub struct Escrow {
+pub struct Appeal {
+    pub escrow: Pubkey,
+    pub appellant: Pubkey,
+    pub bond: u64,
+    pub oracle_reward: u64,          // Part of the bond paid into the dispute's oracle reward pool
+    pub original_quality_score: u8,
+    pub original_refund_percentage: u8,
+    pub filed_at: i64,
+    pub bump: u8,
+    #[max_len(5)]
+    pub first_round: Vec<OracleSubmission>, // First-round scores, settled against the appeal ruling
+    #[max_len(5)]
+    pub first_round_absent: Vec<Pubkey>,    // First-round oracles that never revealed
+}
+
+/// Recurring agreement that opens one escrow per period from pre-deposited funds
+#[account]
+#[derive(InitSpace)]
+pub struct Subscription {
     pub agent: Pubkey,
     pub api: Pubkey,
-    pub amount: u64,
-    pub status: EscrowStatus,
+    #[max_len(24)]
+    pub subscription_id: String,
+    pub amount_per_period: u64,
+    pub period_length: i64,
+    pub total_periods: u16,
+    pub periods_opened: u16,
+    pub next_period_at: i64,
+    pub token_mint: Option<Pubkey>,
+    pub subscription_token_account: Option<Pubkey>,
+    pub token_decimals: u8,
     pub created_at: i64,
-    pub expires_at: i64,
-    #[max_len(64)]
-    pub transaction_id: String,
     pub bump: u8,
+    pub agent_identity: Option<Pubkey>, // Identity each period's escrow binds to
+}
+
+impl Subscription {
+    /// Transaction ID of the escrow opened for a 1-based period
+    pub fn period_transaction_id(&self, period: u16) -> String {
+        format!("{}#{}", self.subscription_id, period)
+    }
+}
+
+/// One payment stage of a milestone escrow
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct Milestone {
+    pub amount: u64,
+    pub deadline: i64,
+    pub status: EscrowStatus,
     pub quality_score: Option<u8>,
     pub refund_percentage: Option<u8>,
-    #[max_len(5)]
-    pub oracle_submissions: Vec<OracleSubmission>,
-    pub token_mint: Option<Pubkey>,
-    pub escrow_token_account: Option<Pubkey>,
-    pub token_decimals: u8,
+}
+
+/// Milestone amount and time lock (seconds from creation) supplied at creation
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MilestoneParams {
+    pub amount: u64,
+    pub time_lock: i64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
@@ -1561,6 +7402,7 @@ pub enum EscrowStatus {
     Released,
     Disputed,
     Resolved,
+    PendingSettlement,
 }
 
 /// Entity Reputation
@@ -1610,6 +7452,157 @@ pub struct ProtocolConfig {
     pub created_at: i64,
     pub updated_at: i64,
     pub bump: u8,
+    pub version: u8,                // Layout version, see ACCOUNT_VERSION
+    pub disputes_opened: u64,      // Dispute counter, used as attestation nonces
+    #[max_len(8)]
+    pub refund_tiers: Vec<RefundTier>, // Quality-to-refund curve
+    pub strict_refunds: bool,      // Reject rulings whose refund disagrees with the curve
+    pub oracle_reward_bps: u16,    // Share of each dispute fee paid to oracles
+    pub oracle_slash_bps: u16,     // Share of an outlier oracle's stake slashed per ruling
+    pub min_oracle_stake: u64,     // Stake an oracle needs to submit scores
+    pub resolution_timeout: i64,   // Seconds a dispute may go without a ruling
+    pub timeout_policy: DisputeTimeoutPolicy, // How timed-out disputes settle
+    pub lost_dispute_slash_bps: u16,      // Agent stake slashed for a lost dispute
+    pub frivolous_dispute_slash_bps: u16, // Agent stake slashed for a dispute with no refund
+    pub slash_counterparty_bps: u16,      // Share of each slash paid to the API
+    pub reputation_authority: Pubkey,     // Signer allowed to adjust agent reputation
+}
+
+/// Why an agent's reputation was adjusted
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum ReputationUpdateReason {
+    ServiceQuality,
+    DisputeOutcome,
+    Misconduct,
+    Correction,
+}
+
+/// Why an agent's stake was slashed
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum SlashReason {
+    LostDispute,
+    FrivolousDispute,
+}
+
+/// How a dispute settles when no ruling arrives before its resolution deadline
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub enum DisputeTimeoutPolicy {
+    FullRefund,
+    Split,
+}
+
+impl DisputeTimeoutPolicy {
+    /// Share of the disputed amount refunded to the agent
+    pub fn refund_percentage(&self) -> u8 {
+        match self {
+            DisputeTimeoutPolicy::FullRefund => 100,
+            DisputeTimeoutPolicy::Split => 50,
+        }
+    }
+}
+
+/// One step of the quality-to-refund curve: scores from `min_quality` up to
+/// the next tier's minimum refund `refund_percentage`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Debug)]
+pub struct RefundTier {
+    pub min_quality: u8,
+    pub refund_percentage: u8,
+}
+
+impl ProtocolConfig {
+    /// Refund percentage the protocol curve assigns to a quality score
+    pub fn refund_for_quality(&self, quality_score: u8) -> u8 {
+        calculate_refund_from_quality(&self.refund_tiers, quality_score)
+    }
+
+    /// Slash owed by an agent whose dispute settled with `refund_percentage`:
+    /// a frivolous dispute returned nothing, a lost one less than half
+    pub fn agent_slash(&self, refund_percentage: u8) -> Option<(SlashReason, u16)> {
+        match refund_percentage {
+            0 => Some((SlashReason::FrivolousDispute, self.frivolous_dispute_slash_bps)),
+            r if r < LOST_DISPUTE_REFUND_THRESHOLD => {
+                Some((SlashReason::LostDispute, self.lost_dispute_slash_bps))
+            }
+            _ => None,
+        }
+    }
+
+    /// Take the next dispute nonce
+    pub fn next_dispute_nonce(&mut self) -> u64 {
+        let nonce = self.disputes_opened;
+        self.disputes_opened = self.disputes_opened.wrapping_add(1);
+        nonce
+    }
+}
+
+// ============================================================================
+// Legacy Layouts
+// ============================================================================
+
+/// AgentIdentity as written before stake cooldowns
+#[derive(AnchorDeserialize)]
+struct LegacyAgentIdentity {
+    owner: Pubkey,
+    name: String,
+    agent_type: AgentType,
+    reputation: u64,
+    stake_amount: u64,
+    is_active: bool,
+    created_at: i64,
+    last_active: i64,
+    total_escrows: u64,
+    successful_escrows: u64,
+    disputed_escrows: u64,
+    bump: u8,
+}
+
+/// OracleRegistry as written before weighted consensus modes
+#[derive(AnchorDeserialize)]
+struct LegacyOracleRegistry {
+    admin: Pubkey,
+    oracles: Vec<OracleConfig>,
+    min_consensus: u8,
+    max_score_deviation: u8,
+    created_at: i64,
+    _updated_at: i64,
+    bump: u8,
+}
+
+/// ProtocolConfig as written before attestation nonces
+#[derive(AnchorDeserialize)]
+struct LegacyProtocolConfig {
+    admin: Pubkey,
+    treasury: Pubkey,
+    agreement_fee_bps: u16,
+    dispute_fee_bps: u16,
+    dispute_base_fee: u64,
+    identity_fee: u64,
+    total_fees_collected: u64,
+    is_active: bool,
+    created_at: i64,
+    _updated_at: i64,
+    bump: u8,
+}
+
+/// Escrow as written under the legacy `[b"escrow", transaction_id]` seeds
+#[derive(AnchorDeserialize, InitSpace)]
+struct LegacyEscrow {
+    agent: Pubkey,
+    api: Pubkey,
+    amount: u64,
+    status: EscrowStatus,
+    created_at: i64,
+    expires_at: i64,
+    #[max_len(64)]
+    transaction_id: String,
+    bump: u8,
+    quality_score: Option<u8>,
+    refund_percentage: Option<u8>,
+    #[max_len(5)]
+    oracle_submissions: Vec<OracleSubmission>,
+    token_mint: Option<Pubkey>,
+    escrow_token_account: Option<Pubkey>,
+    token_decimals: u8,
 }
 
 // ============================================================================
@@ -1687,6 +7680,15 @@ pub enum MitamaError {
     #[msg("Token mint mismatch")]
     TokenMintMismatch,
 
+    #[msg("Invalid token account")]
+    InvalidTokenAccount,
+
+    #[msg("Token mint uses an extension escrows cannot support")]
+    UnsupportedMintExtension,
+
+    #[msg("Missing agent wallet")]
+    MissingAgentWallet,
+
     #[msg("Invalid agent name")]
     InvalidAgentName,
 
@@ -1707,4 +7709,615 @@ pub enum MitamaError {
 
     #[msg("Protocol is not active")]
     ProtocolNotActive,
+
+    #[msg("Account is already at the current layout version")]
+    AlreadyMigrated,
+
+    #[msg("Switchboard feed is not registered in the oracle registry")]
+    UnregisteredSwitchboardFeed,
+
+    #[msg("Invalid Switchboard feed")]
+    InvalidSwitchboardFeed,
+
+    #[msg("Switchboard feed is stale or has too few samples")]
+    StaleSwitchboardFeed,
+
+    #[msg("Invalid milestone")]
+    InvalidMilestone,
+
+    #[msg("Milestone escrows settle through the milestone instructions")]
+    MilestoneEscrow,
+
+    #[msg("Escrow is not a streaming escrow")]
+    NotStreamingEscrow,
+
+    #[msg("Nothing has vested since the last withdrawal")]
+    NothingVested,
+
+    #[msg("Invalid number of subscription periods")]
+    InvalidPeriodCount,
+
+    #[msg("All subscription periods have been opened")]
+    SubscriptionComplete,
+
+    #[msg("Subscription period is not due yet")]
+    PeriodNotDue,
+
+    #[msg("Dispute record does not belong to this dispute")]
+    DisputeRecordMismatch,
+
+    #[msg("Dispute record is required for a disputed escrow")]
+    MissingDisputeRecord,
+
+    #[msg("Evidence window is still open")]
+    EvidenceWindowOpen,
+
+    #[msg("Evidence window has closed")]
+    EvidenceWindowClosed,
+
+    #[msg("Hearing has closed to new evidence")]
+    HearingClosed,
+
+    #[msg("Evidence limit reached")]
+    TooMuchEvidence,
+
+    #[msg("Invalid evidence")]
+    InvalidEvidence,
+
+    #[msg("Invalid appeal parameters")]
+    InvalidAppealParams,
+
+    #[msg("Appeal window has closed")]
+    AppealWindowClosed,
+
+    #[msg("Settlement is held until the appeal window closes")]
+    AppealWindowOpen,
+
+    #[msg("Appealed disputes must be resolved by the appeal oracle quorum")]
+    AppealRequiresQuorum,
+
+    #[msg("Appeal account is required for an appealed escrow")]
+    MissingAppeal,
+
+    #[msg("Invalid oracle attestation")]
+    InvalidAttestation,
+
+    #[msg("Oracle attestation has expired")]
+    AttestationExpired,
+
+    #[msg("Invalid refund tier table")]
+    InvalidRefundTiers,
+
+    #[msg("Refund percentage does not match the protocol refund curve")]
+    RefundMismatch,
+
+    #[msg("Oracle stake is below the protocol minimum")]
+    InsufficientOracleStake,
+
+    #[msg("Single-verifier rulings are closed once oracles have committed scores")]
+    SingleVerifierDisabled,
+
+    #[msg("Oracle stake is locked until a dispute it scored is settled")]
+    OracleStakeLocked,
+
+    #[msg("Oracle stake account does not match the submission")]
+    OracleStakeMismatch,
+
+    #[msg("Invalid oracle stake parameters")]
+    InvalidOracleStakeParams,
+
+    #[msg("Invalid commit or reveal window")]
+    InvalidScoringWindows,
+
+    #[msg("Commit window is still open")]
+    CommitWindowOpen,
+
+    #[msg("Commit window has closed")]
+    CommitWindowClosed,
+
+    #[msg("Reveal window is still open")]
+    RevealWindowOpen,
+
+    #[msg("Reveal window has closed")]
+    RevealWindowClosed,
+
+    #[msg("Oracle has no score commitment for this dispute")]
+    MissingCommitment,
+
+    #[msg("Revealed score does not match the commitment")]
+    CommitmentMismatch,
+
+    #[msg("Escrow is bound to an agent identity that was not provided")]
+    MissingAgentIdentity,
+
+    #[msg("Agent identity does not match the escrow")]
+    AgentIdentityMismatch,
+
+    #[msg("Agent has open disputes")]
+    AgentHasOpenDisputes,
+
+    #[msg("No unstake has been requested")]
+    NoPendingUnstake,
+
+    #[msg("Unstake cooldown has not passed")]
+    UnstakeCooldownActive,
+
+    #[msg("Invalid agent slashing policy")]
+    InvalidSlashingPolicy,
+
+    #[msg("Invalid dispute resolution timeout")]
+    InvalidDisputeTimeout,
+
+    #[msg("Dispute resolution deadline has not passed")]
+    ResolutionDeadlineNotReached,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted_median_averages_on_an_exact_midpoint() {
+        assert_eq!(weighted_median(&[(40, 1), (60, 1)]), Some(50));
+        assert_eq!(weighted_median(&[(40, 2), (60, 1), (80, 1)]), Some(50));
+    }
+
+    #[test]
+    fn weighted_median_follows_the_heaviest_side() {
+        assert_eq!(weighted_median(&[(10, 1), (50, 3), (90, 1)]), Some(50));
+        assert_eq!(weighted_median(&[(10, 5), (50, 1), (90, 1)]), Some(10));
+    }
+
+    #[test]
+    fn weighted_median_needs_weight() {
+        assert_eq!(weighted_median(&[]), None);
+        assert_eq!(weighted_median(&[(50, 0), (70, 0)]), None);
+    }
+
+    #[test]
+    fn weighted_consensus_discards_outliers_from_the_median() {
+        let (score, included) =
+            calculate_weighted_consensus_score(&[50, 52, 90], &[1, 1, 1], 15, false).unwrap();
+        assert_eq!(score, 51);
+        assert_eq!(included, vec![true, true, false]);
+    }
+
+    #[test]
+    fn weighted_consensus_trimmed_mean_rounds_half_up() {
+        let (score, included) =
+            calculate_weighted_consensus_score(&[60, 70, 20], &[3, 1, 1], 15, true).unwrap();
+        assert_eq!(score, 63);
+        assert_eq!(included, vec![true, true, false]);
+    }
+
+    #[test]
+    fn weighted_consensus_rejects_bad_inputs() {
+        assert_eq!(
+            calculate_weighted_consensus_score(&[50], &[1], 15, false).unwrap_err(),
+            MitamaError::InsufficientOracleConsensus.into()
+        );
+        assert_eq!(
+            calculate_weighted_consensus_score(&[50, 60], &[1], 15, false).unwrap_err(),
+            MitamaError::InvalidOracleWeight.into()
+        );
+        assert_eq!(
+            calculate_weighted_consensus_score(&[10, 50, 90], &[1, 1, 1], 5, false).unwrap_err(),
+            MitamaError::NoConsensusReached.into()
+        );
+    }
+
+    /// Ed25519 instruction data whose entries point at their own signature,
+    /// public key and message, laid out after the offsets table
+    fn ed25519_data(entries: &[([u8; 64], Pubkey, &[u8])]) -> Vec<u8> {
+        let header = ED25519_OFFSETS_START + entries.len() * ED25519_OFFSETS_SIZE;
+        let mut offsets = Vec::new();
+        let mut payload = Vec::new();
+
+        for (signature, pubkey, message) in entries {
+            let signature_offset = header + payload.len();
+            payload.extend_from_slice(signature);
+            let pubkey_offset = header + payload.len();
+            payload.extend_from_slice(pubkey.as_ref());
+            let message_offset = header + payload.len();
+            payload.extend_from_slice(message);
+
+            for field in [
+                signature_offset as u16,
+                ED25519_THIS_INSTRUCTION,
+                pubkey_offset as u16,
+                ED25519_THIS_INSTRUCTION,
+                message_offset as u16,
+                message.len() as u16,
+                ED25519_THIS_INSTRUCTION,
+            ] {
+                offsets.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+
+        let mut data = vec![entries.len() as u8, 0];
+        data.extend(offsets);
+        data.extend(payload);
+        data
+    }
+
+    /// Instructions sysvar data holding one account-less instruction,
+    /// followed by the current instruction index
+    fn instructions_sysvar_data(program_id: &Pubkey, data: &[u8], current_index: u16) -> Vec<u8> {
+        let mut sysvar_data = Vec::new();
+        sysvar_data.extend_from_slice(&1u16.to_le_bytes());
+        sysvar_data.extend_from_slice(&4u16.to_le_bytes());
+        sysvar_data.extend_from_slice(&0u16.to_le_bytes());
+        sysvar_data.extend_from_slice(program_id.as_ref());
+        sysvar_data.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        sysvar_data.extend_from_slice(data);
+        sysvar_data.extend_from_slice(&current_index.to_le_bytes());
+        sysvar_data
+    }
+
+    fn contains(data: &[u8], signature: &[u8; 64], pubkey: &Pubkey, message: &[u8]) -> bool {
+        let key = Pubkey::default();
+        let mut lamports = 0;
+        let mut sysvar_data = Vec::new();
+        let sysvar = AccountInfo::new(
+            &key, false, false, &mut lamports, &mut sysvar_data, &key, false, 0,
+        );
+        ed25519_instruction_contains(&sysvar, data, signature, pubkey, message)
+    }
+
+    #[test]
+    fn ed25519_entry_matches_signature_key_and_message() {
+        let pubkey = Pubkey::new_unique();
+        let data = ed25519_data(&[([7; 64], pubkey, b"ruling")]);
+
+        assert!(contains(&data, &[7; 64], &pubkey, b"ruling"));
+        assert!(!contains(&data, &[8; 64], &pubkey, b"ruling"));
+        assert!(!contains(&data, &[7; 64], &Pubkey::new_unique(), b"ruling"));
+        assert!(!contains(&data, &[7; 64], &pubkey, b"other"));
+    }
+
+    #[test]
+    fn ed25519_later_entries_are_searched() {
+        let first = Pubkey::new_unique();
+        let second = Pubkey::new_unique();
+        let data = ed25519_data(&[([1; 64], first, b"one"), ([2; 64], second, b"two")]);
+
+        assert!(contains(&data, &[1; 64], &first, b"one"));
+        assert!(contains(&data, &[2; 64], &second, b"two"));
+        assert!(!contains(&data, &[2; 64], &first, b"two"));
+    }
+
+    #[test]
+    fn ed25519_truncated_data_never_matches() {
+        let pubkey = Pubkey::new_unique();
+        let data = ed25519_data(&[([7; 64], pubkey, b"ruling")]);
+
+        // Count claims more entries than the offsets table holds
+        let mut overcounted = data.clone();
+        overcounted[0] = 3;
+        assert!(contains(&overcounted, &[7; 64], &pubkey, b"ruling"));
+
+        // Offsets that run past the end of the data
+        assert!(!contains(&data[..data.len() - 1], &[7; 64], &pubkey, b"ruling"));
+        assert!(!contains(&data[..ED25519_OFFSETS_START + 4], &[7; 64], &pubkey, b"ruling"));
+        assert!(!contains(&[], &[7; 64], &pubkey, b"ruling"));
+    }
+
+    #[test]
+    fn ed25519_entry_bytes_reads_other_instructions() {
+        let program_id = Pubkey::new_unique();
+        let mut sysvar_data = instructions_sysvar_data(&program_id, b"prefix-ruling", 0);
+        let mut lamports = 0;
+        let owner = Pubkey::default();
+        let sysvar = AccountInfo::new(
+            &INSTRUCTIONS_ID, false, false, &mut lamports, &mut sysvar_data, &owner, false, 0,
+        );
+
+        assert_eq!(ed25519_entry_bytes(&sysvar, &[], 0, 7, 6).as_deref(), Some(&b"ruling"[..]));
+        assert_eq!(ed25519_entry_bytes(&sysvar, &[], 0, 7, 7), None);
+        assert_eq!(ed25519_entry_bytes(&sysvar, &[], 1, 0, 1), None);
+        assert_eq!(
+            ed25519_entry_bytes(&sysvar, &[], ED25519_THIS_INSTRUCTION, u16::MAX, usize::MAX),
+            None
+        );
+    }
+
+    fn oracle_registry(oracle: Pubkey) -> OracleRegistry {
+        OracleRegistry {
+            admin: Pubkey::default(),
+            oracles: vec![OracleConfig { pubkey: oracle, oracle_type: OracleType::Ed25519, weight: 100 }],
+            min_consensus: 2,
+            max_score_deviation: 15,
+            created_at: 0,
+            updated_at: 0,
+            bump: 0,
+            version: ACCOUNT_VERSION,
+            consensus_mode: ConsensusMode::Median,
+            appeal_window: 0,
+            appeal_min_consensus: 0,
+            appeal_bond: 0,
+            genesis_hash: [9; 32],
+            commit_window: DEFAULT_COMMIT_WINDOW,
+            reveal_window: DEFAULT_REVEAL_WINDOW,
+        }
+    }
+
+    fn evidence(submitter: Pubkey) -> Evidence {
+        Evidence { submitter, content_hash: [1; 32], uri: "ipfs://evidence".to_string(), submitted_at: 0 }
+    }
+
+    /// Record of a dispute both parties have answered
+    fn heard_dispute_record(milestone_index: Option<u8>) -> DisputeRecord {
+        let (agent, api) = (Pubkey::new_unique(), Pubkey::new_unique());
+        DisputeRecord {
+            escrow: Pubkey::new_unique(),
+            milestone_index,
+            agent,
+            api,
+            opened_at: 0,
+            response_deadline: DISPUTE_RESPONSE_WINDOW,
+            evidence: vec![evidence(agent), evidence(api)],
+            bump: 0,
+            nonce: 1,
+            oracle_reward_pool: 0,
+            scoring_opens_at: 0,
+            commitments: Vec::new(),
+            resolution_deadline: DEFAULT_RESOLUTION_TIMEOUT,
+        }
+    }
+
+    fn oracle_stake(oracle: Pubkey, amount: u64) -> OracleStake {
+        OracleStake { oracle, amount, rewards: 0, total_slashed: 0, created_at: 0, bump: 0, locked_until: 0 }
+    }
+
+    #[test]
+    fn single_verifier_rules_under_a_multi_oracle_quorum() {
+        let verifier = Pubkey::new_unique();
+        let registry = oracle_registry(verifier);
+        let record = heard_dispute_record(Some(1));
+        let stake = oracle_stake(verifier, DEFAULT_MIN_ORACLE_STAKE);
+        assert!(record.hearing_closed(0));
+        check_single_verifier(&registry, &record, &verifier, &stake, DEFAULT_MIN_ORACLE_STAKE)
+            .unwrap();
+
+        // The verifier's signed ruling is found in the preceding Ed25519 instruction
+        let refund = calculate_refund_from_quality(&DEFAULT_REFUND_TIERS, 70);
+        let attestation = OracleAttestation::for_dispute(&registry, &record, 70, refund, 100);
+        let message = attestation.message().unwrap();
+        let data = ed25519_data(&[([5; 64], verifier, &message)]);
+        let mut sysvar_data = instructions_sysvar_data(&ed25519_program::ID, &data, 1);
+        let mut lamports = 0;
+        let owner = Pubkey::default();
+        let sysvar = AccountInfo::new(
+            &INSTRUCTIONS_ID, false, false, &mut lamports, &mut sysvar_data, &owner, false, 0,
+        );
+        verify_oracle_attestation(&sysvar, &[5; 64], &verifier, &attestation, 99).unwrap();
+        assert_eq!(
+            verify_oracle_attestation(&sysvar, &[6; 64], &verifier, &attestation, 99).unwrap_err(),
+            MitamaError::InvalidSignature.into()
+        );
+    }
+
+    #[test]
+    fn single_verifier_needs_a_registered_staked_oracle_before_commitments() {
+        let verifier = Pubkey::new_unique();
+        let registry = oracle_registry(verifier);
+        let mut record = heard_dispute_record(None);
+        let stake = oracle_stake(verifier, DEFAULT_MIN_ORACLE_STAKE);
+
+        let outsider = Pubkey::new_unique();
+        assert_eq!(
+            check_single_verifier(&registry, &record, &outsider, &stake, DEFAULT_MIN_ORACLE_STAKE)
+                .unwrap_err(),
+            MitamaError::UnregisteredOracle.into()
+        );
+
+        let underbonded = oracle_stake(verifier, DEFAULT_MIN_ORACLE_STAKE - 1);
+        assert_eq!(
+            check_single_verifier(&registry, &record, &verifier, &underbonded, DEFAULT_MIN_ORACLE_STAKE)
+                .unwrap_err(),
+            MitamaError::InsufficientOracleStake.into()
+        );
+
+        record.commitments.push(ScoreCommitment {
+            oracle: verifier,
+            commitment: [0; 32],
+            committed_at: 0,
+            revealed: false,
+        });
+        assert_eq!(
+            check_single_verifier(&registry, &record, &verifier, &stake, DEFAULT_MIN_ORACLE_STAKE)
+                .unwrap_err(),
+            MitamaError::SingleVerifierDisabled.into()
+        );
+    }
+
+    fn tier(min_quality: u8, refund_percentage: u8) -> RefundTier {
+        RefundTier { min_quality, refund_percentage }
+    }
+
+    #[test]
+    fn refund_follows_the_default_curve() {
+        let curve = [(0, 100), (49, 100), (50, 75), (64, 75), (65, 35), (79, 35), (80, 0), (100, 0)];
+        for (quality, refund) in curve {
+            assert_eq!(calculate_refund_from_quality(&[], quality), refund);
+            assert_eq!(calculate_refund_from_quality(&DEFAULT_REFUND_TIERS, quality), refund);
+        }
+    }
+
+    #[test]
+    fn refund_uses_the_highest_tier_reached() {
+        let tiers = [tier(0, 90), tier(70, 10)];
+        assert_eq!(calculate_refund_from_quality(&tiers, 69), 90);
+        assert_eq!(calculate_refund_from_quality(&tiers, 70), 10);
+        assert_eq!(calculate_refund_from_quality(&tiers, 100), 10);
+    }
+
+    #[test]
+    fn refund_tiers_accept_a_falling_curve_from_zero() {
+        assert!(validate_refund_tiers(&DEFAULT_REFUND_TIERS).is_ok());
+        assert!(validate_refund_tiers(&[tier(0, 100)]).is_ok());
+        assert!(validate_refund_tiers(&[tier(0, 50), tier(100, 50)]).is_ok());
+    }
+
+    #[test]
+    fn refund_tiers_reject_malformed_tables() {
+        let invalid: Vec<Vec<RefundTier>> = vec![
+            vec![],
+            vec![tier(10, 100)],
+            vec![tier(0, 100), tier(50, 75), tier(50, 35)],
+            vec![tier(0, 100), tier(60, 75), tier(50, 35)],
+            vec![tier(0, 50), tier(50, 75)],
+            vec![tier(0, 101)],
+            vec![tier(0, 100), tier(101, 0)],
+            (0..9).map(|i| tier(i * 10, 100 - i * 10)).collect(),
+        ];
+        for tiers in invalid {
+            assert_eq!(
+                validate_refund_tiers(&tiers).unwrap_err(),
+                MitamaError::InvalidRefundTiers.into()
+            );
+        }
+    }
+
+    #[test]
+    fn timeout_policies_refund_all_or_half() {
+        assert_eq!(DisputeTimeoutPolicy::FullRefund.refund_percentage(), 100);
+        assert_eq!(DisputeTimeoutPolicy::Split.refund_percentage(), 50);
+
+        let split = DisputeTimeoutPolicy::Split.refund_percentage();
+        assert_eq!(split_escrow_amount(1_001, split).unwrap(), (500, 501));
+        let full = DisputeTimeoutPolicy::FullRefund.refund_percentage();
+        assert_eq!(split_escrow_amount(1_001, full).unwrap(), (1_001, 0));
+    }
+
+    fn milestone_escrow(amounts: &[u64]) -> Escrow {
+        let milestones = amounts
+            .iter()
+            .map(|&amount| Milestone {
+                amount,
+                deadline: 3_600,
+                status: EscrowStatus::Active,
+                quality_score: None,
+                refund_percentage: None,
+            })
+            .collect();
+        Escrow {
+            agent: Pubkey::new_unique(),
+            api: Pubkey::new_unique(),
+            amount: amounts.iter().sum(),
+            status: EscrowStatus::Active,
+            created_at: 0,
+            expires_at: 3_600,
+            transaction_id: "milestones".to_string(),
+            bump: 0,
+            quality_score: None,
+            refund_percentage: None,
+            oracle_submissions: Vec::new(),
+            token_mint: None,
+            escrow_token_account: None,
+            token_decimals: 9,
+            switchboard_feed: None,
+            milestones,
+            streaming: false,
+            withdrawn: 0,
+            disputed_at: 0,
+            appealed: false,
+            settles_at: 0,
+            agent_identity: None,
+        }
+    }
+
+    #[test]
+    fn disputed_milestone_settles_by_timeout_after_the_deadline() {
+        let mut escrow = milestone_escrow(&[1_000, 2_001]);
+        escrow.milestones[0].status = EscrowStatus::Released;
+        escrow.milestones[1].status = EscrowStatus::Disputed;
+
+        let record = heard_dispute_record(Some(1));
+        assert!(!record.timed_out(record.resolution_deadline - 1));
+        assert!(record.timed_out(record.resolution_deadline));
+
+        let refund = DisputeTimeoutPolicy::Split.refund_percentage();
+        assert_eq!(escrow.resolve_milestone(1, None, refund).unwrap(), (1_000, 1_001));
+        assert!(escrow.milestones[1].status == EscrowStatus::Resolved);
+        assert_eq!(escrow.milestones[1].refund_percentage, Some(refund));
+        assert_eq!(escrow.milestones[1].quality_score, None);
+        assert!(escrow.final_milestone_status() == Some(EscrowStatus::Resolved));
+
+        // Settled milestones and unknown indexes cannot be settled again
+        assert_eq!(
+            escrow.resolve_milestone(1, None, refund).unwrap_err(),
+            MitamaError::InvalidStatus.into()
+        );
+        assert_eq!(
+            escrow.resolve_milestone(2, None, refund).unwrap_err(),
+            MitamaError::InvalidMilestone.into()
+        );
+    }
+
+    #[test]
+    fn milestone_ruling_leaves_the_other_milestones_open() {
+        let mut escrow = milestone_escrow(&[1_000, 1_000]);
+        escrow.milestones[0].status = EscrowStatus::Disputed;
+
+        assert_eq!(escrow.resolve_milestone(0, Some(70), 35).unwrap(), (350, 650));
+        assert_eq!(escrow.milestones[0].quality_score, Some(70));
+        assert!(escrow.final_milestone_status().is_none());
+    }
+
+    #[test]
+    fn legacy_escrow_keeps_the_released_account_size() {
+        // 8-byte discriminator plus the escrow layout of the release before
+        // agent-namespaced seeds
+        assert_eq!(8 + LegacyEscrow::INIT_SPACE, 446);
+    }
+
+    fn protocol_config() -> ProtocolConfig {
+        ProtocolConfig {
+            admin: Pubkey::default(),
+            treasury: Pubkey::default(),
+            agreement_fee_bps: DEFAULT_AGREEMENT_FEE_BPS,
+            dispute_fee_bps: DEFAULT_DISPUTE_FEE_BPS,
+            dispute_base_fee: DEFAULT_DISPUTE_BASE_FEE,
+            identity_fee: DEFAULT_IDENTITY_FEE,
+            total_fees_collected: 0,
+            is_active: true,
+            created_at: 0,
+            updated_at: 0,
+            bump: 0,
+            version: ACCOUNT_VERSION,
+            disputes_opened: 0,
+            refund_tiers: DEFAULT_REFUND_TIERS.to_vec(),
+            strict_refunds: false,
+            oracle_reward_bps: DEFAULT_ORACLE_REWARD_BPS,
+            oracle_slash_bps: DEFAULT_ORACLE_SLASH_BPS,
+            min_oracle_stake: DEFAULT_MIN_ORACLE_STAKE,
+            resolution_timeout: DEFAULT_RESOLUTION_TIMEOUT,
+            timeout_policy: DisputeTimeoutPolicy::FullRefund,
+            lost_dispute_slash_bps: DEFAULT_LOST_DISPUTE_SLASH_BPS,
+            frivolous_dispute_slash_bps: DEFAULT_FRIVOLOUS_DISPUTE_SLASH_BPS,
+            slash_counterparty_bps: DEFAULT_SLASH_COUNTERPARTY_BPS,
+            reputation_authority: Pubkey::default(),
+        }
+    }
+
+    #[test]
+    fn agents_are_slashed_for_lost_and_frivolous_disputes() {
+        let config = protocol_config();
+
+        assert_eq!(
+            config.agent_slash(0),
+            Some((SlashReason::FrivolousDispute, DEFAULT_FRIVOLOUS_DISPUTE_SLASH_BPS))
+        );
+        assert_eq!(
+            config.agent_slash(LOST_DISPUTE_REFUND_THRESHOLD - 1),
+            Some((SlashReason::LostDispute, DEFAULT_LOST_DISPUTE_SLASH_BPS))
+        );
+        assert_eq!(
+            config.agent_slash(1),
+            Some((SlashReason::LostDispute, DEFAULT_LOST_DISPUTE_SLASH_BPS))
+        );
+        assert_eq!(config.agent_slash(LOST_DISPUTE_REFUND_THRESHOLD), None);
+        assert_eq!(config.agent_slash(100), None);
+    }
 }
@@ -1,7 +1,15 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
-import { Keypair, LAMPORTS_PER_SOL, PublicKey, SystemProgram } from "@solana/web3.js";
+import {
+  Ed25519Program,
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  SystemProgram,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+} from "@solana/web3.js";
 import { expect } from "chai";
+import { createHash } from "crypto";
 
 // Import IDL (will be generated after build)
 // import { Mitama } from "../target/types/mitama";
@@ -23,6 +31,8 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
   let escrowBump: number;
   let reputationPDA: PublicKey;
   let reputationBump: number;
+  let apiReputationPDA: PublicKey;
+  let protocolConfigPDA: PublicKey;
 
   const transactionId = `test-${Date.now()}`;
 
@@ -47,7 +57,7 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
     );
 
     [escrowPDA, escrowBump] = PublicKey.findProgramAddressSync(
-      [Buffer.from("escrow"), Buffer.from(transactionId)],
+      [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(transactionId)],
       program.programId
     );
 
@@ -55,6 +65,42 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
       [Buffer.from("reputation"), owner.publicKey.toBuffer()],
       program.programId
     );
+
+    // Protocol config and fee vault, administered by the provider wallet
+    [protocolConfigPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("protocol_config")],
+      program.programId
+    );
+
+    await program.methods
+      .initializeProtocolConfig(provider.wallet.publicKey)
+      .accounts({
+        protocolConfig: protocolConfigPDA,
+        admin: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    // Releases and settlements update both parties' reputations
+    [apiReputationPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("reputation"), provider2.publicKey.toBuffer()],
+      program.programId
+    );
+
+    for (const [entity, reputation] of [
+      [owner.publicKey, reputationPDA],
+      [provider2.publicKey, apiReputationPDA],
+    ]) {
+      await program.methods
+        .initReputation()
+        .accounts({
+          reputation,
+          entity,
+          payer: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
   });
 
   // ============================================================================
@@ -145,6 +191,70 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
         expect(err.error.errorCode.code).to.equal("InvalidAgentName");
       }
     });
+
+    it("Tops up stake and queues an unstake behind the cooldown", async () => {
+      const topUp = new anchor.BN(0.2 * LAMPORTS_PER_SOL);
+      const before = await program.account.agentIdentity.fetch(agentPDA);
+
+      await program.methods
+        .increaseStake(topUp)
+        .accounts({
+          agent: agentPDA,
+          owner: owner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+
+      await program.methods
+        .requestUnstake(topUp)
+        .accounts({
+          agent: agentPDA,
+          owner: owner.publicKey,
+        })
+        .signers([owner])
+        .rpc();
+
+      const agent = await program.account.agentIdentity.fetch(agentPDA);
+      expect(agent.stakeAmount.toNumber()).to.equal(
+        before.stakeAmount.toNumber() + topUp.toNumber()
+      );
+      expect(agent.pendingUnstake.toNumber()).to.equal(topUp.toNumber());
+
+      try {
+        await program.methods
+          .completeUnstake()
+          .accounts({
+            agent: agentPDA,
+            owner: owner.publicKey,
+          })
+          .signers([owner])
+          .rpc();
+        expect.fail("Should have thrown UnstakeCooldownActive error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("UnstakeCooldownActive");
+      }
+    });
+
+    it("Refuses to migrate an identity already at the current layout", async () => {
+      const agent = await program.account.agentIdentity.fetch(agentPDA);
+      expect(agent.version).to.equal(1);
+
+      try {
+        await program.methods
+          .migrateAgentIdentity()
+          .accounts({
+            agent: agentPDA,
+            owner: owner.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([owner])
+          .rpc();
+        expect.fail("Should have thrown AlreadyMigrated error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("AlreadyMigrated");
+      }
+    });
   });
 
   // ============================================================================
@@ -167,7 +277,6 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
           escrowTokenAccount: null,
           agentTokenAccount: null,
           tokenProgram: null,
-          associatedTokenProgram: null,
         })
         .signers([owner])
         .rpc();
@@ -184,7 +293,7 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
     it("Fails to create escrow with invalid time lock", async () => {
       const newTxId = `test-invalid-${Date.now()}`;
       const [newEscrowPDA] = PublicKey.findProgramAddressSync(
-        [Buffer.from("escrow"), Buffer.from(newTxId)],
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(newTxId)],
         program.programId
       );
 
@@ -203,7 +312,6 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
             escrowTokenAccount: null,
             agentTokenAccount: null,
             tokenProgram: null,
-            associatedTokenProgram: null,
           })
           .signers([owner])
           .rpc();
@@ -217,13 +325,15 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
       // Create a new escrow for release test
       const releaseTxId = `release-${Date.now()}`;
       const [releaseEscrowPDA] = PublicKey.findProgramAddressSync(
-        [Buffer.from("escrow"), Buffer.from(releaseTxId)],
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(releaseTxId)],
         program.programId
       );
 
       const amount = new anchor.BN(0.05 * LAMPORTS_PER_SOL);
       const timeLock = new anchor.BN(3600);
+      const agentBefore = await program.account.agentIdentity.fetch(agentPDA);
 
+      // Bind the escrow to the agent identity so its counters track it
       await program.methods
         .initializeEscrow(amount, timeLock, releaseTxId, false)
         .accounts({
@@ -235,7 +345,7 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
           escrowTokenAccount: null,
           agentTokenAccount: null,
           tokenProgram: null,
-          associatedTokenProgram: null,
+          agentIdentity: agentPDA,
         })
         .signers([owner])
         .rpc();
@@ -250,9 +360,12 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
           agent: owner.publicKey,
           api: provider2.publicKey,
           systemProgram: SystemProgram.programId,
+          agentReputation: reputationPDA,
+          apiReputation: apiReputationPDA,
           escrowTokenAccount: null,
           apiTokenAccount: null,
           tokenProgram: null,
+          agentIdentity: agentPDA,
         })
         .signers([owner])
         .rpc();
@@ -260,58 +373,138 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
       // Verify escrow status
       const escrow = await program.account.escrow.fetch(releaseEscrowPDA);
       expect(escrow.status).to.deep.equal({ released: {} });
+      expect(escrow.agentIdentity.toString()).to.equal(agentPDA.toString());
+
+      const agentAfter = await program.account.agentIdentity.fetch(agentPDA);
+      expect(agentAfter.totalEscrows.toNumber()).to.equal(agentBefore.totalEscrows.toNumber() + 1);
+      expect(agentAfter.successfulEscrows.toNumber()).to.equal(
+        agentBefore.successfulEscrows.toNumber() + 1
+      );
 
       // Verify provider received funds
       const providerBalanceAfter = await provider.connection.getBalance(provider2.publicKey);
       expect(providerBalanceAfter - providerBalanceBefore).to.equal(amount.toNumber());
+
+      // Close the settled escrow and reclaim its rent
+      const escrowRent = await provider.connection.getBalance(releaseEscrowPDA);
+      const agentBalanceBefore = await provider.connection.getBalance(owner.publicKey);
+
+      await program.methods
+        .closeEscrow()
+        .accounts({
+          escrow: releaseEscrowPDA,
+          agent: owner.publicKey,
+        })
+        .signers([owner])
+        .rpc();
+
+      const closed = await provider.connection.getAccountInfo(releaseEscrowPDA);
+      expect(closed).to.be.null;
+
+      const agentBalanceAfter = await provider.connection.getBalance(owner.publicKey);
+      expect(agentBalanceAfter - agentBalanceBefore).to.equal(escrowRent);
     });
-  });
 
-  // ============================================================================
-  // Reputation Tests
-  // ============================================================================
+    it("Releases a milestone escrow one milestone at a time", async () => {
+      const milestoneTxId = `milestone-${Date.now()}`;
+      const [milestoneEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(milestoneTxId)],
+        program.programId
+      );
+
+      const firstAmount = new anchor.BN(0.02 * LAMPORTS_PER_SOL);
+      const secondAmount = new anchor.BN(0.03 * LAMPORTS_PER_SOL);
+      const agentBefore = await program.account.agentIdentity.fetch(agentPDA);
+      const reputationBefore = await program.account.entityReputation.fetch(reputationPDA);
 
-  describe("Reputation", () => {
-    it("Initializes reputation for an entity", async () => {
       await program.methods
-        .initReputation()
+        .initializeMilestoneEscrow(
+          milestoneTxId,
+          [
+            { amount: firstAmount, timeLock: new anchor.BN(3600) },
+            { amount: secondAmount, timeLock: new anchor.BN(7200) },
+          ],
+          false
+        )
         .accounts({
-          reputation: reputationPDA,
-          entity: owner.publicKey,
-          payer: owner.publicKey,
+          escrow: milestoneEscrowPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
           systemProgram: SystemProgram.programId,
+          tokenMint: null,
+          escrowTokenAccount: null,
+          agentTokenAccount: null,
+          tokenProgram: null,
+          agentIdentity: agentPDA,
         })
         .signers([owner])
         .rpc();
 
-      const reputation = await program.account.entityReputation.fetch(reputationPDA);
-      expect(reputation.entity.toString()).to.equal(owner.publicKey.toString());
-      expect(reputation.totalTransactions.toNumber()).to.equal(0);
-      expect(reputation.reputationScore).to.equal(500); // Default score
-    });
-  });
+      const providerBalanceBefore = await provider.connection.getBalance(provider2.publicKey);
 
-  // ============================================================================
-  // Dispute Tests
-  // ============================================================================
+      const releaseMilestone = (index: number) =>
+        program.methods
+          .releaseMilestone(index)
+          .accounts({
+            escrow: milestoneEscrowPDA,
+            agent: owner.publicKey,
+            api: provider2.publicKey,
+            systemProgram: SystemProgram.programId,
+            agentReputation: reputationPDA,
+            apiReputation: apiReputationPDA,
+            escrowTokenAccount: null,
+            apiTokenAccount: null,
+            tokenProgram: null,
+            agentIdentity: agentPDA,
+          })
+          .signers([owner])
+          .rpc();
 
-  describe("Disputes", () => {
-    it("Marks an escrow as disputed", async () => {
-      // Create a new escrow for dispute test
-      const disputeTxId = `dispute-${Date.now()}`;
-      const [disputeEscrowPDA] = PublicKey.findProgramAddressSync(
-        [Buffer.from("escrow"), Buffer.from(disputeTxId)],
+      await releaseMilestone(0);
+
+      let escrow = await program.account.escrow.fetch(milestoneEscrowPDA);
+      expect(escrow.status).to.deep.equal({ active: {} });
+      expect(escrow.milestones[0].status).to.deep.equal({ released: {} });
+      expect(escrow.milestones[1].status).to.deep.equal({ active: {} });
+
+      await releaseMilestone(1);
+
+      escrow = await program.account.escrow.fetch(milestoneEscrowPDA);
+      expect(escrow.status).to.deep.equal({ released: {} });
+      expect(escrow.agentIdentity.toString()).to.equal(agentPDA.toString());
+
+      const agentAfter = await program.account.agentIdentity.fetch(agentPDA);
+      expect(agentAfter.totalEscrows.toNumber()).to.equal(agentBefore.totalEscrows.toNumber() + 1);
+      expect(agentAfter.successfulEscrows.toNumber()).to.equal(
+        agentBefore.successfulEscrows.toNumber() + 1
+      );
+
+      // Each released milestone counts as a transaction
+      const reputationAfter = await program.account.entityReputation.fetch(reputationPDA);
+      expect(reputationAfter.totalTransactions.toNumber()).to.equal(
+        reputationBefore.totalTransactions.toNumber() + 2
+      );
+
+      const providerBalanceAfter = await provider.connection.getBalance(provider2.publicKey);
+      expect(providerBalanceAfter - providerBalanceBefore).to.equal(
+        firstAmount.add(secondAmount).toNumber()
+      );
+    });
+
+    it("Provider withdraws the vested part of a streaming escrow", async () => {
+      const streamTxId = `stream-${Date.now()}`;
+      const [streamEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(streamTxId)],
         program.programId
       );
 
       const amount = new anchor.BN(0.05 * LAMPORTS_PER_SOL);
       const timeLock = new anchor.BN(3600);
 
-      // Initialize escrow
       await program.methods
-        .initializeEscrow(amount, timeLock, disputeTxId, false)
+        .initializeStreamingEscrow(amount, timeLock, streamTxId, false)
         .accounts({
-          escrow: disputeEscrowPDA,
+          escrow: streamEscrowPDA,
           agent: owner.publicKey,
           api: provider2.publicKey,
           systemProgram: SystemProgram.programId,
@@ -319,157 +512,902 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
           escrowTokenAccount: null,
           agentTokenAccount: null,
           tokenProgram: null,
-          associatedTokenProgram: null,
         })
         .signers([owner])
         .rpc();
 
-      // Mark as disputed
+      // Let a few seconds vest
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
       await program.methods
-        .markDisputed()
+        .withdrawVested()
         .accounts({
-          escrow: disputeEscrowPDA,
-          reputation: reputationPDA,
+          escrow: streamEscrowPDA,
+          api: provider2.publicKey,
           agent: owner.publicKey,
+          systemProgram: SystemProgram.programId,
+          agentReputation: reputationPDA,
+          apiReputation: apiReputationPDA,
+          tokenMint: null,
+          escrowTokenAccount: null,
+          apiTokenAccount: null,
+          tokenProgram: null,
         })
-        .signers([owner])
+        .signers([provider2])
         .rpc();
 
-      // Verify
-      const escrow = await program.account.escrow.fetch(disputeEscrowPDA);
-      expect(escrow.status).to.deep.equal({ disputed: {} });
-
-      const reputation = await program.account.entityReputation.fetch(reputationPDA);
-      expect(reputation.disputesFiled.toNumber()).to.be.greaterThan(0);
+      const escrow = await program.account.escrow.fetch(streamEscrowPDA);
+      expect(escrow.status).to.deep.equal({ active: {} });
+      expect(escrow.withdrawn.toNumber()).to.be.greaterThan(0);
+      expect(escrow.withdrawn.toNumber()).to.be.lessThan(amount.toNumber());
     });
 
-    it("Cannot dispute an already released escrow", async () => {
-      // Create and release an escrow
-      const releasedTxId = `released-dispute-${Date.now()}`;
-      const [releasedEscrowPDA] = PublicKey.findProgramAddressSync(
-        [Buffer.from("escrow"), Buffer.from(releasedTxId)],
+    it("Opens subscription periods with a crank and cancels the rest", async () => {
+      const subscriptionId = `sub-${Date.now() % 1_000_000}`;
+      const [subscriptionPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("subscription"), owner.publicKey.toBuffer(), Buffer.from(subscriptionId)],
         program.programId
       );
 
-      const amount = new anchor.BN(0.02 * LAMPORTS_PER_SOL);
-      const timeLock = new anchor.BN(3600);
+      const amountPerPeriod = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
 
       await program.methods
-        .initializeEscrow(amount, timeLock, releasedTxId, false)
+        .createSubscription(subscriptionId, amountPerPeriod, new anchor.BN(3600), 3, false)
         .accounts({
-          escrow: releasedEscrowPDA,
+          subscription: subscriptionPDA,
           agent: owner.publicKey,
           api: provider2.publicKey,
           systemProgram: SystemProgram.programId,
           tokenMint: null,
-          escrowTokenAccount: null,
+          subscriptionTokenAccount: null,
           agentTokenAccount: null,
           tokenProgram: null,
-          associatedTokenProgram: null,
+          agentIdentity: agentPDA,
         })
         .signers([owner])
         .rpc();
 
-      // Release it
+      // Anyone can crank the first period, which is due immediately
+      const periodTxId = `${subscriptionId}#1`;
+      const [periodEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(periodTxId)],
+        program.programId
+      );
+
       await program.methods
-        .releaseFunds()
+        .openSubscriptionPeriod(periodTxId)
         .accounts({
-          escrow: releasedEscrowPDA,
-          agent: owner.publicKey,
-          api: provider2.publicKey,
+          subscription: subscriptionPDA,
+          escrow: periodEscrowPDA,
+          payer: provider2.publicKey,
           systemProgram: SystemProgram.programId,
+          tokenMint: null,
+          subscriptionTokenAccount: null,
           escrowTokenAccount: null,
-          apiTokenAccount: null,
           tokenProgram: null,
+          agentIdentity: agentPDA,
         })
-        .signers([owner])
+        .signers([provider2])
         .rpc();
 
-      // Try to dispute - should fail
+      const escrow = await program.account.escrow.fetch(periodEscrowPDA);
+      expect(escrow.status).to.deep.equal({ active: {} });
+      expect(escrow.amount.toNumber()).to.equal(amountPerPeriod.toNumber());
+      expect(escrow.agentIdentity.toString()).to.equal(agentPDA.toString());
+
+      const subscription = await program.account.subscription.fetch(subscriptionPDA);
+      expect(subscription.periodsOpened).to.equal(1);
+
+      // The second period is not due for another hour
+      const nextTxId = `${subscriptionId}#2`;
+      const [nextEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(nextTxId)],
+        program.programId
+      );
       try {
         await program.methods
-          .markDisputed()
+          .openSubscriptionPeriod(nextTxId)
           .accounts({
-            escrow: releasedEscrowPDA,
-            reputation: reputationPDA,
+            subscription: subscriptionPDA,
+            escrow: nextEscrowPDA,
+            payer: provider2.publicKey,
+            systemProgram: SystemProgram.programId,
+            tokenMint: null,
+            subscriptionTokenAccount: null,
+            escrowTokenAccount: null,
+            tokenProgram: null,
+            agentIdentity: agentPDA,
+          })
+          .signers([provider2])
+          .rpc();
+        expect.fail("Should have thrown PeriodNotDue error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code || err.message).to.include("PeriodNotDue");
+      }
+
+      // The provider cancels the remaining periods
+      await program.methods
+        .cancelSubscription()
+        .accounts({
+          subscription: subscriptionPDA,
+          authority: provider2.publicKey,
+          agent: owner.publicKey,
+          tokenMint: null,
+          subscriptionTokenAccount: null,
+          agentTokenAccount: null,
+          tokenProgram: null,
+        })
+        .signers([provider2])
+        .rpc();
+
+      const closed = await provider.connection.getAccountInfo(subscriptionPDA);
+      expect(closed).to.be.null;
+
+      // The period already opened stays active
+      const opened = await program.account.escrow.fetch(periodEscrowPDA);
+      expect(opened.status).to.deep.equal({ active: {} });
+    });
+
+    it("Cannot close an active escrow", async () => {
+      try {
+        await program.methods
+          .closeEscrow()
+          .accounts({
+            escrow: escrowPDA,
             agent: owner.publicKey,
           })
           .signers([owner])
           .rpc();
         expect.fail("Should have thrown InvalidStatus error");
       } catch (err: any) {
-        expect(err.error.errorCode.code).to.equal("InvalidStatus");
+        expect(err.error?.errorCode?.code || err.message).to.include("InvalidStatus");
       }
     });
   });
 
   // ============================================================================
-  // Oracle Registry Tests
+  // Reputation Tests
   // ============================================================================
 
-  describe("Oracle Registry", () => {
-    let oracleRegistryPDA: PublicKey;
-    const admin = Keypair.generate();
-
-    before(async () => {
-      const airdropSig = await provider.connection.requestAirdrop(
-        admin.publicKey,
-        2 * LAMPORTS_PER_SOL
-      );
-      await provider.connection.confirmTransaction(airdropSig);
-
-      [oracleRegistryPDA] = PublicKey.findProgramAddressSync(
-        [Buffer.from("oracle_registry")],
+  describe("Reputation", () => {
+    it("Initializes reputation for an entity", async () => {
+      const entity = Keypair.generate();
+      const [entityReputationPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("reputation"), entity.publicKey.toBuffer()],
         program.programId
       );
-    });
-
-    it("Initializes oracle registry", async () => {
-      const minConsensus = 2;
-      const maxScoreDeviation = 15;
 
       await program.methods
-        .initializeOracleRegistry(minConsensus, maxScoreDeviation)
+        .initReputation()
         .accounts({
-          oracleRegistry: oracleRegistryPDA,
-          admin: admin.publicKey,
+          reputation: entityReputationPDA,
+          entity: entity.publicKey,
+          payer: owner.publicKey,
           systemProgram: SystemProgram.programId,
         })
-        .signers([admin])
+        .signers([owner])
         .rpc();
 
-      const registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
-      expect(registry.admin.toString()).to.equal(admin.publicKey.toString());
-      expect(registry.minConsensus).to.equal(minConsensus);
-      expect(registry.maxScoreDeviation).to.equal(maxScoreDeviation);
-      expect(registry.oracles.length).to.equal(0);
+      const reputation = await program.account.entityReputation.fetch(entityReputationPDA);
+      expect(reputation.entity.toString()).to.equal(entity.publicKey.toString());
+      expect(reputation.totalTransactions.toNumber()).to.equal(0);
+      expect(reputation.reputationScore).to.equal(500); // Default score
     });
 
-    it("Adds an oracle to registry", async () => {
-      const oraclePubkey = Keypair.generate().publicKey;
-      const oracleType = { ed25519: {} };
-      const weight = 100;
+    it("Records an undisputed release on both parties' reputations", async () => {
+      const agentBefore = await program.account.entityReputation.fetch(reputationPDA);
+      const apiBefore = await program.account.entityReputation.fetch(apiReputationPDA);
+
+      const repTxId = `reputation-release-${Date.now()}`;
+      const [repEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(repTxId)],
+        program.programId
+      );
 
       await program.methods
-        .addOracle(oraclePubkey, oracleType, weight)
+        .initializeEscrow(new anchor.BN(0.02 * LAMPORTS_PER_SOL), new anchor.BN(3600), repTxId, false)
         .accounts({
-          oracleRegistry: oracleRegistryPDA,
-          admin: admin.publicKey,
+          escrow: repEscrowPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenMint: null,
+          escrowTokenAccount: null,
+          agentTokenAccount: null,
+          tokenProgram: null,
         })
-        .signers([admin])
+        .signers([owner])
         .rpc();
 
-      const registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
+      await program.methods
+        .releaseFunds()
+        .accounts({
+          escrow: repEscrowPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
+          systemProgram: SystemProgram.programId,
+          agentReputation: reputationPDA,
+          apiReputation: apiReputationPDA,
+          escrowTokenAccount: null,
+          apiTokenAccount: null,
+          tokenProgram: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      const reputation = await program.account.entityReputation.fetch(reputationPDA);
+      expect(reputation.totalTransactions.toNumber()).to.equal(
+        agentBefore.totalTransactions.toNumber() + 1
+      );
+      expect(reputation.averageQualityReceived).to.equal(100);
+      expect(reputation.disputesWon.toNumber()).to.equal(agentBefore.disputesWon.toNumber());
+      expect(reputation.disputesLost.toNumber()).to.equal(agentBefore.disputesLost.toNumber());
+
+      const apiReputation = await program.account.entityReputation.fetch(apiReputationPDA);
+      expect(apiReputation.totalTransactions.toNumber()).to.equal(
+        apiBefore.totalTransactions.toNumber() + 1
+      );
+    });
+
+    it("Releases to an API that has no reputation account", async () => {
+      const agentBefore = await program.account.entityReputation.fetch(reputationPDA);
+      const newApi = Keypair.generate();
+
+      const repTxId = `reputation-optional-${Date.now()}`;
+      const [repEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(repTxId)],
+        program.programId
+      );
+
+      await program.methods
+        .initializeEscrow(new anchor.BN(0.02 * LAMPORTS_PER_SOL), new anchor.BN(3600), repTxId, false)
+        .accounts({
+          escrow: repEscrowPDA,
+          agent: owner.publicKey,
+          api: newApi.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenMint: null,
+          escrowTokenAccount: null,
+          agentTokenAccount: null,
+          tokenProgram: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      await program.methods
+        .releaseFunds()
+        .accounts({
+          escrow: repEscrowPDA,
+          agent: owner.publicKey,
+          api: newApi.publicKey,
+          systemProgram: SystemProgram.programId,
+          agentReputation: reputationPDA,
+          apiReputation: null,
+          escrowTokenAccount: null,
+          apiTokenAccount: null,
+          tokenProgram: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      const escrow = await program.account.escrow.fetch(repEscrowPDA);
+      expect(escrow.status).to.deep.equal({ released: {} });
+
+      const reputation = await program.account.entityReputation.fetch(reputationPDA);
+      expect(reputation.totalTransactions.toNumber()).to.equal(
+        agentBefore.totalTransactions.toNumber() + 1
+      );
+    });
+  });
+
+  // ============================================================================
+  // Dispute Tests
+  // ============================================================================
+
+  describe("Disputes", () => {
+    it("Marks an escrow as disputed", async () => {
+      // Create a new escrow for dispute test
+      const disputeTxId = `dispute-${Date.now()}`;
+      const [disputeEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(disputeTxId)],
+        program.programId
+      );
+
+      const amount = new anchor.BN(0.05 * LAMPORTS_PER_SOL);
+      const timeLock = new anchor.BN(3600);
+
+      // Initialize escrow
+      await program.methods
+        .initializeEscrow(amount, timeLock, disputeTxId, false)
+        .accounts({
+          escrow: disputeEscrowPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenMint: null,
+          escrowTokenAccount: null,
+          agentTokenAccount: null,
+          tokenProgram: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      // Mark as disputed
+      await program.methods
+        .markDisputed()
+        .accounts({
+          escrow: disputeEscrowPDA,
+          reputation: reputationPDA,
+          agent: owner.publicKey,
+        })
+        .signers([owner])
+        .rpc();
+
+      // Verify
+      const escrow = await program.account.escrow.fetch(disputeEscrowPDA);
+      expect(escrow.status).to.deep.equal({ disputed: {} });
+
+      const reputation = await program.account.entityReputation.fetch(reputationPDA);
+      expect(reputation.disputesFiled.toNumber()).to.be.greaterThan(0);
+
+      // Both parties can submit evidence during the response window
+      const [disputeRecordPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("dispute"), disputeEscrowPDA.toBuffer()],
+        program.programId
+      );
+
+      for (const submitter of [owner, provider2]) {
+        await program.methods
+          .submitEvidence(Array(32).fill(submitter === owner ? 1 : 2), "ipfs://evidence")
+          .accounts({
+            escrow: disputeEscrowPDA,
+            disputeRecord: disputeRecordPDA,
+            submitter: submitter.publicKey,
+          })
+          .signers([submitter])
+          .rpc();
+      }
+
+      const record = await program.account.disputeRecord.fetch(disputeRecordPDA);
+      expect(record.evidence.length).to.equal(2);
+      expect(record.evidence[1].submitter.toString()).to.equal(provider2.publicKey.toString());
+
+      // Both parties have responded, so the hearing is closed to new evidence
+      try {
+        await program.methods
+          .submitEvidence(Array(32).fill(3), "ipfs://late-evidence")
+          .accounts({
+            escrow: disputeEscrowPDA,
+            disputeRecord: disputeRecordPDA,
+            submitter: owner.publicKey,
+          })
+          .signers([owner])
+          .rpc();
+        expect.fail("Should have thrown HearingClosed error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("HearingClosed");
+      }
+    });
+
+    it("Cannot settle a dispute by timeout before its deadline", async () => {
+      const timeoutTxId = `timeout-${Date.now()}`;
+      const [timeoutEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(timeoutTxId)],
+        program.programId
+      );
+      const [disputeRecordPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("dispute"), timeoutEscrowPDA.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .initializeEscrow(new anchor.BN(0.02 * LAMPORTS_PER_SOL), new anchor.BN(3600), timeoutTxId, false)
+        .accounts({
+          escrow: timeoutEscrowPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenMint: null,
+          escrowTokenAccount: null,
+          agentTokenAccount: null,
+          tokenProgram: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      await program.methods
+        .markDisputed()
+        .accounts({
+          escrow: timeoutEscrowPDA,
+          reputation: reputationPDA,
+          agent: owner.publicKey,
+        })
+        .signers([owner])
+        .rpc();
+
+      const config = await program.account.protocolConfig.fetch(protocolConfigPDA);
+      const record = await program.account.disputeRecord.fetch(disputeRecordPDA);
+      expect(record.resolutionDeadline.toNumber()).to.equal(
+        record.openedAt.toNumber() + config.resolutionTimeout.toNumber()
+      );
+
+      try {
+        await program.methods
+          .resolveDisputeTimeout()
+          .accounts({
+            escrow: timeoutEscrowPDA,
+            disputeRecord: disputeRecordPDA,
+            protocolConfig: protocolConfigPDA,
+            appeal: null,
+            agent: owner.publicKey,
+            api: provider2.publicKey,
+            agentReputation: reputationPDA,
+            apiReputation: apiReputationPDA,
+            systemProgram: SystemProgram.programId,
+            tokenMint: null,
+            escrowTokenAccount: null,
+            agentTokenAccount: null,
+            apiTokenAccount: null,
+            tokenProgram: null,
+            agentIdentity: null,
+          })
+          .rpc();
+        expect.fail("Should have thrown ResolutionDeadlineNotReached error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("ResolutionDeadlineNotReached");
+      }
+
+      const escrow = await program.account.escrow.fetch(timeoutEscrowPDA);
+      expect(escrow.status).to.deep.equal({ disputed: {} });
+    });
+
+    it("Takes milestone dispute records in the timeout path", async () => {
+      const milestoneTxId = `milestone-timeout-${Date.now()}`;
+      const [milestoneEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(milestoneTxId)],
+        program.programId
+      );
+      const [disputeRecordPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("dispute"), milestoneEscrowPDA.toBuffer(), Buffer.from([1])],
+        program.programId
+      );
+
+      await program.methods
+        .initializeMilestoneEscrow(
+          milestoneTxId,
+          [
+            { amount: new anchor.BN(0.01 * LAMPORTS_PER_SOL), timeLock: new anchor.BN(3600) },
+            { amount: new anchor.BN(0.01 * LAMPORTS_PER_SOL), timeLock: new anchor.BN(7200) },
+          ],
+          false
+        )
+        .accounts({
+          escrow: milestoneEscrowPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenMint: null,
+          escrowTokenAccount: null,
+          agentTokenAccount: null,
+          tokenProgram: null,
+          agentIdentity: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      await program.methods
+        .disputeMilestone(1)
+        .accounts({
+          escrow: milestoneEscrowPDA,
+          disputeRecord: disputeRecordPDA,
+          reputation: reputationPDA,
+          agent: owner.publicKey,
+          protocolConfig: protocolConfigPDA,
+          systemProgram: SystemProgram.programId,
+          agentIdentity: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      const record = await program.account.disputeRecord.fetch(disputeRecordPDA);
+      expect(record.milestoneIndex).to.equal(1);
+
+      // The record is accepted and the crank waits on its deadline. The
+      // local validator cannot run past the deadline, so the payout itself
+      // is not exercised here.
+      try {
+        await program.methods
+          .resolveDisputeTimeout()
+          .accounts({
+            escrow: milestoneEscrowPDA,
+            disputeRecord: disputeRecordPDA,
+            protocolConfig: protocolConfigPDA,
+            appeal: null,
+            agent: owner.publicKey,
+            api: provider2.publicKey,
+            agentReputation: reputationPDA,
+            apiReputation: apiReputationPDA,
+            systemProgram: SystemProgram.programId,
+            tokenMint: null,
+            escrowTokenAccount: null,
+            agentTokenAccount: null,
+            apiTokenAccount: null,
+            tokenProgram: null,
+            agentIdentity: null,
+          })
+          .rpc();
+        expect.fail("Should have thrown ResolutionDeadlineNotReached error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("ResolutionDeadlineNotReached");
+      }
+
+      const escrow = await program.account.escrow.fetch(milestoneEscrowPDA);
+      expect(escrow.milestones[1].status).to.deep.equal({ disputed: {} });
+    });
+
+    it("Cannot dispute an already released escrow", async () => {
+      // Create and release an escrow
+      const releasedTxId = `released-dispute-${Date.now()}`;
+      const [releasedEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(releasedTxId)],
+        program.programId
+      );
+
+      const amount = new anchor.BN(0.02 * LAMPORTS_PER_SOL);
+      const timeLock = new anchor.BN(3600);
+
+      await program.methods
+        .initializeEscrow(amount, timeLock, releasedTxId, false)
+        .accounts({
+          escrow: releasedEscrowPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenMint: null,
+          escrowTokenAccount: null,
+          agentTokenAccount: null,
+          tokenProgram: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      // Release it
+      await program.methods
+        .releaseFunds()
+        .accounts({
+          escrow: releasedEscrowPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
+          systemProgram: SystemProgram.programId,
+          agentReputation: reputationPDA,
+          apiReputation: apiReputationPDA,
+          escrowTokenAccount: null,
+          apiTokenAccount: null,
+          tokenProgram: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      // Try to dispute - should fail
+      try {
+        await program.methods
+          .markDisputed()
+          .accounts({
+            escrow: releasedEscrowPDA,
+            reputation: reputationPDA,
+            agent: owner.publicKey,
+          })
+          .signers([owner])
+          .rpc();
+        expect.fail("Should have thrown InvalidStatus error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidStatus");
+      }
+    });
+  });
+
+  // ============================================================================
+  // Oracle Registry Tests
+  // ============================================================================
+
+  describe("Oracle Registry", () => {
+    let oracleRegistryPDA: PublicKey;
+    const admin = Keypair.generate();
+
+    before(async () => {
+      const airdropSig = await provider.connection.requestAirdrop(
+        admin.publicKey,
+        2 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+
+      [oracleRegistryPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("oracle_registry")],
+        program.programId
+      );
+    });
+
+    it("Initializes oracle registry", async () => {
+      const minConsensus = 2;
+      const maxScoreDeviation = 15;
+      const genesisHash = anchor.utils.bytes.bs58.decode(
+        await provider.connection.getGenesisHash()
+      );
+
+      await program.methods
+        .initializeOracleRegistry(minConsensus, maxScoreDeviation, Array.from(genesisHash))
+        .accounts({
+          oracleRegistry: oracleRegistryPDA,
+          admin: admin.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+
+      const registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
+      expect(registry.admin.toString()).to.equal(admin.publicKey.toString());
+      expect(registry.minConsensus).to.equal(minConsensus);
+      expect(registry.maxScoreDeviation).to.equal(maxScoreDeviation);
+      expect(Buffer.from(registry.genesisHash)).to.deep.equal(Buffer.from(genesisHash));
+      expect(registry.oracles.length).to.equal(0);
+      expect(registry.version).to.equal(1);
+    });
+
+    it("Refuses to migrate a registry already at the current layout", async () => {
+      const registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
+
+      try {
+        await program.methods
+          .migrateOracleRegistry(Array.from(registry.genesisHash))
+          .accounts({
+            oracleRegistry: oracleRegistryPDA,
+            admin: admin.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([admin])
+          .rpc();
+        expect.fail("Should have thrown AlreadyMigrated error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("AlreadyMigrated");
+      }
+    });
+
+    it("Adds an oracle to registry", async () => {
+      const oraclePubkey = Keypair.generate().publicKey;
+      const oracleType = { ed25519: {} };
+      const weight = 100;
+
+      await program.methods
+        .addOracle(oraclePubkey, oracleType, weight)
+        .accounts({
+          oracleRegistry: oracleRegistryPDA,
+          admin: admin.publicKey,
+        })
+        .signers([admin])
+        .rpc();
+
+      const registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
       expect(registry.oracles.length).to.equal(1);
       expect(registry.oracles[0].pubkey.toString()).to.equal(oraclePubkey.toString());
       expect(registry.oracles[0].weight).to.equal(weight);
     });
 
-    it("Removes an oracle from registry", async () => {
-      // First add another oracle
-      const oraclePubkey = Keypair.generate().publicKey;
+    it("Removes an oracle from registry", async () => {
+      // First add another oracle
+      const oraclePubkey = Keypair.generate().publicKey;
+      await program.methods
+        .addOracle(oraclePubkey, { ed25519: {} }, 50)
+        .accounts({
+          oracleRegistry: oracleRegistryPDA,
+          admin: admin.publicKey,
+        })
+        .signers([admin])
+        .rpc();
+
+      let registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
+      const initialCount = registry.oracles.length;
+
+      // Remove it
+      await program.methods
+        .removeOracle(oraclePubkey)
+        .accounts({
+          oracleRegistry: oracleRegistryPDA,
+          admin: admin.publicKey,
+        })
+        .signers([admin])
+        .rpc();
+
+      registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
+      expect(registry.oracles.length).to.equal(initialCount - 1);
+    });
+
+    it("Configures the appeal round", async () => {
+      // The appeal quorum must exceed the first-round quorum of 2
+      try {
+        await program.methods
+          .setAppealParams(new anchor.BN(86400), 2, new anchor.BN(0.1 * LAMPORTS_PER_SOL))
+          .accounts({
+            oracleRegistry: oracleRegistryPDA,
+            admin: admin.publicKey,
+          })
+          .signers([admin])
+          .rpc();
+        expect.fail("Should have thrown InvalidAppealParams error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidAppealParams");
+      }
+
+      await program.methods
+        .setAppealParams(new anchor.BN(86400), 3, new anchor.BN(0.1 * LAMPORTS_PER_SOL))
+        .accounts({
+          oracleRegistry: oracleRegistryPDA,
+          admin: admin.publicKey,
+        })
+        .signers([admin])
+        .rpc();
+
+      const registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
+      expect(registry.appealWindow.toNumber()).to.equal(86400);
+      expect(registry.appealMinConsensus).to.equal(3);
+    });
+
+    it("Sets the commit and reveal windows", async () => {
+      try {
+        await program.methods
+          .setScoringWindows(new anchor.BN(0), new anchor.BN(3600))
+          .accounts({
+            oracleRegistry: oracleRegistryPDA,
+            protocolConfig: protocolConfigPDA,
+            admin: admin.publicKey,
+          })
+          .signers([admin])
+          .rpc();
+        expect.fail("Should have thrown InvalidScoringWindows error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidScoringWindows");
+      }
+
+      await program.methods
+        .setScoringWindows(new anchor.BN(7200), new anchor.BN(3600))
+        .accounts({
+          oracleRegistry: oracleRegistryPDA,
+          protocolConfig: protocolConfigPDA,
+          admin: admin.publicKey,
+        })
+        .signers([admin])
+        .rpc();
+
+      const registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
+      expect(registry.commitWindow.toNumber()).to.equal(7200);
+      expect(registry.revealWindow.toNumber()).to.equal(3600);
+    });
+
+    it("Keeps the dispute timeout beyond the scoring windows", async () => {
+      // 48h response window + 2h commit + 1h reveal is the shortest allowed
+      const shortest = 172_800 + 7200 + 3600;
+
+      try {
+        await program.methods
+          .setDisputeTimeout(new anchor.BN(shortest - 1), { fullRefund: {} })
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            oracleRegistry: oracleRegistryPDA,
+            admin: provider.wallet.publicKey,
+          })
+          .rpc();
+        expect.fail("Should have thrown InvalidDisputeTimeout error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidDisputeTimeout");
+      }
+
+      await program.methods
+        .setDisputeTimeout(new anchor.BN(shortest), { split: {} })
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          oracleRegistry: oracleRegistryPDA,
+          admin: provider.wallet.publicKey,
+        })
+        .rpc();
+
+      let config = await program.account.protocolConfig.fetch(protocolConfigPDA);
+      expect(config.resolutionTimeout.toNumber()).to.equal(shortest);
+      expect(config.timeoutPolicy).to.deep.equal({ split: {} });
+
+      // Windows that would outlast the timeout are rejected
+      try {
+        await program.methods
+          .setScoringWindows(new anchor.BN(7200), new anchor.BN(3601))
+          .accounts({
+            oracleRegistry: oracleRegistryPDA,
+            protocolConfig: protocolConfigPDA,
+            admin: admin.publicKey,
+          })
+          .signers([admin])
+          .rpc();
+        expect.fail("Should have thrown InvalidScoringWindows error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidScoringWindows");
+      }
+
+      await program.methods
+        .setDisputeTimeout(new anchor.BN(1_209_600), { fullRefund: {} })
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          oracleRegistry: oracleRegistryPDA,
+          admin: provider.wallet.publicKey,
+        })
+        .rpc();
+      config = await program.account.protocolConfig.fetch(protocolConfigPDA);
+      expect(config.timeoutPolicy).to.deep.equal({ fullRefund: {} });
+    });
+
+    it("Bonds and tops up an oracle stake", async () => {
+      const oracle = Keypair.generate();
+      const airdropSig = await provider.connection.requestAirdrop(
+        oracle.publicKey,
+        2 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+
+      const [oracleStakePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("oracle_stake"), oracle.publicKey.toBuffer()],
+        program.programId
+      );
+
       await program.methods
-        .addOracle(oraclePubkey, { ed25519: {} }, 50)
+        .stakeOracle(new anchor.BN(0.5 * LAMPORTS_PER_SOL))
+        .accounts({
+          oracleStake: oracleStakePDA,
+          oracle: oracle.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([oracle])
+        .rpc();
+
+      await program.methods
+        .addOracleStake(new anchor.BN(0.25 * LAMPORTS_PER_SOL))
+        .accounts({
+          oracleStake: oracleStakePDA,
+          oracle: oracle.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([oracle])
+        .rpc();
+
+      const stake = await program.account.oracleStake.fetch(oracleStakePDA);
+      expect(stake.oracle.toString()).to.equal(oracle.publicKey.toString());
+      expect(stake.amount.toNumber()).to.equal(0.75 * LAMPORTS_PER_SOL);
+      expect(stake.rewards.toNumber()).to.equal(0);
+
+      const balance = await provider.connection.getBalance(oracleStakePDA);
+      expect(balance).to.be.at.least(0.75 * LAMPORTS_PER_SOL);
+    });
+
+    it("Settles a milestone dispute on one staked verifier's ruling", async () => {
+      // The registry needs two oracles for consensus; a single staked,
+      // registered verifier can still rule before any scores are committed
+      const verifier = Keypair.generate();
+      const airdropSig = await provider.connection.requestAirdrop(
+        verifier.publicKey,
+        2 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(airdropSig);
+
+      const [verifierStakePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("oracle_stake"), verifier.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .stakeOracle(new anchor.BN(LAMPORTS_PER_SOL))
+        .accounts({
+          oracleStake: verifierStakePDA,
+          oracle: verifier.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([verifier])
+        .rpc();
+      await program.methods
+        .addOracle(verifier.publicKey, { ed25519: {} }, 100)
         .accounts({
           oracleRegistry: oracleRegistryPDA,
           admin: admin.publicKey,
@@ -477,21 +1415,186 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
         .signers([admin])
         .rpc();
 
-      let registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
-      const initialCount = registry.oracles.length;
+      const milestoneTxId = `verifier-${Date.now()}`;
+      const [milestoneEscrowPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("escrow"), owner.publicKey.toBuffer(), Buffer.from(milestoneTxId)],
+        program.programId
+      );
+      const [disputeRecordPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("dispute"), milestoneEscrowPDA.toBuffer(), Buffer.from([0])],
+        program.programId
+      );
+      const milestoneAmount = new anchor.BN(0.02 * LAMPORTS_PER_SOL);
 
-      // Remove it
       await program.methods
-        .removeOracle(oraclePubkey)
+        .initializeMilestoneEscrow(
+          milestoneTxId,
+          [
+            { amount: milestoneAmount, timeLock: new anchor.BN(3600) },
+            { amount: milestoneAmount, timeLock: new anchor.BN(7200) },
+          ],
+          false
+        )
+        .accounts({
+          escrow: milestoneEscrowPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
+          systemProgram: SystemProgram.programId,
+          tokenMint: null,
+          escrowTokenAccount: null,
+          agentTokenAccount: null,
+          tokenProgram: null,
+          agentIdentity: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      await program.methods
+        .disputeMilestone(0)
+        .accounts({
+          escrow: milestoneEscrowPDA,
+          disputeRecord: disputeRecordPDA,
+          reputation: reputationPDA,
+          agent: owner.publicKey,
+          protocolConfig: protocolConfigPDA,
+          systemProgram: SystemProgram.programId,
+          agentIdentity: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      // Both parties answer, which closes the hearing
+      for (const submitter of [owner, provider2]) {
+        await program.methods
+          .submitEvidence(Array(32).fill(submitter === owner ? 1 : 2), "ipfs://evidence")
+          .accounts({
+            escrow: milestoneEscrowPDA,
+            disputeRecord: disputeRecordPDA,
+            submitter: submitter.publicKey,
+          })
+          .signers([submitter])
+          .rpc();
+      }
+
+      // Sign the attestation the program rebuilds from the dispute record
+      const registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
+      const record = await program.account.disputeRecord.fetch(disputeRecordPDA);
+      const evidenceHash = createHash("sha256");
+      for (const evidence of record.evidence) {
+        evidenceHash.update(
+          createHash("sha256")
+            .update(evidence.submitter.toBuffer())
+            .update(Buffer.from(evidence.contentHash))
+            .update(Buffer.from(evidence.uri))
+            .digest()
+        );
+      }
+
+      const qualityScore = 70;
+      const refundPercentage = 35; // Default tier for a score of 65-79
+      const expiresAt = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      const message = Buffer.concat([
+        Buffer.from("mitama-oracle-attestation"),
+        Buffer.from([1]),
+        program.programId.toBuffer(),
+        Buffer.from(registry.genesisHash),
+        milestoneEscrowPDA.toBuffer(),
+        Buffer.from([1, 0]),
+        record.nonce.toArrayLike(Buffer, "le", 8),
+        Buffer.from([qualityScore, refundPercentage]),
+        evidenceHash.digest(),
+        expiresAt.toArrayLike(Buffer, "le", 8),
+      ]);
+      const ed25519Ix = Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: verifier.secretKey,
+        message,
+      });
+      // Signature follows the 16-byte header and the 32-byte public key
+      const signature = Array.from(ed25519Ix.data.subarray(48, 112));
+
+      const providerBalanceBefore = await provider.connection.getBalance(provider2.publicKey);
+
+      await program.methods
+        .resolveMilestoneDispute(0, qualityScore, expiresAt, signature)
         .accounts({
+          escrow: milestoneEscrowPDA,
+          disputeRecord: disputeRecordPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
+          verifier: verifier.publicKey,
+          verifierStake: verifierStakePDA,
+          instructionsSysvar: SYSVAR_INSTRUCTIONS_PUBKEY,
           oracleRegistry: oracleRegistryPDA,
-          admin: admin.publicKey,
+          protocolConfig: protocolConfigPDA,
+          agentReputation: reputationPDA,
+          apiReputation: apiReputationPDA,
+          systemProgram: SystemProgram.programId,
+          tokenMint: null,
+          escrowTokenAccount: null,
+          agentTokenAccount: null,
+          apiTokenAccount: null,
+          tokenProgram: null,
+          agentIdentity: null,
         })
-        .signers([admin])
+        .preInstructions([ed25519Ix])
         .rpc();
 
-      registry = await program.account.oracleRegistry.fetch(oracleRegistryPDA);
-      expect(registry.oracles.length).to.equal(initialCount - 1);
+      const escrow = await program.account.escrow.fetch(milestoneEscrowPDA);
+      expect(escrow.status).to.deep.equal({ active: {} });
+      expect(escrow.milestones[0].status).to.deep.equal({ resolved: {} });
+      expect(escrow.milestones[0].qualityScore).to.equal(qualityScore);
+      expect(escrow.milestones[0].refundPercentage).to.equal(refundPercentage);
+
+      const providerBalanceAfter = await provider.connection.getBalance(provider2.publicKey);
+      expect(providerBalanceAfter - providerBalanceBefore).to.equal(
+        milestoneAmount.toNumber() - (milestoneAmount.toNumber() * refundPercentage) / 100
+      );
+
+      // Once the last milestone is released, closing the escrow needs the
+      // record of the disputed milestone and closes it too
+      await program.methods
+        .releaseMilestone(1)
+        .accounts({
+          escrow: milestoneEscrowPDA,
+          agent: owner.publicKey,
+          api: provider2.publicKey,
+          systemProgram: SystemProgram.programId,
+          agentReputation: reputationPDA,
+          apiReputation: apiReputationPDA,
+          escrowTokenAccount: null,
+          apiTokenAccount: null,
+          tokenProgram: null,
+          agentIdentity: null,
+        })
+        .signers([owner])
+        .rpc();
+
+      try {
+        await program.methods
+          .closeEscrow()
+          .accounts({
+            escrow: milestoneEscrowPDA,
+            agent: owner.publicKey,
+          })
+          .signers([owner])
+          .rpc();
+        expect.fail("Should have thrown DisputeRecordMismatch error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("DisputeRecordMismatch");
+      }
+
+      await program.methods
+        .closeEscrow()
+        .accounts({
+          escrow: milestoneEscrowPDA,
+          agent: owner.publicKey,
+        })
+        .remainingAccounts([{ pubkey: disputeRecordPDA, isWritable: true, isSigner: false }])
+        .signers([owner])
+        .rpc();
+
+      expect(await provider.connection.getAccountInfo(milestoneEscrowPDA)).to.be.null;
+      expect(await provider.connection.getAccountInfo(disputeRecordPDA)).to.be.null;
     });
 
     it("Non-admin cannot add oracle", async () => {
@@ -518,6 +1621,217 @@ describe("Mitama - Agent Identity & Conflict Resolution", () => {
     });
   });
 
+  // ============================================================================
+  // Protocol Config Tests
+  // ============================================================================
+
+  describe("Protocol Config", () => {
+    it("Refuses to migrate a config already at the current layout", async () => {
+      const config = await program.account.protocolConfig.fetch(protocolConfigPDA);
+      expect(config.version).to.equal(1);
+
+      try {
+        await program.methods
+          .migrateProtocolConfig()
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            admin: provider.wallet.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc();
+        expect.fail("Should have thrown AlreadyMigrated error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("AlreadyMigrated");
+      }
+    });
+
+    it("Validates and replaces the refund tier table", async () => {
+      const setRefundTiers = (tiers: any[], strict: boolean, admin?: Keypair) =>
+        program.methods
+          .setRefundTiers(tiers, strict)
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            admin: admin ? admin.publicKey : provider.wallet.publicKey,
+          })
+          .signers(admin ? [admin] : [])
+          .rpc();
+
+      // Better quality must never refund more
+      try {
+        await setRefundTiers(
+          [
+            { minQuality: 0, refundPercentage: 50 },
+            { minQuality: 50, refundPercentage: 75 },
+          ],
+          false
+        );
+        expect.fail("Should have thrown InvalidRefundTiers error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidRefundTiers");
+      }
+
+      // The table must start at quality 0
+      try {
+        await setRefundTiers([{ minQuality: 10, refundPercentage: 100 }], false);
+        expect.fail("Should have thrown InvalidRefundTiers error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("InvalidRefundTiers");
+      }
+
+      const tiers = [
+        { minQuality: 0, refundPercentage: 90 },
+        { minQuality: 70, refundPercentage: 10 },
+      ];
+
+      try {
+        await setRefundTiers(tiers, true, owner);
+        expect.fail("Should have thrown Unauthorized error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      await setRefundTiers(tiers, true);
+
+      let config = await program.account.protocolConfig.fetch(protocolConfigPDA);
+      expect(config.refundTiers).to.deep.equal(tiers);
+      expect(config.strictRefunds).to.be.true;
+
+      // Restore the default curve for the remaining tests
+      const defaultTiers = [
+        { minQuality: 0, refundPercentage: 100 },
+        { minQuality: 50, refundPercentage: 75 },
+        { minQuality: 65, refundPercentage: 35 },
+        { minQuality: 80, refundPercentage: 0 },
+      ];
+      await setRefundTiers(defaultTiers, false);
+
+      config = await program.account.protocolConfig.fetch(protocolConfigPDA);
+      expect(config.refundTiers).to.deep.equal(defaultTiers);
+      expect(config.strictRefunds).to.be.false;
+    });
+
+    it("Validates the agent slashing policy", async () => {
+      const setAgentSlashing = (lost: number, frivolous: number, counterparty: number, admin?: Keypair) =>
+        program.methods
+          .setAgentSlashing(lost, frivolous, counterparty)
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            admin: admin ? admin.publicKey : provider.wallet.publicKey,
+          })
+          .signers(admin ? [admin] : [])
+          .rpc();
+
+      for (const [lost, frivolous, counterparty] of [
+        [10_001, 1_000, 5_000],
+        [500, 10_001, 5_000],
+        [500, 1_000, 10_001],
+      ]) {
+        try {
+          await setAgentSlashing(lost, frivolous, counterparty);
+          expect.fail("Should have thrown InvalidSlashingPolicy error");
+        } catch (err: any) {
+          expect(err.error.errorCode.code).to.equal("InvalidSlashingPolicy");
+        }
+      }
+
+      try {
+        await setAgentSlashing(0, 0, 0, owner);
+        expect.fail("Should have thrown Unauthorized error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      await setAgentSlashing(2_000, 10_000, 0);
+
+      let config = await program.account.protocolConfig.fetch(protocolConfigPDA);
+      expect(config.lostDisputeSlashBps).to.equal(2_000);
+      expect(config.frivolousDisputeSlashBps).to.equal(10_000);
+      expect(config.slashCounterpartyBps).to.equal(0);
+
+      // Restore the defaults for the remaining tests
+      await setAgentSlashing(500, 1_000, 5_000);
+
+      config = await program.account.protocolConfig.fetch(protocolConfigPDA);
+      expect(config.lostDisputeSlashBps).to.equal(500);
+      expect(config.frivolousDisputeSlashBps).to.equal(1_000);
+      expect(config.slashCounterpartyBps).to.equal(5_000);
+    });
+
+    it("Limits agent reputation updates to the reputation authority", async () => {
+      const updateAgentRep = (delta: number, reason: any, authority?: Keypair) =>
+        program.methods
+          .updateAgentRep(new anchor.BN(delta), reason)
+          .accounts({
+            agent: agentPDA,
+            protocolConfig: protocolConfigPDA,
+            authority: authority ? authority.publicKey : provider.wallet.publicKey,
+          })
+          .signers(authority ? [authority] : [])
+          .rpc();
+
+      // The agent owner cannot raise its own reputation
+      try {
+        await updateAgentRep(100, { serviceQuality: {} }, owner);
+        expect.fail("Should have thrown Unauthorized error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      // The admin is the initial reputation authority
+      const before = await program.account.agentIdentity.fetch(agentPDA);
+      await updateAgentRep(-50, { misconduct: {} });
+
+      let agent = await program.account.agentIdentity.fetch(agentPDA);
+      expect(agent.reputation.toNumber()).to.equal(before.reputation.toNumber() - 50);
+
+      // Only the admin can hand the role over
+      const authority = Keypair.generate();
+      try {
+        await program.methods
+          .setReputationAuthority(authority.publicKey)
+          .accounts({
+            protocolConfig: protocolConfigPDA,
+            admin: owner.publicKey,
+          })
+          .signers([owner])
+          .rpc();
+        expect.fail("Should have thrown Unauthorized error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      await program.methods
+        .setReputationAuthority(authority.publicKey)
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          admin: provider.wallet.publicKey,
+        })
+        .rpc();
+
+      await updateAgentRep(50, { correction: {} }, authority);
+
+      agent = await program.account.agentIdentity.fetch(agentPDA);
+      expect(agent.reputation.toNumber()).to.equal(before.reputation.toNumber());
+
+      // The previous authority has lost the role
+      try {
+        await updateAgentRep(10, { serviceQuality: {} });
+        expect.fail("Should have thrown Unauthorized error");
+      } catch (err: any) {
+        expect(err.error.errorCode.code).to.equal("Unauthorized");
+      }
+
+      // Hand the role back to the admin for the remaining tests
+      await program.methods
+        .setReputationAuthority(provider.wallet.publicKey)
+        .accounts({
+          protocolConfig: protocolConfigPDA,
+          admin: provider.wallet.publicKey,
+        })
+        .rpc();
+    });
+  });
+
   // ============================================================================
   // Deactivation Tests
   // ============================================================================